@@ -1 +1,4 @@
 mod parse;
+pub mod selector;
+
+pub use parse::SelectorParseError;
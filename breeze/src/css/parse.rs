@@ -0,0 +1,329 @@
+//! Selector parser, see <https://drafts.csswg.org/selectors/#grammar>
+
+use thiserror::Error;
+use super::selector::{
+  AttributeOperator, AttributeSelector, Combinator, CssSelector, CssSelectorList, CssSelectorPart, Nth, PseudoClass,
+};
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum SelectorParseError {
+  #[error("unexpected end of selector")]
+  UnexpectedEnd,
+
+  #[error("unexpected {0:?} at offset {1}")]
+  UnexpectedCharacter(char, usize),
+
+  #[error("unknown pseudo-class :{0}")]
+  UnknownPseudoClass(String),
+
+  #[error("pseudo-elements never match elements")]
+  PseudoElement,
+
+  #[error("invalid An+B expression {0:?}")]
+  InvalidNth(String),
+
+  #[error("selector nested too deeply")]
+  TooDeeplyNested,
+}
+
+type Result<T> = std::result::Result<T, SelectorParseError>;
+
+/// Nesting limit of selector lists in pseudo-classes like `:not()`, deeper ones would overflow the stack
+const MAX_NESTING: usize = 64;
+
+impl CssSelectorList {
+  pub fn parse(input: &str) -> Result<Self> {
+    let mut parser = Parser { input, position: 0, depth: 0 };
+    let list = parser.selector_list()?;
+    match parser.peek() {
+      None => Ok(list),
+      Some(_) => Err(parser.unexpected()),
+    }
+  }
+}
+
+impl std::str::FromStr for CssSelectorList {
+  type Err = SelectorParseError;
+  fn from_str(input: &str) -> Result<Self> {
+    Self::parse(input)
+  }
+}
+
+fn is_ident_char(c: char) -> bool {
+  c.is_ascii_alphanumeric() || matches!(c, '-' | '_') || !c.is_ascii()
+}
+
+struct Parser<'a> {
+  input: &'a str,
+  position: usize,
+  /// Selector lists the parser is in
+  depth: usize,
+}
+
+impl Parser<'_> {
+  fn rest(&self) -> &str {
+    &self.input[self.position..]
+  }
+
+  fn peek(&self) -> Option<char> {
+    self.rest().chars().next()
+  }
+
+  fn bump(&mut self) -> Option<char> {
+    let c = self.peek()?;
+    self.position += c.len_utf8();
+    Some(c)
+  }
+
+  fn eat(&mut self, c: char) -> bool {
+    let matched = self.peek() == Some(c);
+    if matched {
+      self.position += c.len_utf8();
+    }
+    matched
+  }
+
+  fn expect(&mut self, c: char) -> Result<()> {
+    match self.eat(c) {
+      true => Ok(()),
+      false => Err(self.unexpected()),
+    }
+  }
+
+  fn unexpected(&self) -> SelectorParseError {
+    match self.peek() {
+      Some(c) => SelectorParseError::UnexpectedCharacter(c, self.position),
+      None => SelectorParseError::UnexpectedEnd,
+    }
+  }
+
+  /// Returns whether there was any whitespace
+  fn skip_whitespace(&mut self) -> bool {
+    let start = self.position;
+    while self.peek().is_some_and(|c| c.is_ascii_whitespace()) {
+      self.bump();
+    }
+    self.position > start
+  }
+
+  fn at_ident(&self) -> bool {
+    let mut chars = self.rest().chars();
+    let first = chars.next();
+    let starts = |c: Option<char>| c.is_some_and(|c| c == '\\' || (is_ident_char(c) && !c.is_ascii_digit() && c != '-'));
+    match first {
+      Some('-') => { let second = chars.next(); second == Some('-') || starts(second) },
+      c => starts(c),
+    }
+  }
+
+  /// Character after a backslash
+  fn escape(&mut self) -> char {
+    let hex: String = self.rest().chars().take_while(|c| c.is_ascii_hexdigit()).take(6).collect();
+    if hex.is_empty() {
+      return self.bump().unwrap_or('\u{fffd}')
+    }
+    self.position += hex.len();
+    if self.peek().is_some_and(|c| c.is_ascii_whitespace()) {
+      self.bump();
+    }
+    let code = u32::from_str_radix(&hex, 16).unwrap();
+    char::from_u32(code).filter(|_| code != 0).unwrap_or('\u{fffd}')
+  }
+
+  fn ident(&mut self) -> Result<String> {
+    if !self.at_ident() {
+      return Err(self.unexpected())
+    }
+    let mut ident = String::new();
+    loop {
+      match self.peek() {
+        Some('\\') => { self.bump(); ident.push(self.escape()) },
+        Some(c) if is_ident_char(c) => { self.bump(); ident.push(c) },
+        _ => return Ok(ident),
+      }
+    }
+  }
+
+  fn string(&mut self) -> Result<String> {
+    let quote = self.bump().unwrap();
+    let mut string = String::new();
+    loop {
+      match self.bump() {
+        Some(c) if c == quote => return Ok(string),
+        Some('\\') if self.eat('\n') => (),
+        Some('\\') => string.push(self.escape()),
+        Some('\n') | None => return Err(SelectorParseError::UnexpectedEnd),
+        Some(c) => string.push(c),
+      }
+    }
+  }
+
+  fn selector_list(&mut self) -> Result<CssSelectorList> {
+    if self.depth == MAX_NESTING {
+      return Err(SelectorParseError::TooDeeplyNested)
+    }
+    self.depth += 1;
+    let mut selectors = Vec::new();
+    loop {
+      self.skip_whitespace();
+      selectors.push(self.complex_selector()?);
+      if !self.eat(',') {
+        self.depth -= 1;
+        return Ok(CssSelectorList(selectors))
+      }
+    }
+  }
+
+  fn complex_selector(&mut self) -> Result<CssSelector> {
+    let mut parts = Vec::new();
+    self.compound_selector(&mut parts)?;
+    loop {
+      let whitespace = self.skip_whitespace();
+      let combinator = match self.peek() {
+        Some('>') => Combinator::Child,
+        Some('+') => Combinator::NextSibling,
+        Some('~') => Combinator::SubsequentSibling,
+        Some(',' | ')') | None => return Ok(CssSelector { parts }),
+        _ if whitespace => Combinator::Descendant,
+        _ => return Err(self.unexpected()),
+      };
+      if combinator != Combinator::Descendant {
+        self.bump();
+        self.skip_whitespace();
+      }
+      parts.push(CssSelectorPart::Combinator(combinator));
+      self.compound_selector(&mut parts)?;
+    }
+  }
+
+  fn compound_selector(&mut self, parts: &mut Vec<CssSelectorPart>) -> Result<()> {
+    let start = parts.len();
+    if self.eat('*') {
+      parts.push(CssSelectorPart::Universal);
+    } else if self.at_ident() {
+      parts.push(CssSelectorPart::Element(self.ident()?));
+    }
+    loop {
+      let part = match self.peek() {
+        Some('#') => { self.bump(); CssSelectorPart::Id(self.ident()?) },
+        Some('.') => { self.bump(); CssSelectorPart::Class(self.ident()?) },
+        Some('[') => { self.bump(); CssSelectorPart::Attribute(self.attribute_selector()?) },
+        Some(':') => {
+          self.bump();
+          if self.peek() == Some(':') {
+            return Err(SelectorParseError::PseudoElement)
+          }
+          CssSelectorPart::PseudoClass(self.pseudo_class()?)
+        },
+        _ if parts.len() == start => return Err(self.unexpected()),
+        _ => return Ok(()),
+      };
+      parts.push(part);
+    }
+  }
+
+  fn attribute_selector(&mut self) -> Result<AttributeSelector> {
+    self.skip_whitespace();
+    let name = self.ident()?;
+    self.skip_whitespace();
+    if self.eat(']') {
+      return Ok(AttributeSelector { name, value: None, case_insensitive: false })
+    }
+    let operator = match self.rest().as_bytes() {
+      [b'=', ..] => AttributeOperator::Equals,
+      [b'~', b'=', ..] => AttributeOperator::Includes,
+      [b'|', b'=', ..] => AttributeOperator::DashMatch,
+      [b'^', b'=', ..] => AttributeOperator::Prefix,
+      [b'$', b'=', ..] => AttributeOperator::Suffix,
+      [b'*', b'=', ..] => AttributeOperator::Substring,
+      _ => return Err(self.unexpected()),
+    };
+    self.position += if operator == AttributeOperator::Equals { 1 } else { 2 };
+    self.skip_whitespace();
+    let value = match self.peek() {
+      Some('"' | '\'') => self.string()?,
+      _ => self.ident()?,
+    };
+    self.skip_whitespace();
+    let flag = self.position;
+    let case_insensitive = match self.at_ident() {
+      true => match self.ident()?.to_ascii_lowercase().as_str() {
+        "i" => true,
+        "s" => false,
+        _ => {
+          self.position = flag;
+          return Err(self.unexpected())
+        },
+      },
+      false => false,
+    };
+    self.skip_whitespace();
+    self.expect(']')?;
+    Ok(AttributeSelector { name, value: Some((operator, value)), case_insensitive })
+  }
+
+  fn pseudo_class(&mut self) -> Result<PseudoClass> {
+    let name = self.ident()?.to_ascii_lowercase();
+    if !self.eat('(') {
+      return Ok(match name.as_str() {
+        "root" => PseudoClass::Root,
+        "scope" => PseudoClass::Scope,
+        "empty" => PseudoClass::Empty,
+        "first-child" => PseudoClass::FirstChild,
+        "last-child" => PseudoClass::LastChild,
+        "only-child" => PseudoClass::OnlyChild,
+        "first-of-type" => PseudoClass::FirstOfType,
+        "last-of-type" => PseudoClass::LastOfType,
+        "only-of-type" => PseudoClass::OnlyOfType,
+        _ => return Err(SelectorParseError::UnknownPseudoClass(name)),
+      })
+    }
+    let pseudo_class = match name.as_str() {
+      "not" => PseudoClass::Not(self.selector_list()?),
+      "is" | "where" => PseudoClass::Is(self.selector_list()?),
+      "nth-child" => PseudoClass::NthChild(self.nth()?),
+      "nth-last-child" => PseudoClass::NthLastChild(self.nth()?),
+      "nth-of-type" => PseudoClass::NthOfType(self.nth()?),
+      "nth-last-of-type" => PseudoClass::NthLastOfType(self.nth()?),
+      _ => return Err(SelectorParseError::UnknownPseudoClass(name)),
+    };
+    self.skip_whitespace();
+    self.expect(')')?;
+    Ok(pseudo_class)
+  }
+
+  /// `odd`, `even` or `An+B`, see <https://drafts.csswg.org/css-syntax/#anb-microsyntax>
+  fn nth(&mut self) -> Result<Nth> {
+    let end = self.rest().find(')').ok_or(SelectorParseError::UnexpectedEnd)?;
+    let text = self.rest()[..end].trim().to_ascii_lowercase();
+    self.position += end;
+    let invalid = || SelectorParseError::InvalidNth(text.clone());
+    let number = |x: &str| x.parse::<i32>().map_err(|_| invalid());
+    match text.as_str() {
+      "odd" => return Ok(Nth { a: 2, b: 1 }),
+      "even" => return Ok(Nth { a: 2, b: 0 }),
+      _ => (),
+    }
+    let Some((a, b)) = text.split_once('n') else {
+      return Ok(Nth { a: 0, b: number(&text)? })
+    };
+    let a = match a {
+      "" | "+" => 1,
+      "-" => -1,
+      a => number(a)?,
+    };
+    let b = b.trim_start();
+    let b = match b.chars().next() {
+      None => 0,
+      Some(sign @ ('+' | '-')) => {
+        let digits = b[1..].trim_start();
+        if digits.is_empty() || !digits.bytes().all(|x| x.is_ascii_digit()) {
+          return Err(invalid())
+        }
+        number(digits)? * if sign == '-' { -1 } else { 1 }
+      },
+      Some(_) => return Err(invalid()),
+    };
+    Ok(Nth { a, b })
+  }
+}
@@ -1,10 +1,235 @@
+//! Selectors and matching them against elements\
+//! See <https://drafts.csswg.org/selectors/>
+
+use std::iter;
+use crate::dom::{Namespace, Node, NodeId, NodeIteratorExt, NodeRef};
+
+/// Comma-separated list of selectors, matching elements matched by any of them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CssSelectorList(pub Vec<CssSelector>);
+
+/// Compound selectors joined by combinators, like `ul > li.item`
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct CssSelector {
+  /// Parts from left to right, compound selectors are separated by combinators
   pub parts: Vec<CssSelectorPart>,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum CssSelectorPart {
+  /// `*`
+  Universal,
   Id(String),
   Class(String),
   Element(String),
-  PseudoClass(Box<CssSelectorPart>),
+  Attribute(AttributeSelector),
+  PseudoClass(PseudoClass),
+  Combinator(Combinator),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Combinator {
+  /// `a b`
+  Descendant,
+  /// `a > b`
+  Child,
+  /// `a + b`
+  NextSibling,
+  /// `a ~ b`
+  SubsequentSibling,
+}
+
+/// `[name]`, or `[name=value]` with any of the operators
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AttributeSelector {
+  pub name: String,
+  pub value: Option<(AttributeOperator, String)>,
+  /// The `i` flag was given
+  pub case_insensitive: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttributeOperator {
+  /// `=`
+  Equals,
+  /// `~=`, one of the whitespace-separated words
+  Includes,
+  /// `|=`, the value or the value followed by `-`
+  DashMatch,
+  /// `^=`
+  Prefix,
+  /// `$=`
+  Suffix,
+  /// `*=`
+  Substring,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PseudoClass {
+  Root,
+  Scope,
+  Empty,
+  FirstChild,
+  LastChild,
+  OnlyChild,
+  FirstOfType,
+  LastOfType,
+  OnlyOfType,
+  NthChild(Nth),
+  NthLastChild(Nth),
+  NthOfType(Nth),
+  NthLastOfType(Nth),
+  Not(CssSelectorList),
+  /// `:is()` and `:where()`, they only differ in specificity
+  Is(CssSelectorList),
+}
+
+/// `An+B`, matching the elements at positions `A*n + B` for any n >= 0, counting from 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Nth {
+  pub a: i32,
+  pub b: i32,
+}
+
+impl Nth {
+  pub fn matches(self, position: usize) -> bool {
+    let offset = position as i64 - self.b as i64;
+    match self.a {
+      0 => offset == 0,
+      a => offset % a as i64 == 0 && offset / a as i64 >= 0,
+    }
+  }
+}
+
+/// Information about the document needed for matching
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MatchContext {
+  /// Ids and class names are case-insensitive in quirks mode
+  pub quirks: bool,
+  /// Element matched by `:scope`, `:root` is used if there's none
+  pub scope: Option<NodeId>,
+}
+
+/// Elements, but not the root of the tree, which stands in for the document
+fn is_element(node: NodeRef) -> bool {
+  node.element().is_some() && node != node.dom().root()
+}
+
+fn parent_element(node: NodeRef<'_>) -> Option<NodeRef<'_>> {
+  node.parent().filter(|parent| is_element(*parent))
+}
+
+fn same_type(node: NodeRef, other: NodeRef) -> bool {
+  let (Some(node), Some(other)) = (node.element(), other.element()) else { return false };
+  node.tag_name == other.tag_name && node.namespace == other.namespace
+}
+
+fn compare(a: &str, b: &str, case_insensitive: bool) -> bool {
+  match case_insensitive {
+    true => a.eq_ignore_ascii_case(b),
+    false => a == b,
+  }
+}
+
+impl CssSelectorList {
+  pub fn matches(&self, node: NodeRef, context: &MatchContext) -> bool {
+    is_element(node) && self.0.iter().any(|selector| matches_parts(&selector.parts, node, context))
+  }
+}
+
+impl CssSelector {
+  pub fn matches(&self, node: NodeRef, context: &MatchContext) -> bool {
+    is_element(node) && matches_parts(&self.parts, node, context)
+  }
+}
+
+/// Match the rightmost compound selector against `node`, then the rest against its relatives
+fn matches_parts(parts: &[CssSelectorPart], node: NodeRef, context: &MatchContext) -> bool {
+  let start = parts.iter().rposition(|x| matches!(x, CssSelectorPart::Combinator(_))).map_or(0, |x| x + 1);
+  if !parts[start..].iter().all(|part| part.matches(node, context)) {
+    return false
+  }
+  let Some(CssSelectorPart::Combinator(combinator)) = start.checked_sub(1).map(|x| &parts[x]) else {
+    return true
+  };
+  let rest = &parts[..start - 1];
+  match combinator {
+    Combinator::Descendant => iter::successors(parent_element(node), |x| parent_element(*x))
+      .any(|x| matches_parts(rest, x, context)),
+    Combinator::Child => parent_element(node).is_some_and(|x| matches_parts(rest, x, context)),
+    Combinator::NextSibling => node.preceding_siblings().elements().next().is_some_and(|x| matches_parts(rest, x, context)),
+    Combinator::SubsequentSibling => node.preceding_siblings().elements().any(|x| matches_parts(rest, x, context)),
+  }
+}
+
+impl CssSelectorPart {
+  /// Whether an element matches this simple selector
+  fn matches(&self, node: NodeRef, context: &MatchContext) -> bool {
+    let element = node.element().unwrap();
+    match self {
+      Self::Universal => true,
+      Self::Id(id) => element.attribute("id").is_some_and(|x| compare(x, id, context.quirks)),
+      Self::Class(class) => element.attribute("class").is_some_and(|x| {
+        x.split_ascii_whitespace().any(|x| compare(x, class, context.quirks))
+      }),
+      //type selectors are case-insensitive for html elements in html documents
+      Self::Element(name) => compare(&element.tag_name, name, element.namespace == Namespace::Html && !element.xml),
+      Self::Attribute(selector) => selector.matches(element.attribute(&selector.name)),
+      Self::PseudoClass(pseudo_class) => pseudo_class.matches(node, context),
+      Self::Combinator(_) => unreachable!(),
+    }
+  }
+}
+
+impl AttributeSelector {
+  fn matches(&self, value: Option<&str>) -> bool {
+    let Some(value) = value else { return false };
+    let Some((operator, expected)) = &self.value else { return true };
+    let (value, expected) = match self.case_insensitive {
+      true => (value.to_ascii_lowercase(), expected.to_ascii_lowercase()),
+      false => (value.to_string(), expected.clone()),
+    };
+    match operator {
+      AttributeOperator::Equals => value == expected,
+      AttributeOperator::Includes => value.split_ascii_whitespace().any(|x| x == expected),
+      AttributeOperator::DashMatch => value.strip_prefix(&expected).is_some_and(|x| x.is_empty() || x.starts_with('-')),
+      AttributeOperator::Prefix => !expected.is_empty() && value.starts_with(&expected),
+      AttributeOperator::Suffix => !expected.is_empty() && value.ends_with(&expected),
+      AttributeOperator::Substring => !expected.is_empty() && value.contains(&expected),
+    }
+  }
+}
+
+impl PseudoClass {
+  fn matches(&self, node: NodeRef, context: &MatchContext) -> bool {
+    let before = || node.preceding_siblings().elements();
+    let after = || node.following_siblings().elements();
+    let position = |siblings: &mut dyn Iterator<Item = NodeRef>, of_type: bool| {
+      siblings.filter(|x| !of_type || same_type(*x, node)).count() + 1
+    };
+    match self {
+      Self::Root => node.parent().is_some_and(|x| x == node.dom().root() && x.element().is_some()),
+      Self::Scope => match context.scope {
+        Some(scope) => node.id() == scope,
+        None => Self::Root.matches(node, context),
+      },
+      Self::Empty => node.children().all(|x| match x.value() {
+        Node::Element(_) => false,
+        Node::Text(text) => text.text.is_empty(),
+        _ => true,
+      }),
+      Self::FirstChild => before().next().is_none(),
+      Self::LastChild => after().next().is_none(),
+      Self::OnlyChild => before().next().is_none() && after().next().is_none(),
+      Self::FirstOfType => !before().any(|x| same_type(x, node)),
+      Self::LastOfType => !after().any(|x| same_type(x, node)),
+      Self::OnlyOfType => !before().chain(after()).any(|x| same_type(x, node)),
+      Self::NthChild(nth) => nth.matches(position(&mut before(), false)),
+      Self::NthLastChild(nth) => nth.matches(position(&mut after(), false)),
+      Self::NthOfType(nth) => nth.matches(position(&mut before(), true)),
+      Self::NthLastOfType(nth) => nth.matches(position(&mut after(), true)),
+      Self::Not(selectors) => !selectors.matches(node, context),
+      Self::Is(selectors) => selectors.matches(node, context),
+    }
+  }
 }
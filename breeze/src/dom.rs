@@ -1,19 +1,79 @@
-use std::{fmt::Debug, rc::Rc, cell::RefCell};
+use std::{borrow::Cow, fmt::Debug, hash::BuildHasherDefault, sync::{Arc, OnceLock}};
+use indexmap::IndexMap;
 use partialdebug::placeholder::PartialDebug;
-use rustc_hash::{FxHashMap, FxHashSet};
+use rustc_hash::{FxHashMap, FxHashSet, FxHasher};
 use crate::elements::ElementInterface;
 
-mod shared;
+mod tree;
 mod errors;
+mod source;
 mod parse;
+mod query;
+mod collection;
+mod token_list;
+mod serialize;
+mod text;
+mod mutation;
 
-pub use shared::{SharedNode, WeakNode, SharedClone};
-pub use errors::DomPushError;
+pub use tree::{NodeId, NodeRef, NodeMut, Children, Edge, NodeFilter, Traverse, Descendants, PostOrder, Links, TreeWalker, NodeIteratorExt, Elements};
+pub use errors::{DomPushError, ParseError, ParseErrorKind, TokenListError};
+pub use source::{LineColumn, SourceSpan, ElementSource};
+pub use collection::HtmlCollection;
+pub use token_list::TokenList;
+pub use mutation::{MutationObserverId, MutationObserverInit, MutationRecord};
+pub use parse::{DomParser, XmlParser};
+pub use encoding_rs::Encoding;
 
+/// Serialization to markup that parses back to the same tree, HTML or XML depending on the document
 pub trait InnerHtml {
+  /// Markup of the children, or the contents of a template
   fn inner_html(&self) -> String;
+  /// Markup of the node itself
+  fn outer_html(&self) -> String;
 }
 
+/// Namespace of an element or attribute
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub enum Namespace {
+  #[default]
+  Html,
+  Svg,
+  MathMl,
+  XLink,
+  Xml,
+  Xmlns,
+  /// Elements of XML documents outside of any namespace
+  None,
+  /// Any other namespace, by its URL
+  Other(Arc<str>),
+}
+
+impl Namespace {
+  pub fn url(&self) -> &str {
+    match self {
+      Namespace::Html => "http://www.w3.org/1999/xhtml",
+      Namespace::Svg => "http://www.w3.org/2000/svg",
+      Namespace::MathMl => "http://www.w3.org/1998/Math/MathML",
+      Namespace::XLink => "http://www.w3.org/1999/xlink",
+      Namespace::Xml => "http://www.w3.org/XML/1998/namespace",
+      Namespace::Xmlns => "http://www.w3.org/2000/xmlns/",
+      Namespace::None => "",
+      Namespace::Other(url) => url,
+    }
+  }
+
+  /// Namespace with the given URL, an empty URL means no namespace
+  pub fn from_url(url: &str) -> Self {
+    [Namespace::Html, Namespace::Svg, Namespace::MathMl, Namespace::XLink, Namespace::Xml, Namespace::Xmlns, Namespace::None]
+      .into_iter()
+      .find(|x| x.url() == url)
+      .unwrap_or_else(|| Namespace::Other(url.into()))
+  }
+}
+
+/// Attributes by name, in the order they were added
+pub type AttributeMap = IndexMap<String, String, BuildHasherDefault<FxHasher>>;
+
 /// Do not change values in this struct directly
 #[derive(Debug, Default)]
 pub struct ElementNodeCache {
@@ -23,12 +83,18 @@ pub struct ElementNodeCache {
 
 #[derive(Default, PartialDebug)]
 pub struct ElementNode {
-  pub element: Option<Rc<RefCell<dyn ElementInterface>>>,
+  pub element: Option<Arc<dyn ElementInterface>>,
   pub tag_name: String,
-  pub attributes: FxHashMap<String, String>,
+  pub namespace: Namespace,
+  pub attributes: AttributeMap,
+  /// Namespaces of namespaced attributes (like `xlink:href`), by qualified name
+  pub attribute_namespaces: FxHashMap<String, Namespace>,
   pub cache: ElementNodeCache,
-  pub children: Vec<SharedNode>,
-  pub parent: Option<WeakNode>,
+  /// Contents of a `<template>` element, which aren't its children
+  pub template_contents: Option<NodeId>,
+  pub source: Option<Box<ElementSource>>,
+  /// Created by the XML parser, attribute names are case-sensitive even for HTML elements
+  pub xml: bool,
 }
 
 impl ElementNode {
@@ -43,7 +109,7 @@ impl ElementNode {
     this
   }
 
-  pub fn new_with_tag_and_attributes(tag: String, attributes: FxHashMap<String, String>) -> Self {
+  pub fn new_with_tag_and_attributes(tag: String, attributes: AttributeMap) -> Self {
     let mut this = Self::new_with_tag(tag);
     //XXX: should process_attribute_change be called after setting this.attributes? (requires clone)
     for (k, v) in &attributes {
@@ -62,8 +128,24 @@ impl ElementNode {
     self.tag_name = tag;
   }
 
+  /// Attribute names of HTML elements in HTML documents are case-insensitive
+  fn attribute_key<'a>(&self, name: &'a str) -> Cow<'a, str> {
+    match self.namespace {
+      Namespace::Html if !self.xml && name.bytes().any(|x| x.is_ascii_uppercase()) => Cow::Owned(name.to_ascii_lowercase()),
+      _ => Cow::Borrowed(name),
+    }
+  }
+
   pub fn attribute(&self, name: &str) -> Option<&str> {
-    self.attributes.get(name.to_lowercase().as_str()).map(|x| x.as_str())
+    self.attributes.get(&*self.attribute_key(name)).map(|x| x.as_str())
+  }
+
+  /// Get a namespaced attribute by its local name, e.g. `href` in `xlink:href`
+  pub fn attribute_ns(&self, namespace: Namespace, local_name: &str) -> Option<&str> {
+    let (name, _) = self.attribute_namespaces.iter().find(|(name, x)| {
+      **x == namespace && name.rsplit(':').next() == Some(local_name)
+    })?;
+    self.attribute(name)
   }
 
   pub fn id(&self) -> Option<&str> {
@@ -92,12 +174,13 @@ impl ElementNode {
   //TODO: remove needless clones in set_attribute/process_attribute_change, maybe clean up?
 
   pub fn set_attribute(&mut self, key: &str, value: Option<String>) {
-    let lc_key = key.to_ascii_lowercase();
+    let lc_key = self.attribute_key(key).into_owned();
     if let Some(value) = value {
       let prev = self.attributes.insert(lc_key.clone(), value.clone());
       self.process_attribute_change(&lc_key, Some(&value), prev.as_deref());
     } else {
-      let prev = self.attributes.remove(&lc_key);
+      let prev = self.attributes.shift_remove(&lc_key);
+      self.attribute_namespaces.remove(&lc_key);
       self.process_attribute_change(&lc_key, None, prev.as_deref());
     }
   }
@@ -108,7 +191,7 @@ impl ElementNode {
       "class" => {
         self.cache.classes.clear();
         if let Some(to) = to {
-          self.cache.classes.extend(to.trim().split(' ').map(|x| x.to_ascii_lowercase()));
+          self.cache.classes.extend(to.split_ascii_whitespace().map(str::to_string));
         }
       },
       "id" => {
@@ -119,34 +202,42 @@ impl ElementNode {
   }
 }
 
-impl InnerHtml for ElementNode {
-  fn inner_html(&self) -> String {
-    format!(
-      "<{}{}{}>{}{}{}{}",
-      self.tag_name,
-      self.attributes.iter().map(|(k, v)| {
-        format!(" {}=\"{}\"", k, v.replace('\\', "\\\\").replace('"', "\\\""))
-      }).collect::<String>(),
-      if self.children.is_empty() { "/" } else { "" },
-      self.children.iter().map(|x| x.0.borrow().inner_html()).collect::<String>(),
-      if self.children.is_empty() { "" } else { "</" },
-      if self.children.is_empty() { "" } else { self.tag_name.as_str() },
-      if self.children.is_empty() { "" } else { ">" },
-    )
-  }
+#[derive(Debug, Default)]
+pub struct TextNode {
+  pub text: String,
+  pub source: Option<SourceSpan>,
 }
 
+
 #[derive(Debug, Default)]
-pub struct TextNode {
+pub struct CommentNode {
   pub text: String,
-  pub parent: Option<WeakNode>,
+  pub source: Option<SourceSpan>,
 }
 
-impl InnerHtml for TextNode {
-  fn inner_html(&self) -> String {
-    //TODO: this can lead tho xss:
-    self.text.clone()
-  }
+
+#[derive(Debug, Default)]
+pub struct ProcessingInstructionNode {
+  pub target: String,
+  pub data: String,
+  pub source: Option<SourceSpan>,
+}
+
+
+#[derive(Debug, Default)]
+pub struct DoctypeNode {
+  pub name: String,
+  pub public_id: String,
+  pub system_id: String,
+  pub source: Option<SourceSpan>,
+}
+
+
+/// Detached list of nodes, it never has a parent
+#[derive(Debug, Default)]
+pub struct DocumentFragmentNode {
+  /// Template element this fragment holds the contents of, set when the template is created
+  pub(crate) host: Option<NodeId>,
 }
 
 // NOTE: Implementing Clone for Node may cause issues with Deref?
@@ -155,55 +246,101 @@ impl InnerHtml for TextNode {
 pub enum Node {
   Element(ElementNode),
   Text(TextNode),
+  Comment(CommentNode),
+  Doctype(DoctypeNode),
+  ProcessingInstruction(ProcessingInstructionNode),
+  DocumentFragment(DocumentFragmentNode),
 }
 
 impl Node {
-  pub fn parent(&self) -> Option<&WeakNode> {
-    match self {
-      Node::Element(element) => element.parent.as_ref(),
-      Node::Text(text) => text.parent.as_ref(),
-    }
-  }
-  pub fn set_parent(&mut self, parent: Option<WeakNode>) {
+  /// Where the node is in the source markup, the start tag for elements
+  pub fn source_span(&self) -> Option<&SourceSpan> {
     match self {
-      Node::Element(element) => element.parent = parent,
-      Node::Text(text) => text.parent = parent,
+      Node::Element(element) => element.source.as_ref().map(|x| &x.start_tag),
+      Node::Text(text) => text.source.as_ref(),
+      Node::Comment(comment) => comment.source.as_ref(),
+      Node::Doctype(doctype) => doctype.source.as_ref(),
+      Node::ProcessingInstruction(instruction) => instruction.source.as_ref(),
+      Node::DocumentFragment(_) => None,
     }
   }
 }
 
-impl InnerHtml for Node {
-  fn inner_html(&self) -> String {
-    match self {
-      Node::Element(element) => element.inner_html(),
-      Node::Text(text) => text.inner_html(),
-    }
-  }
+/// How closely the document follows the standards, determined by its doctype\
+/// See <https://dom.spec.whatwg.org/#concept-document-mode>
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DocumentMode {
+  #[default]
+  NoQuirks,
+  LimitedQuirks,
+  Quirks,
 }
 
-impl SharedNode {
-  pub fn root() -> Self {
-    Node::Element(ElementNode {
-      tag_name: "root".into(),
-      ..Default::default()
-    }).into()
-  }
+/// Markup language of a document
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Syntax {
+  #[default]
+  Html,
+  /// XML documents, including XHTML and SVG
+  Xml,
+}
 
-  pub fn push(&self, node: SharedNode) -> Result<(), DomPushError> {
-    if node.0.borrow().parent().is_some() {
-      return Err(DomPushError::AlreadyHasParent);
-    }
-    if let Node::Element(self_node) = &mut *self.0.borrow_mut() {
-      node.0.borrow_mut().set_parent(Some(self.shared_clone().into()));
-      self_node.children.push(node.shared_clone());
-    } else {
-      return Err(DomPushError::NodeInfertile);
+impl Syntax {
+  /// Syntax of a document served with the given MIME type, e.g. `application/xhtml+xml; charset=utf-8`
+  pub fn from_content_type(content_type: &str) -> Self {
+    let essence = content_type.split(';').next().unwrap().trim().to_ascii_lowercase();
+    match essence.as_str() {
+      "text/xml" | "application/xml" => Syntax::Xml,
+      _ if essence.ends_with("+xml") => Syntax::Xml,
+      _ => Syntax::Html,
     }
-    Ok(())
   }
 }
 
 #[derive(Debug)]
 pub struct Dom {
-  pub tree: SharedNode
+  /// Every node of the document, including detached ones
+  nodes: Vec<tree::NodeData>,
+  /// Slots of freed nodes, by the id the next node in them gets
+  free: Vec<NodeId>,
+  /// Id of the root node, see [`Dom::root`]
+  tree: NodeId,
+  /// Built on first lookup, see [`Dom::get_element_by_id`]
+  ids: OnceLock<tree::IdIndex>,
+  /// See [`Dom::create_mutation_observer`]
+  observers: Vec<mutation::MutationObserver>,
+  /// Errors found while parsing, in source order\
+  /// Errors in HTML are recovered from, in XML the first error stops parsing
+  pub errors: Vec<ParseError>,
+  /// Character encoding the document was decoded from
+  pub encoding: &'static Encoding,
+  pub mode: DocumentMode,
+  pub syntax: Syntax,
+}
+
+impl Default for Dom {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl Dom {
+  /// Empty HTML document, with just the root element
+  pub fn new() -> Self {
+    let root = Node::Element(ElementNode {
+      tag_name: "root".into(),
+      ..Default::default()
+    });
+    Dom {
+      nodes: vec![tree::NodeData::new(root)],
+      free: Vec::new(),
+      tree: NodeId::from_index(0),
+      ids: OnceLock::new(),
+      observers: Vec::new(),
+      errors: Vec::new(),
+      encoding: encoding_rs::UTF_8,
+      mode: DocumentMode::NoQuirks,
+      syntax: Syntax::Html,
+    }
+  }
 }
@@ -0,0 +1,79 @@
+//! Live collections of elements, like DOM `HTMLCollection`
+
+use super::{Dom, DocumentMode, Namespace, NodeId, NodeRef};
+
+#[derive(Debug, Clone)]
+enum Filter {
+  /// Qualified name, or `*` for any element
+  TagName(String),
+  ClassNames(Vec<String>),
+}
+
+/// Elements under a node matching a filter, in document order\
+/// The collection doesn't hold any nodes, it reflects the current state of the document every time it's used
+#[derive(Debug, Clone)]
+pub struct HtmlCollection {
+  root: NodeId,
+  filter: Filter,
+}
+
+impl HtmlCollection {
+  fn matches(&self, node: NodeRef) -> bool {
+    let Some(element) = node.element() else { return false };
+    match &self.filter {
+      Filter::TagName(name) if name == "*" => true,
+      //html elements in html documents match the name in any case
+      Filter::TagName(name) => match element.namespace == Namespace::Html && !element.xml {
+        true => element.tag_name.eq_ignore_ascii_case(name),
+        false => element.tag_name == *name,
+      },
+      Filter::ClassNames(names) => {
+        let quirks = node.dom().mode == DocumentMode::Quirks;
+        let classes = element.attribute("class").unwrap_or_default();
+        !names.is_empty() && names.iter().all(|name| classes.split_ascii_whitespace().any(|class| match quirks {
+          true => class.eq_ignore_ascii_case(name),
+          false => class == name,
+        }))
+      },
+    }
+  }
+
+  pub fn iter<'a>(&'a self, dom: &'a Dom) -> impl Iterator<Item = NodeRef<'a>> + 'a {
+    dom.node(self.root).descendants().filter(|x| self.matches(*x))
+  }
+
+  pub fn len(&self, dom: &Dom) -> usize {
+    self.iter(dom).count()
+  }
+
+  pub fn is_empty(&self, dom: &Dom) -> bool {
+    self.iter(dom).next().is_none()
+  }
+
+  pub fn item<'a>(&'a self, dom: &'a Dom, index: usize) -> Option<NodeRef<'a>> {
+    self.iter(dom).nth(index)
+  }
+}
+
+impl NodeRef<'_> {
+  /// Descendant elements with the qualified name, or all of them for `*`
+  pub fn get_elements_by_tag_name(&self, name: &str) -> HtmlCollection {
+    HtmlCollection { root: self.id(), filter: Filter::TagName(name.to_string()) }
+  }
+
+  /// Descendant elements having all of the whitespace-separated class names
+  pub fn get_elements_by_class_name(&self, names: &str) -> HtmlCollection {
+    let names = names.split_ascii_whitespace().map(str::to_string).collect();
+    HtmlCollection { root: self.id(), filter: Filter::ClassNames(names) }
+  }
+}
+
+impl Dom {
+  pub fn get_elements_by_tag_name(&self, name: &str) -> HtmlCollection {
+    self.root().get_elements_by_tag_name(name)
+  }
+
+  pub fn get_elements_by_class_name(&self, names: &str) -> HtmlCollection {
+    self.root().get_elements_by_class_name(names)
+  }
+}
@@ -1,10 +1,296 @@
+use std::ops::Range;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum DomPushError {
-  #[error("text nodes can't have children")]
+  #[error("node can't have children")]
   NodeInfertile,
 
   #[error("node already has parent")]
   AlreadyHasParent,
+
+  #[error("node can't be inserted into its own subtree")]
+  HierarchyRequest,
+
+  #[error("node is not a child of this node")]
+  NotAChild,
+}
+
+/// Invalid token given to a [`TokenList`](super::TokenList)
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenListError {
+  #[error("token is empty")]
+  Empty,
+
+  #[error("token contains whitespace")]
+  Whitespace,
+}
+
+/// Kind of a parse error\
+/// `Display` gives the error code, tokenizer errors use the codes defined in the HTML spec\
+/// In XML documents any error is fatal, parsing stops at the first one
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseErrorKind {
+  #[error("abrupt-closing-of-empty-comment")]
+  AbruptClosingOfEmptyComment,
+
+  #[error("abrupt-doctype-public-identifier")]
+  AbruptDoctypePublicIdentifier,
+
+  #[error("abrupt-doctype-system-identifier")]
+  AbruptDoctypeSystemIdentifier,
+
+  #[error("absence-of-digits-in-numeric-character-reference")]
+  AbsenceOfDigitsInNumericCharacterReference,
+
+  #[error("cdata-in-html-content")]
+  CdataInHtmlContent,
+
+  #[error("character-reference-outside-unicode-range")]
+  CharacterReferenceOutsideUnicodeRange,
+
+  #[error("control-character-reference")]
+  ControlCharacterReference,
+
+  #[error("duplicate-attribute")]
+  DuplicateAttribute,
+
+  #[error("end-tag-with-attributes")]
+  EndTagWithAttributes,
+
+  #[error("end-tag-with-trailing-solidus")]
+  EndTagWithTrailingSolidus,
+
+  #[error("eof-before-tag-name")]
+  EofBeforeTagName,
+
+  #[error("eof-in-cdata")]
+  EofInCdata,
+
+  #[error("eof-in-comment")]
+  EofInComment,
+
+  #[error("eof-in-doctype")]
+  EofInDoctype,
+
+  #[error("eof-in-script-html-comment-like-text")]
+  EofInScriptHtmlCommentLikeText,
+
+  #[error("eof-in-tag")]
+  EofInTag,
+
+  #[error("incorrectly-closed-comment")]
+  IncorrectlyClosedComment,
+
+  #[error("incorrectly-opened-comment")]
+  IncorrectlyOpenedComment,
+
+  #[error("invalid-character-sequence-after-doctype-name")]
+  InvalidCharacterSequenceAfterDoctypeName,
+
+  #[error("invalid-first-character-of-tag-name")]
+  InvalidFirstCharacterOfTagName,
+
+  #[error("missing-attribute-value")]
+  MissingAttributeValue,
+
+  #[error("missing-doctype-name")]
+  MissingDoctypeName,
+
+  #[error("missing-doctype-public-identifier")]
+  MissingDoctypePublicIdentifier,
+
+  #[error("missing-doctype-system-identifier")]
+  MissingDoctypeSystemIdentifier,
+
+  #[error("missing-end-tag-name")]
+  MissingEndTagName,
+
+  #[error("missing-quote-before-doctype-public-identifier")]
+  MissingQuoteBeforeDoctypePublicIdentifier,
+
+  #[error("missing-quote-before-doctype-system-identifier")]
+  MissingQuoteBeforeDoctypeSystemIdentifier,
+
+  #[error("missing-semicolon-after-character-reference")]
+  MissingSemicolonAfterCharacterReference,
+
+  #[error("missing-whitespace-after-doctype-public-keyword")]
+  MissingWhitespaceAfterDoctypePublicKeyword,
+
+  #[error("missing-whitespace-after-doctype-system-keyword")]
+  MissingWhitespaceAfterDoctypeSystemKeyword,
+
+  #[error("missing-whitespace-before-doctype-name")]
+  MissingWhitespaceBeforeDoctypeName,
+
+  #[error("missing-whitespace-between-attributes")]
+  MissingWhitespaceBetweenAttributes,
+
+  #[error("missing-whitespace-between-doctype-public-and-system-identifiers")]
+  MissingWhitespaceBetweenDoctypePublicAndSystemIdentifiers,
+
+  #[error("nested-comment")]
+  NestedComment,
+
+  #[error("non-void-html-element-start-tag-with-trailing-solidus")]
+  NonVoidHtmlElementStartTagWithTrailingSolidus,
+
+  #[error("noncharacter-character-reference")]
+  NoncharacterCharacterReference,
+
+  #[error("null-character-reference")]
+  NullCharacterReference,
+
+  #[error("surrogate-character-reference")]
+  SurrogateCharacterReference,
+
+  #[error("unexpected-character-after-doctype-system-identifier")]
+  UnexpectedCharacterAfterDoctypeSystemIdentifier,
+
+  #[error("unexpected-character-in-attribute-name")]
+  UnexpectedCharacterInAttributeName,
+
+  #[error("unexpected-character-in-unquoted-attribute-value")]
+  UnexpectedCharacterInUnquotedAttributeValue,
+
+  #[error("unexpected-equals-sign-before-attribute-name")]
+  UnexpectedEqualsSignBeforeAttributeName,
+
+  #[error("unexpected-null-character")]
+  UnexpectedNullCharacter,
+
+  #[error("unexpected-question-mark-instead-of-tag-name")]
+  UnexpectedQuestionMarkInsteadOfTagName,
+
+  #[error("unexpected-solidus-in-tag")]
+  UnexpectedSolidusInTag,
+
+  #[error("unknown-named-character-reference")]
+  UnknownNamedCharacterReference,
+
+  // Tree construction errors
+
+  #[error("eof-in-element-that-can-hold-text")]
+  EofInElementThatCanHoldText,
+
+  #[error("eof-in-frameset")]
+  EofInFrameset,
+
+  #[error("eof-in-template")]
+  EofInTemplate,
+
+  #[error("missing-doctype")]
+  MissingDoctype,
+
+  #[error("non-conforming-doctype")]
+  NonConformingDoctype,
+
+  #[error("unclosed-elements")]
+  UnclosedElements,
+
+  #[error("unexpected-doctype")]
+  UnexpectedDoctype,
+
+  #[error("unexpected-end-tag")]
+  UnexpectedEndTag,
+
+  #[error("unexpected-start-tag")]
+  UnexpectedStartTag,
+
+  #[error("unexpected-token-after-body")]
+  UnexpectedTokenAfterBody,
+
+  #[error("unexpected-token-after-frameset")]
+  UnexpectedTokenAfterFrameset,
+
+  #[error("unexpected-token-in-column-group")]
+  UnexpectedTokenInColumnGroup,
+
+  #[error("unexpected-token-in-frameset")]
+  UnexpectedTokenInFrameset,
+
+  #[error("unexpected-token-in-noscript")]
+  UnexpectedTokenInNoscript,
+
+  #[error("unexpected-token-in-table")]
+  UnexpectedTokenInTable,
+
+  #[error("unexpected-token-in-table-body")]
+  UnexpectedTokenInTableBody,
+
+  // XML well-formedness errors, some of the errors above are reused where they fit
+
+  #[error("cdata-end-in-text")]
+  CdataEndInText,
+
+  #[error("content-outside-root-element")]
+  ContentOutsideRootElement,
+
+  #[error("double-hyphen-in-comment")]
+  DoubleHyphenInComment,
+
+  #[error("entity-too-large")]
+  EntityTooLarge,
+
+  #[error("eof-in-processing-instruction")]
+  EofInProcessingInstruction,
+
+  #[error("invalid-character")]
+  InvalidCharacter,
+
+  #[error("invalid-character-reference")]
+  InvalidCharacterReference,
+
+  #[error("invalid-name")]
+  InvalidName,
+
+  #[error("invalid-qualified-name")]
+  InvalidQualifiedName,
+
+  #[error("invalid-xml-declaration")]
+  InvalidXmlDeclaration,
+
+  #[error("less-than-sign-in-attribute-value")]
+  LessThanSignInAttributeValue,
+
+  #[error("mismatched-end-tag")]
+  MismatchedEndTag,
+
+  #[error("misplaced-xml-declaration")]
+  MisplacedXmlDeclaration,
+
+  #[error("missing-root-element")]
+  MissingRootElement,
+
+  #[error("reserved-namespace")]
+  ReservedNamespace,
+
+  #[error("unbound-namespace-prefix")]
+  UnboundNamespacePrefix,
+
+  #[error("undefined-entity")]
+  UndefinedEntity,
+
+  #[error("unescaped-ampersand")]
+  UnescapedAmpersand,
+
+  #[error("unexpected-character")]
+  UnexpectedCharacter,
+
+  #[error("unquoted-attribute-value")]
+  UnquotedAttributeValue,
+}
+
+/// An error encountered while parsing a document
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("{line}:{column}: {kind}")]
+pub struct ParseError {
+  pub kind: ParseErrorKind,
+  /// 1-based line number
+  pub line: usize,
+  /// 1-based column number, in characters
+  pub column: usize,
+  /// Byte range in the source
+  pub span: Range<usize>,
 }
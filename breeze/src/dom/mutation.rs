@@ -0,0 +1,167 @@
+//! Mutation observers, recording changes made to the tree\
+//! See <https://dom.spec.whatwg.org/#mutation-observers>
+//!
+//! Changes made with the mutation methods of [`NodeMut`](super::NodeMut) are recorded,
+//! changes made by the parser or through `&mut Node` are not.
+//! Records are queued until taken with [`Dom::take_records`], there are no callbacks.
+
+use super::{Dom, NodeId};
+
+/// Observer created with [`Dom::create_mutation_observer`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MutationObserverId(usize);
+
+/// What an observer is notified about, at least one of `child_list`, `attributes` and `character_data` should be set
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MutationObserverInit {
+  /// Children added or removed
+  pub child_list: bool,
+  /// Attributes set or removed
+  pub attributes: bool,
+  /// Record the previous value of changed attributes
+  pub attribute_old_value: bool,
+  /// Only observe these attributes, if given
+  pub attribute_filter: Option<Vec<String>>,
+  /// Data of text, comment and processing instruction nodes changed
+  pub character_data: bool,
+  /// Record the previous data of changed nodes
+  pub character_data_old_value: bool,
+  /// Observe the descendants of the target too, not just the target itself
+  pub subtree: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MutationRecord {
+  ChildList {
+    /// Parent of the added and removed nodes
+    target: NodeId,
+    added: Vec<NodeId>,
+    /// Children replaced by `set_text_content` or `set_inner_html` are freed, their ids are no longer valid
+    removed: Vec<NodeId>,
+    /// Siblings around the added or removed nodes
+    previous_sibling: Option<NodeId>,
+    next_sibling: Option<NodeId>,
+  },
+  Attributes {
+    target: NodeId,
+    name: String,
+    /// Only recorded if requested with `attribute_old_value`
+    old_value: Option<String>,
+  },
+  CharacterData {
+    target: NodeId,
+    /// Only recorded if requested with `character_data_old_value`
+    old_value: Option<String>,
+  },
+}
+
+impl MutationRecord {
+  /// Node that was changed
+  pub fn target(&self) -> NodeId {
+    match self {
+      Self::ChildList { target, .. } | Self::Attributes { target, .. } | Self::CharacterData { target, .. } => *target,
+    }
+  }
+}
+
+#[derive(Debug, Default)]
+pub(super) struct MutationObserver {
+  /// Observed nodes, each at most once
+  targets: Vec<(NodeId, MutationObserverInit)>,
+  records: Vec<MutationRecord>,
+}
+
+impl Dom {
+  pub fn create_mutation_observer(&mut self) -> MutationObserverId {
+    self.observers.push(MutationObserver::default());
+    MutationObserverId(self.observers.len() - 1)
+  }
+
+  /// Panics if the observer doesn't belong to this document
+  fn observer(&mut self, observer: MutationObserverId) -> &mut MutationObserver {
+    self.observers.get_mut(observer.0).expect("observer does not belong to this document")
+  }
+
+  /// Start observing `target`, or change the options if it's already observed
+  pub fn observe(&mut self, observer: MutationObserverId, target: NodeId, options: MutationObserverInit) {
+    let targets = &mut self.observer(observer).targets;
+    match targets.iter_mut().find(|(x, _)| *x == target) {
+      Some((_, old_options)) => *old_options = options,
+      None => targets.push((target, options)),
+    }
+  }
+
+  /// Stop observing all targets and drop the queued records
+  pub fn disconnect(&mut self, observer: MutationObserverId) {
+    *self.observer(observer) = MutationObserver::default();
+  }
+
+  /// Records queued since the last call, in the order the changes were made
+  pub fn take_records(&mut self, observer: MutationObserverId) -> Vec<MutationRecord> {
+    std::mem::take(&mut self.observer(observer).records)
+  }
+
+  /// Queue a record for every observer interested in it\
+  /// Old values are dropped for observers that didn't ask for them
+  fn queue_record(&mut self, record: MutationRecord) {
+    if self.observers.iter().all(|x| x.targets.is_empty()) {
+      return
+    }
+    let node = self.node(record.target());
+    let inclusive_ancestors: Vec<_> = std::iter::once(node).chain(node.ancestors()).map(|x| x.id()).collect();
+    for observer in &mut self.observers {
+      //an observer gets one record even if several of its targets match, with the old value if any asks for it
+      let mut interested = false;
+      let mut old_value = false;
+      let matching = observer.targets.iter()
+        .filter(|(target, options)| *target == record.target() || (options.subtree && inclusive_ancestors.contains(target)));
+      for (_, options) in matching {
+        match &record {
+          MutationRecord::ChildList { .. } if options.child_list => interested = true,
+          MutationRecord::Attributes { name, .. }
+            if options.attributes && options.attribute_filter.as_ref().is_none_or(|filter| filter.contains(name)) => {
+            interested = true;
+            old_value |= options.attribute_old_value;
+          },
+          MutationRecord::CharacterData { .. } if options.character_data => {
+            interested = true;
+            old_value |= options.character_data_old_value;
+          },
+          _ => (),
+        }
+      }
+      if !interested {
+        continue
+      }
+      let mut record = record.clone();
+      if let MutationRecord::Attributes { old_value: value, .. } | MutationRecord::CharacterData { old_value: value, .. } = &mut record {
+        if !old_value {
+          *value = None;
+        }
+      }
+      observer.records.push(record);
+    }
+  }
+
+  /// Record `added` and `removed` children of `target`, between the given siblings
+  pub(super) fn queue_child_list(
+    &mut self,
+    target: NodeId,
+    added: Vec<NodeId>,
+    removed: Vec<NodeId>,
+    previous_sibling: Option<NodeId>,
+    next_sibling: Option<NodeId>,
+  ) {
+    if !added.is_empty() || !removed.is_empty() {
+      self.queue_record(MutationRecord::ChildList { target, added, removed, previous_sibling, next_sibling });
+    }
+  }
+
+  pub(super) fn queue_attribute(&mut self, target: NodeId, name: String, old_value: Option<String>) {
+    self.queue_record(MutationRecord::Attributes { target, name, old_value });
+  }
+
+  pub(super) fn queue_character_data(&mut self, target: NodeId, old_value: String) {
+    self.queue_record(MutationRecord::CharacterData { target, old_value: Some(old_value) });
+  }
+}
@@ -1,127 +1,275 @@
-use crate::grammar::html::{Parser as HtmlParser, Rule as HtmlRule};
-use super::{Dom, Node, ElementNode, TextNode, SharedNode, SharedClone};
-use rustc_hash::FxHashMap;
-use pest::{Parser, iterators::Pair, error::Error as PestError};
-use thiserror::Error;
-
-#[derive(Error, Debug)]
-pub enum DomParseError {
-  #[error("failed to parse html")]
-  ParseError(#[from] Box<PestError<HtmlRule>>),
+use std::{cell::Cell, ops::Range};
+use super::{Dom, Node, NodeRef, NodeMut, ParseError, ParseErrorKind, DomPushError, ElementSource, LineColumn, SourceSpan, Syntax};
+
+mod encoding;
+mod entities;
+mod foreign;
+mod quirks;
+mod tokenizer;
+mod tree_builder;
+mod xml;
+
+use encoding::{InputDecoder, Sniffing};
+use tokenizer::{Token, Tokenizer};
+use tree_builder::TreeBuilder;
+pub use xml::XmlParser;
+
+/// Maps byte offsets to line and column numbers, updated as more source arrives
+struct LineIndex {
+  line_starts: Vec<usize>,
+  scanned: usize,
+  /// Last located offset and its line and column, offsets mostly come in order
+  /// so columns can be counted from here instead of the start of the line
+  last: Cell<(usize, LineColumn)>,
 }
 
-impl Dom {
-  //This function should never panic if the implementation is correct!
-  pub fn parse(html: &str) -> Result<Self, DomParseError> {
-    fn parse_tree(parent_node: &SharedNode, tree_thingy: Pair<HtmlRule>) {
-      debug_assert_eq!(tree_thingy.as_rule(), HtmlRule::tree);
-      for node_thingy in tree_thingy.into_inner() {
-        debug_assert_eq!(node_thingy.as_rule(), HtmlRule::node);
-        let node_subtype = node_thingy.into_inner().next().unwrap();
-        let node_subtype_rule = node_subtype.as_rule();
-
-        match node_subtype_rule {
-          //Full tags: <b>...</b>, and void tags: <img>, <div/>
-          HtmlRule::full_tag | HtmlRule::void_tag => {
-            let mut node_subtype_inner = node_subtype.into_inner();
-
-            let opening_tag_or_ident = node_subtype_inner.next().unwrap();
-
-            //parse opening tag (get name and attributes)
-            let (tag_name, attributes) = {
-              let (attributes_pairs, tag_name) = match opening_tag_or_ident.as_rule() {
-                HtmlRule::opening_tag => {
-                  let mut opening_tag_inner = opening_tag_or_ident.into_inner();
-                  let tag_name_pair = opening_tag_inner.next().unwrap();
-                  debug_assert_eq!(tag_name_pair.as_rule(), HtmlRule::ident);
-                  let tag_name = tag_name_pair.as_str().into();
-                  let attributes_pair = opening_tag_inner.next();
-                  (attributes_pair, tag_name)
-                },
-                HtmlRule::ident | HtmlRule::void_tag_name => {
-                  let tag_name = opening_tag_or_ident.as_str().into();
-                  let attributes_pair = node_subtype_inner.next();
-                  (attributes_pair, tag_name)
-                },
-                _ => unreachable!()
-              };
-              
-              //parse attributes
-              let mut attributes = FxHashMap::default();
-              if let Some(attributes_pairs) = attributes_pairs {
-                for attribute_pair in attributes_pairs.into_inner() {
-                  debug_assert_eq!(attribute_pair.as_rule(), HtmlRule::attribute);
-                  let mut attribute_inner = attribute_pair.into_inner();
-
-                  let attribute_ident = attribute_inner.next().unwrap();
-                  debug_assert_eq!(attribute_ident.as_rule(), HtmlRule::ident);
-                  let attribute_name = attribute_ident.as_str().to_owned();
-
-                  let attribute_value: String = if let Some(str_pair) = attribute_inner.next() {
-                    match str_pair.as_rule() {
-                      HtmlRule::value_proper => {
-                        let &(chr, mut as_str) = &str_pair.as_str().split_at(1);
-                        as_str = &as_str[..(as_str.len() - 1)];
-                        as_str.replace("\\\\", "\\").replace(
-                          if chr == "\"" { "\\\"" } else if chr == "'" { "\\'" } else { unreachable!() },
-                          if chr == "\"" { "\"" } else if chr == "'" { "'" } else { unreachable!() },
-                        )
-                      },
-                      HtmlRule::value_naked => str_pair.as_str().into(),
-                      _ => unreachable!()
-                    }
-                  } else {
-                    "".into()
-                  };
-
-                  attributes.insert(attribute_name, attribute_value);
-                }
-              }
-              (tag_name, attributes)
-            };
-            
-            //Create and insert new node
-            let new_node: SharedNode = Node::Element(ElementNode::new_with_tag_and_attributes(tag_name, attributes)).into();
-            println!("NODE {:?}", new_node);
-            parent_node.push(new_node.shared_clone()).unwrap();
-
-            //If full tag, recursively parse children
-            if node_subtype_rule == HtmlRule::full_tag {
-              let ptree = node_subtype_inner.next().unwrap();
-              debug_assert_eq!(ptree.as_rule(), HtmlRule::tree);
-              parse_tree(&new_node, ptree);
-
-              // ensure correctness of the parsed tree?
-              let closing_tag = node_subtype_inner.next().unwrap();
-              debug_assert_eq!(closing_tag.as_rule(), HtmlRule::closing_tag);
-            }
-          },
-          //Text nodes
-          HtmlRule::text_node => {
-            let new_node: SharedNode = Node::Text(TextNode {
-              text: node_subtype.as_str().into(),
-              ..Default::default()
-            }).into();
-            println!("TEXT {:?}", new_node);
-            parent_node.push(new_node).unwrap();
-          },
-          _ => unreachable!(),
-        }
+impl LineIndex {
+  fn new() -> Self {
+    Self {
+      line_starts: vec![0],
+      scanned: 0,
+      last: Cell::new((0, LineColumn { line: 1, column: 1 })),
+    }
+  }
+
+  /// Index newly appended source
+  fn update(&mut self, source: &str, closed: bool) {
+    let bytes = source.as_bytes();
+    while self.scanned < bytes.len() {
+      let index = self.scanned;
+      match (bytes[index], bytes.get(index + 1)) {
+        (b'\r', None) if !closed => return,
+        (b'\n', _) => self.line_starts.push(index + 1),
+        (b'\r', next) if next != Some(&b'\n') => self.line_starts.push(index + 1),
+        _ => (),
       }
+      self.scanned += 1;
+    }
+  }
+
+  /// 1-based line and column (in characters) of a byte offset
+  fn locate(&self, source: &str, offset: usize) -> LineColumn {
+    let line = self.line_starts.partition_point(|&x| x <= offset) - 1;
+    let (last_offset, last) = self.last.get();
+    let position = if last.line == line + 1 && last_offset <= offset {
+      LineColumn { line: last.line, column: last.column + source[last_offset..offset].chars().count() }
+    } else if last.line == line + 1 && offset - self.line_starts[line] > last_offset - offset {
+      LineColumn { line: last.line, column: last.column - source[offset..last_offset].chars().count() }
+    } else {
+      LineColumn { line: line + 1, column: source[self.line_starts[line]..offset].chars().count() + 1 }
+    };
+    self.last.set((offset, position));
+    position
+  }
+
+  fn span(&self, source: &str, range: Range<usize>) -> SourceSpan {
+    SourceSpan {
+      start: self.locate(source, range.start),
+      end: self.locate(source, range.end),
+      range,
+    }
+  }
+}
+
+/// Incremental HTML parser
+///
+/// Feed it chunks of the document as they arrive, the partially built [`Dom`]
+/// can be inspected between chunks.
+pub struct DomParser {
+  tokenizer: Tokenizer,
+  /// Holds the document being built
+  builder: TreeBuilder,
+  line_index: LineIndex,
+  input: InputDecoder,
+}
+
+impl Default for DomParser {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl DomParser {
+  /// Parser for UTF-8 encoded input
+  pub fn new() -> Self {
+    Self::with_builder(TreeBuilder::new())
+  }
+
+  /// Parser that detects the encoding of the input
+  ///
+  /// The encoding is taken from the byte order mark, then `transport_hint`
+  /// (an encoding label, like the `charset` of the `Content-Type` header),
+  /// then a `<meta>` charset declaration at the start of the document,
+  /// falling back to windows-1252.
+  pub fn with_encoding_detection(transport_hint: Option<&str>) -> Self {
+    let mut parser = Self::with_builder(TreeBuilder::new());
+    parser.input = InputDecoder::detecting(Sniffing::Html, transport_hint);
+    parser
+  }
+
+  /// Parser for a fragment of HTML, as if it was inside of `context`
+  fn new_fragment(context: NodeRef) -> Self {
+    Self::with_builder(TreeBuilder::new_fragment(context))
+  }
+
+  fn with_builder(mut builder: TreeBuilder) -> Self {
+    let mut tokenizer = Tokenizer::new();
+    if let Some(state) = builder.take_tokenizer_state() {
+      tokenizer.set_state(state);
+    }
+    Self {
+      tokenizer,
+      builder,
+      line_index: LineIndex::new(),
+      input: InputDecoder::utf8(Sniffing::Html),
+    }
+  }
+
+  /// Feed the next chunk of input\
+  /// Chunks may be split at any byte, invalid sequences are replaced with U+FFFD
+  pub fn feed(&mut self, chunk: &[u8]) {
+    self.decode(chunk, false);
+  }
+
+  fn decode(&mut self, bytes: &[u8], last: bool) {
+    let data = self.input.decode(bytes, last);
+    if let Some(encoding) = self.input.encoding() {
+      self.builder.dom.encoding = encoding;
+    }
+    self.feed_str(&data);
+  }
+
+  fn feed_str(&mut self, data: &str) {
+    if data.is_empty() {
+      return
     }
+    self.tokenizer.push_str(data);
+    self.run();
+  }
 
-    let tree = SharedNode::root();
+  /// The document parsed so far
+  pub fn dom(&self) -> &Dom {
+    &self.builder.dom
+  }
 
-    let mut pairs = HtmlParser::parse(HtmlRule::dom, html).map_err(Box::new)?;
-    let dom = pairs.next().unwrap(); //Never fails
-    for thingy in dom.into_inner() {
-      match thingy.as_rule() {
-        HtmlRule::tree => parse_tree(&tree, thingy),
-        HtmlRule::EOI | HtmlRule::preamble => (),
-        _ => unreachable!(),
+  /// Signal the end of input and return the finished document
+  pub fn finish(mut self) -> Dom {
+    self.decode(&[], true);
+    self.tokenizer.close();
+    self.run();
+    self.builder.dom
+  }
+
+  fn run(&mut self) {
+    loop {
+      self.tokenizer.set_cdata_allowed(self.builder.in_foreign_element());
+      let Some((mut token, span)) = self.tokenizer.next_token() else { break };
+      let source = self.tokenizer.source();
+      self.line_index.update(source, self.tokenizer.is_closed());
+      if let Token::StartTag(tag) = &mut token {
+        tag.source = Some(ElementSource {
+          start_tag: self.line_index.span(source, span.clone()),
+          end_tag: None,
+          attributes: tag.attributes.iter().map(|attribute| {
+            (attribute.name.clone(), self.line_index.span(source, attribute.span.clone()))
+          }).collect(),
+        });
       }
+      let span = self.line_index.span(source, span);
+      self.builder.process(token, span);
+      if let Some(state) = self.builder.take_tokenizer_state() {
+        self.tokenizer.set_state(state);
+      }
+    }
+    self.builder.dom.mode = self.builder.document_mode;
+    self.collect_errors();
+  }
+
+  fn collect_errors(&mut self) {
+    let mut errors: Vec<(ParseErrorKind, Range<usize>)> = self.tokenizer.errors.drain(..).collect();
+    errors.append(&mut self.builder.errors);
+    if errors.is_empty() {
+      return
+    }
+    errors.sort_by_key(|(_, span)| span.start);
+    let source = self.tokenizer.source();
+    self.line_index.update(source, self.tokenizer.is_closed());
+    self.builder.dom.errors.extend(errors.into_iter().map(|(kind, span)| {
+      let LineColumn { line, column } = self.line_index.locate(source, span.start);
+      ParseError { kind, line, column, span }
+    }));
+  }
+}
+
+impl Dom {
+  /// Parse a fragment of HTML, as if it was the contents of `context`, or of body if it isn't an element
+  ///
+  /// The [`Dom::root`] of the result is a document fragment holding the parsed nodes
+  pub fn parse_fragment(html: &str, context: NodeRef) -> Self {
+    let mut parser = DomParser::new_fragment(context);
+    parser.feed_str(html);
+    let mut dom = parser.finish();
+    // Move the nodes out of the html element the fragment was parsed into
+    let html = dom.data(dom.tree).last_child.unwrap();
+    let fragment = dom.create_fragment();
+    dom.move_children(html, fragment);
+    dom.tree = fragment;
+    dom
+  }
+
+  /// Parse an HTML document
+  ///
+  /// Like browsers do, this never fails, any markup errors are recovered from
+  /// and reported in [`Dom::errors`]
+  pub fn parse(html: &str) -> Self {
+    let mut parser = DomParser::new();
+    parser.feed_str(html);
+    parser.finish()
+  }
+
+  /// Parse an HTML document from bytes in an unknown encoding
+  ///
+  /// See [`DomParser::with_encoding_detection`] for how the encoding is
+  /// determined, the one used ends up in [`Dom::encoding`]
+  pub fn parse_bytes(html: &[u8], transport_hint: Option<&str>) -> Self {
+    let mut parser = DomParser::with_encoding_detection(transport_hint);
+    parser.feed(html);
+    parser.finish()
+  }
+}
+
+impl Dom {
+  /// Parse a document served with the given `Content-Type`
+  ///
+  /// The MIME type picks between the HTML and XML parser (see [`Syntax::from_content_type`]),
+  /// its `charset` parameter is used as the transport encoding
+  pub fn parse_document(bytes: &[u8], content_type: &str) -> Self {
+    let charset = encoding::encoding_from_content(content_type.as_bytes()).map(|x| x.name());
+    match Syntax::from_content_type(content_type) {
+      Syntax::Html => Self::parse_bytes(bytes, charset),
+      Syntax::Xml => Self::parse_xml_bytes(bytes, charset),
     }
+  }
+}
 
-    Ok(Self { tree })
+impl NodeMut<'_> {
+  /// Replace the children of this node (or the contents of a template) with nodes parsed from `html`\
+  /// The old children are freed, document fragments are parsed into as if they were body
+  pub fn set_inner_html(&mut self, html: &str) -> Result<(), DomPushError> {
+    let target = match self.node().value() {
+      Node::Element(element) => element.template_contents.unwrap_or(self.id()),
+      Node::DocumentFragment(_) => self.id(),
+      _ => return Err(DomPushError::NodeInfertile),
+    };
+    let parsed = Dom::parse_fragment(html, self.node());
+    let dom = self.dom();
+    let nodes = dom.adopt(parsed);
+    let fragment = dom.create_fragment();
+    for node in nodes {
+      dom.link_child(fragment, node);
+    }
+    let result = dom.replace_all(target, Some(fragment));
+    dom.node_mut(fragment).remove();
+    result
   }
 }
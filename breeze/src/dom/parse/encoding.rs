@@ -0,0 +1,335 @@
+//! Encoding sniffing, as described in
+//! <https://html.spec.whatwg.org/multipage/parsing.html#determining-the-character-encoding>,
+//! and decoding of input that arrives in chunks
+
+use encoding_rs::{CoderResult, Decoder, Encoding, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252, X_USER_DEFINED};
+
+/// Encoding used when nothing else tells us what the document is encoded in
+const DEFAULT_ENCODING: &Encoding = WINDOWS_1252;
+
+/// How many bytes of the document are searched for a `<meta>` charset declaration
+const PRESCAN_LENGTH: usize = 1024;
+
+fn is_whitespace(byte: u8) -> bool {
+  matches!(byte, b'\t' | b'\n' | b'\x0C' | b'\r' | b' ')
+}
+
+/// Encodings declared from within the document can't be ASCII-incompatible
+fn declared_encoding(encoding: &'static Encoding) -> &'static Encoding {
+  if encoding == UTF_16BE || encoding == UTF_16LE {
+    UTF_8
+  } else if encoding == X_USER_DEFINED {
+    WINDOWS_1252
+  } else {
+    encoding
+  }
+}
+
+/// Extract the encoding from a `content` attribute like `text/html; charset=utf-8`
+pub(crate) fn encoding_from_content(content: &[u8]) -> Option<&'static Encoding> {
+  let mut position = 0;
+  loop {
+    let offset = content[position..].windows(7).position(|x| x.eq_ignore_ascii_case(b"charset"))?;
+    position += offset + 7;
+    while content.get(position).is_some_and(|&x| is_whitespace(x)) {
+      position += 1;
+    }
+    if content.get(position) == Some(&b'=') {
+      position += 1;
+      break
+    }
+  }
+  while content.get(position).is_some_and(|&x| is_whitespace(x)) {
+    position += 1;
+  }
+  let value = &content[position..];
+  match value.first()? {
+    quote @ (b'"' | b'\'') => {
+      let end = value[1..].iter().position(|x| x == quote)?;
+      Encoding::for_label(&value[1..end + 1])
+    },
+    _ => {
+      let end = value.iter().position(|&x| is_whitespace(x) || x == b';').unwrap_or(value.len());
+      Encoding::for_label(&value[..end])
+    }
+  }
+}
+
+struct Prescanner<'a> {
+  bytes: &'a [u8],
+  position: usize,
+}
+
+impl Prescanner<'_> {
+  fn byte(&self) -> Option<u8> {
+    self.bytes.get(self.position).copied()
+  }
+
+  fn starts_with(&self, what: &[u8]) -> bool {
+    self.bytes.get(self.position..self.position + what.len()).is_some_and(|x| x.eq_ignore_ascii_case(what))
+  }
+
+  fn skip_while(&mut self, predicate: impl Fn(u8) -> bool) -> Option<u8> {
+    loop {
+      let byte = self.byte()?;
+      if !predicate(byte) {
+        return Some(byte)
+      }
+      self.position += 1;
+    }
+  }
+
+  /// Move past the next occurence of `what`, searching from `from` bytes ahead
+  fn skip_past(&mut self, from: usize, what: &[u8]) -> Option<()> {
+    let start = self.position + from;
+    let offset = self.bytes.get(start..)?.windows(what.len()).position(|x| x == what)?;
+    self.position = start + offset + what.len();
+    Some(())
+  }
+
+  /// Read the next attribute, `Ok(None)` means the end of the tag was reached
+  fn attribute(&mut self) -> Option<Option<(Vec<u8>, Vec<u8>)>> {
+    if self.skip_while(|x| is_whitespace(x) || x == b'/')? == b'>' {
+      return Some(None)
+    }
+    let mut name = Vec::new();
+    let mut value = Vec::new();
+    loop {
+      match self.byte()? {
+        b'=' if !name.is_empty() => {
+          self.position += 1;
+          break
+        },
+        byte if is_whitespace(byte) => {
+          if self.skip_while(is_whitespace)? != b'=' {
+            return Some(Some((name, value)))
+          }
+          self.position += 1;
+          break
+        },
+        b'/' | b'>' => return Some(Some((name, value))),
+        byte => name.push(byte.to_ascii_lowercase()),
+      }
+      self.position += 1;
+    }
+    match self.skip_while(is_whitespace)? {
+      quote @ (b'"' | b'\'') => loop {
+        self.position += 1;
+        match self.byte()? {
+          byte if byte == quote => {
+            self.position += 1;
+            return Some(Some((name, value)))
+          },
+          byte => value.push(byte.to_ascii_lowercase()),
+        }
+      },
+      b'>' => return Some(Some((name, value))),
+      _ => (),
+    }
+    loop {
+      match self.byte()? {
+        byte if is_whitespace(byte) || byte == b'>' => return Some(Some((name, value))),
+        byte => value.push(byte.to_ascii_lowercase()),
+      }
+      self.position += 1;
+    }
+  }
+
+  /// Handle a `<meta` tag, the position is right after the tag name
+  fn meta(&mut self) -> Option<Option<&'static Encoding>> {
+    let mut seen = Vec::new();
+    let mut got_pragma = false;
+    let mut need_pragma = None;
+    let mut charset = None;
+    while let Some((name, value)) = self.attribute()? {
+      if seen.contains(&name) {
+        continue
+      }
+      match &name[..] {
+        b"http-equiv" => got_pragma |= value == b"content-type",
+        b"content" if charset.is_none() => {
+          if let Some(encoding) = encoding_from_content(&value) {
+            charset = Some(encoding);
+            need_pragma = Some(true);
+          }
+        },
+        b"charset" => {
+          charset = Encoding::for_label(&value);
+          need_pragma = Some(false);
+        },
+        _ => (),
+      }
+      seen.push(name);
+    }
+    match need_pragma {
+      Some(true) if !got_pragma => Some(None),
+      Some(_) => Some(charset.map(declared_encoding)),
+      None => Some(None),
+    }
+  }
+
+  fn run(&mut self) -> Option<&'static Encoding> {
+    while self.position < self.bytes.len() {
+      if self.starts_with(b"<!--") {
+        self.skip_past(2, b"-->")?;
+        continue
+      }
+      if self.starts_with(b"<meta") && self.bytes.get(self.position + 5).is_some_and(|&x| is_whitespace(x) || x == b'/') {
+        self.position += 5;
+        if let Some(encoding) = self.meta()? {
+          return Some(encoding)
+        }
+      } else if self.starts_with(b"<") && self.bytes.get(self.position + 1).is_some_and(u8::is_ascii_alphabetic)
+        || self.starts_with(b"</") && self.bytes.get(self.position + 2).is_some_and(u8::is_ascii_alphabetic)
+      {
+        self.skip_while(|x| !is_whitespace(x) && x != b'>')?;
+        while self.attribute()?.is_some() {}
+      } else if self.starts_with(b"<!") || self.starts_with(b"</") || self.starts_with(b"<?") {
+        self.skip_past(1, b">")?;
+        continue
+      }
+      self.position += 1;
+    }
+    None
+  }
+}
+
+/// Look for a `<meta>` charset declaration at the start of the document
+fn prescan(bytes: &[u8]) -> Option<&'static Encoding> {
+  let bytes = &bytes[..bytes.len().min(PRESCAN_LENGTH)];
+  Prescanner { bytes, position: 0 }.run()
+}
+
+/// Check if enough of an XML document has been buffered to find its encoding
+fn xml_sniffing_done(bytes: &[u8]) -> bool {
+  const BOMS: [&[u8]; 3] = [b"\xEF\xBB\xBF", b"\xFE\xFF", b"\xFF\xFE"];
+  if bytes.len() >= PRESCAN_LENGTH || Encoding::for_bom(bytes).is_some() {
+    return true
+  }
+  if BOMS.iter().any(|bom| bom.starts_with(bytes)) {
+    return false
+  }
+  let start = &bytes[..bytes.len().min(5)];
+  !b"<?xml".starts_with(start) || bytes.windows(2).any(|x| x == b"?>")
+}
+
+/// Encoding from the `<?xml encoding="..."?>` declaration at the start of an XML document
+fn xml_declaration_encoding(bytes: &[u8]) -> Option<&'static Encoding> {
+  let bytes = &bytes[..bytes.len().min(PRESCAN_LENGTH)];
+  if !bytes.starts_with(b"<?xml") || !bytes.get(5).is_some_and(|&x| is_whitespace(x)) {
+    return None
+  }
+  let end = bytes.windows(2).position(|x| x == b"?>")?;
+  let declaration = &bytes[..end];
+  let mut position = declaration.windows(8).position(|x| x == b"encoding")? + 8;
+  let skip_whitespace = |mut position: usize| {
+    while declaration.get(position).is_some_and(|&x| is_whitespace(x)) {
+      position += 1;
+    }
+    position
+  };
+  position = skip_whitespace(position);
+  if declaration.get(position) != Some(&b'=') {
+    return None
+  }
+  position = skip_whitespace(position + 1);
+  let quote @ (b'"' | b'\'') = *declaration.get(position)? else { return None };
+  let value = &declaration[position + 1..];
+  let end = value.iter().position(|&x| x == quote)?;
+  Encoding::for_label(&value[..end]).map(declared_encoding)
+}
+
+/// Which kind of document the encoding is sniffed for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Sniffing {
+  /// `<meta>` charset declarations, falling back to windows-1252
+  Html,
+  /// The `<?xml?>` declaration, falling back to UTF-8
+  Xml,
+}
+
+/// Decodes input chunks into text, holding them back while the encoding is being sniffed
+pub(crate) struct InputDecoder {
+  sniffing: Sniffing,
+  /// `None` until the encoding of the input is known
+  decoder: Option<Decoder>,
+  /// Input held back while sniffing the encoding
+  sniff_buffer: Vec<u8>,
+  /// Encoding given by the transport layer (e.g. the `Content-Type` header)
+  transport_encoding: Option<&'static Encoding>,
+}
+
+impl InputDecoder {
+  /// Decoder for UTF-8 input
+  pub(crate) fn utf8(sniffing: Sniffing) -> Self {
+    Self {
+      sniffing,
+      decoder: Some(UTF_8.new_decoder_with_bom_removal()),
+      sniff_buffer: Vec::new(),
+      transport_encoding: None,
+    }
+  }
+
+  /// Decoder that takes the encoding from the byte order mark, then `transport_hint`, then the document itself
+  pub(crate) fn detecting(sniffing: Sniffing, transport_hint: Option<&str>) -> Self {
+    Self {
+      decoder: None,
+      transport_encoding: transport_hint.and_then(|x| Encoding::for_label(x.as_bytes())),
+      ..Self::utf8(sniffing)
+    }
+  }
+
+  /// Encoding of the input, once it's known
+  pub(crate) fn encoding(&self) -> Option<&'static Encoding> {
+    self.decoder.as_ref().map(|x| x.encoding())
+  }
+
+  /// Decode the next chunk, `last` if it's the end of the input\
+  /// Chunks may be split at any byte, invalid sequences are replaced with U+FFFD
+  pub(crate) fn decode(&mut self, chunk: &[u8], last: bool) -> String {
+    if self.decoder.is_some() {
+      return self.decode_bytes(chunk, last)
+    }
+    self.sniff_buffer.extend_from_slice(chunk);
+    if !last && !self.sniffing_done() {
+      return String::new()
+    }
+    let buffer = std::mem::take(&mut self.sniff_buffer);
+    let bom_length = self.start_decoding(&buffer);
+    self.decode_bytes(&buffer[bom_length..], last)
+  }
+
+  fn sniffing_done(&self) -> bool {
+    match (self.transport_encoding, self.sniffing) {
+      // Only the byte order mark can override the transport encoding
+      (Some(_), _) => self.sniff_buffer.len() >= 3,
+      (None, Sniffing::Html) => self.sniff_buffer.len() >= PRESCAN_LENGTH,
+      (None, Sniffing::Xml) => xml_sniffing_done(&self.sniff_buffer),
+    }
+  }
+
+  /// Settle on an encoding, returns the length of the byte order mark
+  fn start_decoding(&mut self, buffer: &[u8]) -> usize {
+    let (encoding, bom_length) = Encoding::for_bom(buffer).unwrap_or_else(|| {
+      let encoding = self.transport_encoding.or_else(|| match self.sniffing {
+        Sniffing::Html => prescan(buffer),
+        Sniffing::Xml => xml_declaration_encoding(buffer),
+      });
+      let fallback = match self.sniffing {
+        Sniffing::Html => DEFAULT_ENCODING,
+        Sniffing::Xml => UTF_8,
+      };
+      (encoding.unwrap_or(fallback), 0)
+    });
+    self.decoder = Some(encoding.new_decoder_without_bom_handling());
+    bom_length
+  }
+
+  fn decode_bytes(&mut self, bytes: &[u8], last: bool) -> String {
+    let decoder = self.decoder.as_mut().unwrap();
+    let mut data = String::with_capacity(decoder.max_utf8_buffer_length(bytes.len()).unwrap());
+    let (result, _, _) = decoder.decode_to_string(bytes, &mut data, last);
+    debug_assert!(result == CoderResult::InputEmpty);
+    data
+  }
+}
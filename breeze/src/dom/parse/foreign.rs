@@ -0,0 +1,151 @@
+//! Name adjustments for SVG and MathML elements, see
+//! <https://html.spec.whatwg.org/multipage/parsing.html#parsing-main-inforeign>
+
+use super::tokenizer::Tag;
+use crate::dom::Namespace;
+
+const SVG_TAG_NAMES: &[(&str, &str)] = &[
+  ("altglyph", "altGlyph"),
+  ("altglyphdef", "altGlyphDef"),
+  ("altglyphitem", "altGlyphItem"),
+  ("animatecolor", "animateColor"),
+  ("animatemotion", "animateMotion"),
+  ("animatetransform", "animateTransform"),
+  ("clippath", "clipPath"),
+  ("feblend", "feBlend"),
+  ("fecolormatrix", "feColorMatrix"),
+  ("fecomponenttransfer", "feComponentTransfer"),
+  ("fecomposite", "feComposite"),
+  ("feconvolvematrix", "feConvolveMatrix"),
+  ("fediffuselighting", "feDiffuseLighting"),
+  ("fedisplacementmap", "feDisplacementMap"),
+  ("fedistantlight", "feDistantLight"),
+  ("fedropshadow", "feDropShadow"),
+  ("feflood", "feFlood"),
+  ("fefunca", "feFuncA"),
+  ("fefuncb", "feFuncB"),
+  ("fefuncg", "feFuncG"),
+  ("fefuncr", "feFuncR"),
+  ("fegaussianblur", "feGaussianBlur"),
+  ("feimage", "feImage"),
+  ("femerge", "feMerge"),
+  ("femergenode", "feMergeNode"),
+  ("femorphology", "feMorphology"),
+  ("feoffset", "feOffset"),
+  ("fepointlight", "fePointLight"),
+  ("fespecularlighting", "feSpecularLighting"),
+  ("fespotlight", "feSpotLight"),
+  ("fetile", "feTile"),
+  ("feturbulence", "feTurbulence"),
+  ("foreignobject", "foreignObject"),
+  ("glyphref", "glyphRef"),
+  ("lineargradient", "linearGradient"),
+  ("radialgradient", "radialGradient"),
+  ("textpath", "textPath"),
+];
+
+const SVG_ATTRIBUTE_NAMES: &[(&str, &str)] = &[
+  ("attributename", "attributeName"),
+  ("attributetype", "attributeType"),
+  ("basefrequency", "baseFrequency"),
+  ("baseprofile", "baseProfile"),
+  ("calcmode", "calcMode"),
+  ("clippathunits", "clipPathUnits"),
+  ("diffuseconstant", "diffuseConstant"),
+  ("edgemode", "edgeMode"),
+  ("filterunits", "filterUnits"),
+  ("glyphref", "glyphRef"),
+  ("gradienttransform", "gradientTransform"),
+  ("gradientunits", "gradientUnits"),
+  ("kernelmatrix", "kernelMatrix"),
+  ("kernelunitlength", "kernelUnitLength"),
+  ("keypoints", "keyPoints"),
+  ("keysplines", "keySplines"),
+  ("keytimes", "keyTimes"),
+  ("lengthadjust", "lengthAdjust"),
+  ("limitingconeangle", "limitingConeAngle"),
+  ("markerheight", "markerHeight"),
+  ("markerunits", "markerUnits"),
+  ("markerwidth", "markerWidth"),
+  ("maskcontentunits", "maskContentUnits"),
+  ("maskunits", "maskUnits"),
+  ("numoctaves", "numOctaves"),
+  ("pathlength", "pathLength"),
+  ("patterncontentunits", "patternContentUnits"),
+  ("patterntransform", "patternTransform"),
+  ("patternunits", "patternUnits"),
+  ("pointsatx", "pointsAtX"),
+  ("pointsaty", "pointsAtY"),
+  ("pointsatz", "pointsAtZ"),
+  ("preservealpha", "preserveAlpha"),
+  ("preserveaspectratio", "preserveAspectRatio"),
+  ("primitiveunits", "primitiveUnits"),
+  ("refx", "refX"),
+  ("refy", "refY"),
+  ("repeatcount", "repeatCount"),
+  ("repeatdur", "repeatDur"),
+  ("requiredextensions", "requiredExtensions"),
+  ("requiredfeatures", "requiredFeatures"),
+  ("specularconstant", "specularConstant"),
+  ("specularexponent", "specularExponent"),
+  ("spreadmethod", "spreadMethod"),
+  ("startoffset", "startOffset"),
+  ("stddeviation", "stdDeviation"),
+  ("stitchtiles", "stitchTiles"),
+  ("surfacescale", "surfaceScale"),
+  ("systemlanguage", "systemLanguage"),
+  ("tablevalues", "tableValues"),
+  ("targetx", "targetX"),
+  ("targety", "targetY"),
+  ("textlength", "textLength"),
+  ("viewbox", "viewBox"),
+  ("viewtarget", "viewTarget"),
+  ("xchannelselector", "xChannelSelector"),
+  ("ychannelselector", "yChannelSelector"),
+  ("zoomandpan", "zoomAndPan"),
+];
+
+const FOREIGN_ATTRIBUTES: &[(&str, Namespace)] = &[
+  ("xlink:actuate", Namespace::XLink),
+  ("xlink:arcrole", Namespace::XLink),
+  ("xlink:href", Namespace::XLink),
+  ("xlink:role", Namespace::XLink),
+  ("xlink:show", Namespace::XLink),
+  ("xlink:title", Namespace::XLink),
+  ("xlink:type", Namespace::XLink),
+  ("xml:lang", Namespace::Xml),
+  ("xml:space", Namespace::Xml),
+  ("xmlns", Namespace::Xmlns),
+  ("xmlns:xlink", Namespace::Xmlns),
+];
+
+fn lookup<T: Clone>(table: &[(&str, T)], name: &str) -> Option<T> {
+  table.iter().find(|(from, _)| *from == name).map(|(_, to)| to.clone())
+}
+
+pub(crate) fn adjust_svg_tag_name(tag: &mut Tag) {
+  if let Some(name) = lookup(SVG_TAG_NAMES, &tag.name) {
+    tag.name = name.into();
+  }
+}
+
+pub(crate) fn adjust_svg_attributes(tag: &mut Tag) {
+  for attribute in &mut tag.attributes {
+    if let Some(name) = lookup(SVG_ATTRIBUTE_NAMES, &attribute.name) {
+      attribute.name = name.into();
+    }
+  }
+}
+
+pub(crate) fn adjust_mathml_attributes(tag: &mut Tag) {
+  for attribute in &mut tag.attributes {
+    if attribute.name == "definitionurl" {
+      attribute.name = "definitionURL".into();
+    }
+  }
+}
+
+/// Namespace of an attribute on a foreign element
+pub(crate) fn foreign_attribute_namespace(name: &str) -> Option<Namespace> {
+  lookup(FOREIGN_ATTRIBUTES, name)
+}
@@ -0,0 +1,110 @@
+//! Document mode determination, see <https://html.spec.whatwg.org/multipage/parsing.html#the-initial-insertion-mode>
+
+use super::tokenizer::Doctype;
+use crate::dom::DocumentMode;
+
+const QUIRKY_PUBLIC_IDS: &[&str] = &[
+  "-//W3O//DTD W3 HTML Strict 3.0//EN//",
+  "-/W3C/DTD HTML 4.0 Transitional/EN",
+  "HTML",
+];
+
+const QUIRKY_PUBLIC_ID_PREFIXES: &[&str] = &[
+  "+//Silmaril//dtd html Pro v0r11 19970101//",
+  "-//AS//DTD HTML 3.0 asWedit + extensions//",
+  "-//AdvaSoft Ltd//DTD HTML 3.0 asWedit + extensions//",
+  "-//IETF//DTD HTML 2.0 Level 1//",
+  "-//IETF//DTD HTML 2.0 Level 2//",
+  "-//IETF//DTD HTML 2.0 Strict Level 1//",
+  "-//IETF//DTD HTML 2.0 Strict Level 2//",
+  "-//IETF//DTD HTML 2.0 Strict//",
+  "-//IETF//DTD HTML 2.0//",
+  "-//IETF//DTD HTML 2.1E//",
+  "-//IETF//DTD HTML 3.0//",
+  "-//IETF//DTD HTML 3.2 Final//",
+  "-//IETF//DTD HTML 3.2//",
+  "-//IETF//DTD HTML 3//",
+  "-//IETF//DTD HTML Level 0//",
+  "-//IETF//DTD HTML Level 1//",
+  "-//IETF//DTD HTML Level 2//",
+  "-//IETF//DTD HTML Level 3//",
+  "-//IETF//DTD HTML Strict Level 0//",
+  "-//IETF//DTD HTML Strict Level 1//",
+  "-//IETF//DTD HTML Strict Level 2//",
+  "-//IETF//DTD HTML Strict Level 3//",
+  "-//IETF//DTD HTML Strict//",
+  "-//IETF//DTD HTML//",
+  "-//Metrius//DTD Metrius Presentational//",
+  "-//Microsoft//DTD Internet Explorer 2.0 HTML Strict//",
+  "-//Microsoft//DTD Internet Explorer 2.0 HTML//",
+  "-//Microsoft//DTD Internet Explorer 2.0 Tables//",
+  "-//Microsoft//DTD Internet Explorer 3.0 HTML Strict//",
+  "-//Microsoft//DTD Internet Explorer 3.0 HTML//",
+  "-//Microsoft//DTD Internet Explorer 3.0 Tables//",
+  "-//Netscape Comm. Corp.//DTD HTML//",
+  "-//Netscape Comm. Corp.//DTD Strict HTML//",
+  "-//O'Reilly and Associates//DTD HTML 2.0//",
+  "-//O'Reilly and Associates//DTD HTML Extended 1.0//",
+  "-//O'Reilly and Associates//DTD HTML Extended Relaxed 1.0//",
+  "-//SQ//DTD HTML 2.0 HoTMetaL + extensions//",
+  "-//SoftQuad Software//DTD HoTMetaL PRO 6.0::19990601::extensions to HTML 4.0//",
+  "-//SoftQuad//DTD HoTMetaL PRO 4.0::19971010::extensions to HTML 4.0//",
+  "-//Spyglass//DTD HTML 2.0 Extended//",
+  "-//Sun Microsystems Corp.//DTD HotJava HTML//",
+  "-//Sun Microsystems Corp.//DTD HotJava Strict HTML//",
+  "-//W3C//DTD HTML 3 1995-03-24//",
+  "-//W3C//DTD HTML 3.2 Draft//",
+  "-//W3C//DTD HTML 3.2 Final//",
+  "-//W3C//DTD HTML 3.2//",
+  "-//W3C//DTD HTML 3.2S Draft//",
+  "-//W3C//DTD HTML 4.0 Frameset//",
+  "-//W3C//DTD HTML 4.0 Transitional//",
+  "-//W3C//DTD HTML Experimental 19960712//",
+  "-//W3C//DTD HTML Experimental 970421//",
+  "-//W3C//DTD W3 HTML//",
+  "-//W3O//DTD W3 HTML 3.0//",
+  "-//WebTechs//DTD Mozilla HTML 2.0//",
+  "-//WebTechs//DTD Mozilla HTML//",
+];
+
+const QUIRKY_SYSTEM_ID: &str = "http://www.ibm.com/data/dtd/v11/ibmxhtml1-transitional.dtd";
+
+/// Quirky without a system identifier, limited-quirky with one
+const HTML_401_PUBLIC_ID_PREFIXES: &[&str] = &[
+  "-//W3C//DTD HTML 4.01 Frameset//",
+  "-//W3C//DTD HTML 4.01 Transitional//",
+];
+
+const LIMITED_QUIRKY_PUBLIC_ID_PREFIXES: &[&str] = &[
+  "-//W3C//DTD XHTML 1.0 Frameset//",
+  "-//W3C//DTD XHTML 1.0 Transitional//",
+];
+
+fn starts_with_ignore_case(string: &str, prefix: &str) -> bool {
+  string.get(..prefix.len()).is_some_and(|x| x.eq_ignore_ascii_case(prefix))
+}
+
+fn starts_with_any(string: &str, prefixes: &[&str]) -> bool {
+  prefixes.iter().any(|prefix| starts_with_ignore_case(string, prefix))
+}
+
+/// Document mode implied by a doctype
+pub(crate) fn document_mode(doctype: &Doctype) -> DocumentMode {
+  let public_id = doctype.public_id.as_deref().unwrap_or_default();
+  let system_id = doctype.system_id.as_deref();
+  if doctype.force_quirks ||
+    doctype.name.as_deref() != Some("html") ||
+    QUIRKY_PUBLIC_IDS.iter().any(|x| public_id.eq_ignore_ascii_case(x)) ||
+    system_id.is_some_and(|x| x.eq_ignore_ascii_case(QUIRKY_SYSTEM_ID)) ||
+    starts_with_any(public_id, QUIRKY_PUBLIC_ID_PREFIXES) ||
+    (system_id.is_none() && starts_with_any(public_id, HTML_401_PUBLIC_ID_PREFIXES))
+  {
+    DocumentMode::Quirks
+  } else if starts_with_any(public_id, LIMITED_QUIRKY_PUBLIC_ID_PREFIXES) ||
+    (system_id.is_some() && starts_with_any(public_id, HTML_401_PUBLIC_ID_PREFIXES))
+  {
+    DocumentMode::LimitedQuirks
+  } else {
+    DocumentMode::NoQuirks
+  }
+}
@@ -0,0 +1,1424 @@
+//! HTML tokenizer, see <https://html.spec.whatwg.org/multipage/parsing.html#tokenization>
+
+use std::{collections::VecDeque, ops::Range};
+use super::entities::NAMED_CHARACTER_REFERENCES;
+use crate::dom::{ElementSource, ParseErrorKind};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum State {
+  Data,
+  Rcdata,
+  Rawtext,
+  ScriptData,
+  Plaintext,
+  TagOpen,
+  EndTagOpen,
+  TagName,
+  RcdataLessThanSign,
+  RcdataEndTagOpen,
+  RcdataEndTagName,
+  RawtextLessThanSign,
+  RawtextEndTagOpen,
+  RawtextEndTagName,
+  ScriptDataLessThanSign,
+  ScriptDataEndTagOpen,
+  ScriptDataEndTagName,
+  ScriptDataEscapeStart,
+  ScriptDataEscapeStartDash,
+  ScriptDataEscaped,
+  ScriptDataEscapedDash,
+  ScriptDataEscapedDashDash,
+  ScriptDataEscapedLessThanSign,
+  ScriptDataEscapedEndTagOpen,
+  ScriptDataEscapedEndTagName,
+  ScriptDataDoubleEscapeStart,
+  ScriptDataDoubleEscaped,
+  ScriptDataDoubleEscapedDash,
+  ScriptDataDoubleEscapedDashDash,
+  ScriptDataDoubleEscapedLessThanSign,
+  ScriptDataDoubleEscapeEnd,
+  BeforeAttributeName,
+  AttributeName,
+  AfterAttributeName,
+  BeforeAttributeValue,
+  AttributeValueDoubleQuoted,
+  AttributeValueSingleQuoted,
+  AttributeValueUnquoted,
+  AfterAttributeValueQuoted,
+  SelfClosingStartTag,
+  BogusComment,
+  MarkupDeclarationOpen,
+  CommentStart,
+  CommentStartDash,
+  Comment,
+  CommentLessThanSign,
+  CommentLessThanSignBang,
+  CommentLessThanSignBangDash,
+  CommentLessThanSignBangDashDash,
+  CommentEndDash,
+  CommentEnd,
+  CommentEndBang,
+  Doctype,
+  BeforeDoctypeName,
+  DoctypeName,
+  AfterDoctypeName,
+  AfterDoctypePublicKeyword,
+  BeforeDoctypePublicIdentifier,
+  DoctypePublicIdentifierDoubleQuoted,
+  DoctypePublicIdentifierSingleQuoted,
+  AfterDoctypePublicIdentifier,
+  BetweenDoctypePublicAndSystemIdentifiers,
+  AfterDoctypeSystemKeyword,
+  BeforeDoctypeSystemIdentifier,
+  DoctypeSystemIdentifierDoubleQuoted,
+  DoctypeSystemIdentifierSingleQuoted,
+  AfterDoctypeSystemIdentifier,
+  BogusDoctype,
+  CdataSection,
+  CdataSectionBracket,
+  CdataSectionEnd,
+  CharacterReference,
+  NamedCharacterReference,
+  AmbiguousAmpersand,
+  NumericCharacterReference,
+  HexadecimalCharacterReferenceStart,
+  DecimalCharacterReferenceStart,
+  HexadecimalCharacterReference,
+  DecimalCharacterReference,
+  NumericCharacterReferenceEnd,
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Attribute {
+  pub name: String,
+  pub value: String,
+  /// Byte range of the attribute in the source
+  pub span: Range<usize>,
+}
+
+/// Attributes are equal if their names and values are
+impl PartialEq for Attribute {
+  fn eq(&self, other: &Self) -> bool {
+    self.name == other.name && self.value == other.value
+  }
+}
+
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Tag {
+  pub name: String,
+  pub self_closing: bool,
+  pub attributes: Vec<Attribute>,
+  /// Filled in with line and column numbers before the tag is passed to the tree builder
+  pub source: Option<ElementSource>,
+}
+
+impl Tag {
+  pub fn new(name: &str) -> Self {
+    Self {
+      name: name.into(),
+      ..Default::default()
+    }
+  }
+
+  pub fn attribute(&self, name: &str) -> Option<&str> {
+    self.attributes.iter().find(|x| x.name == name).map(|x| x.value.as_str())
+  }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Doctype {
+  pub name: Option<String>,
+  pub public_id: Option<String>,
+  pub system_id: Option<String>,
+  pub force_quirks: bool,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Comment {
+  pub data: String,
+  /// Bogus comment opened with `<?`, kept as a processing instruction
+  pub processing_instruction: bool,
+}
+
+#[derive(Debug)]
+pub(crate) enum Token {
+  Doctype(Doctype),
+  StartTag(Tag),
+  EndTag(Tag),
+  Comment(Comment),
+  Character(char),
+  Eof,
+}
+
+/// Input stream, takes care of newline normalization
+struct Input {
+  data: String,
+  pos: usize,
+  prev: usize,
+  /// No more data will be appended
+  closed: bool,
+}
+
+impl Input {
+  /// Whether the next character can be consumed, or the end of input is known
+  fn can_consume(&self) -> bool {
+    // A trailing CR may be the first half of a CRLF pair
+    self.closed || !matches!(self.rest(), "" | "\r")
+  }
+
+  /// Whether the next `len` bytes are available, or the end of input is known
+  fn can_peek(&self, len: usize) -> bool {
+    self.closed || self.rest().len() >= len
+  }
+
+  fn next(&mut self) -> Option<char> {
+    self.prev = self.pos;
+    let chr = self.data[self.pos..].chars().next()?;
+    self.pos += chr.len_utf8();
+    if chr == '\r' {
+      if self.data[self.pos..].starts_with('\n') {
+        self.pos += 1;
+      }
+      return Some('\n')
+    }
+    Some(chr)
+  }
+
+  /// Un-consume the last consumed character
+  fn back(&mut self) {
+    self.pos = self.prev;
+  }
+
+  fn rest(&self) -> &str {
+    &self.data[self.pos..]
+  }
+
+  /// Consume `what` if the input starts with it
+  fn eat(&mut self, what: &str, ignore_case: bool) -> bool {
+    let Some(rest) = self.data.get(self.pos..(self.pos + what.len())) else {
+      return false
+    };
+    let matches = if ignore_case {
+      rest.eq_ignore_ascii_case(what)
+    } else {
+      rest == what
+    };
+    if matches {
+      self.pos += what.len();
+    }
+    matches
+  }
+}
+
+fn is_whitespace(chr: char) -> bool {
+  matches!(chr, '\t' | '\n' | '\x0C' | ' ')
+}
+
+/// Length of the longest named character reference, `CounterClockwiseContourIntegral;`
+const LONGEST_NAMED_REFERENCE: usize = 32;
+
+pub(crate) struct Tokenizer {
+  input: Input,
+  state: State,
+  tag: Tag,
+  tag_is_end: bool,
+  attribute: Option<Attribute>,
+  attribute_start: usize,
+  attribute_end: usize,
+  comment: String,
+  processing_instruction: bool,
+  doctype: Doctype,
+  temp: String,
+  return_state: State,
+  char_ref_code: u32,
+  last_start_tag: Option<String>,
+  token_start: usize,
+  /// Whether `<![CDATA[` opens a CDATA section, only the case in foreign content
+  cdata_allowed: bool,
+  queue: VecDeque<(Token, Range<usize>)>,
+  done: bool,
+  pub errors: Vec<(ParseErrorKind, Range<usize>)>,
+}
+
+impl Tokenizer {
+  pub fn new() -> Self {
+    Self {
+      input: Input {
+        data: String::new(),
+        pos: 0,
+        prev: 0,
+        closed: false,
+      },
+      state: State::Data,
+      tag: Tag::default(),
+      tag_is_end: false,
+      attribute: None,
+      attribute_start: 0,
+      attribute_end: 0,
+      comment: String::new(),
+      processing_instruction: false,
+      doctype: Doctype::default(),
+      temp: String::new(),
+      return_state: State::Data,
+      char_ref_code: 0,
+      last_start_tag: None,
+      token_start: 0,
+      cdata_allowed: false,
+      queue: VecDeque::new(),
+      done: false,
+      errors: Vec::new(),
+    }
+  }
+
+  /// Append more input
+  pub fn push_str(&mut self, data: &str) {
+    debug_assert!(!self.input.closed);
+    self.input.data.push_str(data);
+  }
+
+  /// Signal the end of input
+  pub fn close(&mut self) {
+    self.input.closed = true;
+  }
+
+  pub fn is_closed(&self) -> bool {
+    self.input.closed
+  }
+
+  /// All input received so far
+  pub fn source(&self) -> &str {
+    &self.input.data
+  }
+
+  pub fn set_cdata_allowed(&mut self, allowed: bool) {
+    self.cdata_allowed = allowed;
+  }
+
+  /// Used by the tree builder to switch between Data, RCDATA, RAWTEXT etc.
+  pub fn set_state(&mut self, state: State) {
+    self.state = state;
+  }
+
+  /// Returns the next token and its byte range in the source
+  ///
+  /// Returns `None` once the input is exhausted, more tokens may become available after [`Tokenizer::push_str`]
+  pub fn next_token(&mut self) -> Option<(Token, Range<usize>)> {
+    while self.queue.is_empty() && !self.done && self.step() {}
+    self.queue.pop_front()
+  }
+
+  fn parse_error(&mut self, kind: ParseErrorKind) {
+    self.errors.push((kind, self.input.prev..self.input.pos));
+  }
+
+  fn emit(&mut self, token: Token) {
+    let span = match token {
+      Token::Eof => {
+        self.done = true;
+        self.input.pos..self.input.pos
+      },
+      _ => self.token_start..self.input.pos,
+    };
+    self.queue.push_back((token, span));
+  }
+
+  fn emit_str(&mut self, s: &str) {
+    for chr in s.chars() {
+      self.emit(Token::Character(chr));
+    }
+  }
+
+  fn reconsume_in(&mut self, state: State) {
+    self.input.back();
+    self.state = state;
+  }
+
+  fn new_tag(&mut self, is_end: bool) {
+    self.tag = Tag::default();
+    self.tag_is_end = is_end;
+    self.attribute = None;
+  }
+
+  /// Finish the current attribute (if any) and add it to the tag.\
+  /// Duplicate attributes are dropped, only the first one is kept
+  fn finish_attribute(&mut self) {
+    if let Some(mut attribute) = self.attribute.take() {
+      attribute.span = self.attribute_start..self.attribute_end;
+      if self.tag.attributes.iter().any(|x| x.name == attribute.name) {
+        let span = self.attribute_start..(self.attribute_start + attribute.name.len());
+        self.errors.push((ParseErrorKind::DuplicateAttribute, span));
+      } else {
+        self.tag.attributes.push(attribute);
+      }
+    }
+  }
+
+  fn new_attribute(&mut self, name: &str) {
+    self.finish_attribute();
+    self.attribute_start = self.input.prev;
+    self.attribute_end = self.input.pos;
+    self.attribute = Some(Attribute {
+      name: name.into(),
+      ..Default::default()
+    });
+  }
+
+  fn attribute_name(&mut self) -> &mut String {
+    self.attribute_end = self.input.pos;
+    &mut self.attribute.as_mut().unwrap().name
+  }
+
+  fn attribute_value(&mut self) -> &mut String {
+    self.attribute_end = self.input.pos;
+    &mut self.attribute.as_mut().unwrap().value
+  }
+
+  fn emit_tag(&mut self) {
+    self.finish_attribute();
+    let tag = std::mem::take(&mut self.tag);
+    if self.tag_is_end {
+      if !tag.attributes.is_empty() {
+        self.parse_error(ParseErrorKind::EndTagWithAttributes);
+      }
+      if tag.self_closing {
+        self.parse_error(ParseErrorKind::EndTagWithTrailingSolidus);
+      }
+      self.emit(Token::EndTag(tag));
+    } else {
+      self.last_start_tag = Some(tag.name.clone());
+      self.emit(Token::StartTag(tag));
+    }
+  }
+
+  fn emit_comment(&mut self) {
+    let comment = Comment {
+      data: std::mem::take(&mut self.comment),
+      processing_instruction: std::mem::take(&mut self.processing_instruction),
+    };
+    self.emit(Token::Comment(comment));
+  }
+
+  fn emit_doctype(&mut self) {
+    let doctype = std::mem::take(&mut self.doctype);
+    self.emit(Token::Doctype(doctype));
+  }
+
+  fn is_appropriate_end_tag(&self) -> bool {
+    self.last_start_tag.as_deref() == Some(self.tag.name.as_str())
+  }
+
+  /// Run one step of the state machine\
+  /// Returns `false` (without consuming anything) if more input is needed
+  fn step(&mut self) -> bool {
+    let ready = match self.state {
+      State::MarkupDeclarationOpen => self.input.can_peek("[CDATA[".len()),
+      State::AfterDoctypeName => self.input.can_peek("PUBLIC".len()),
+      State::NamedCharacterReference => {
+        self.input.can_peek(LONGEST_NAMED_REFERENCE) ||
+        self.input.rest().bytes().any(|x| !x.is_ascii_alphanumeric())
+      },
+      State::NumericCharacterReferenceEnd => true,
+      _ => self.input.can_consume(),
+    };
+    if !ready {
+      return false
+    }
+    // In states that emit characters directly, each character is a separate token
+    if matches!(
+      self.state,
+      State::Data | State::Rcdata | State::Rawtext | State::ScriptData | State::Plaintext |
+      State::ScriptDataEscaped | State::ScriptDataEscapedDash | State::ScriptDataEscapedDashDash |
+      State::ScriptDataDoubleEscaped | State::ScriptDataDoubleEscapedDash | State::ScriptDataDoubleEscapedDashDash |
+      State::CdataSection | State::CdataSectionBracket | State::CdataSectionEnd
+    ) {
+      self.token_start = self.input.pos;
+    }
+    match self.state {
+      State::Data => match self.input.next() {
+        Some('&') => self.begin_character_reference(State::Data),
+        Some('<') => self.state = State::TagOpen,
+        Some('\0') => {
+          self.parse_error(ParseErrorKind::UnexpectedNullCharacter);
+          self.emit(Token::Character('\0'));
+        },
+        Some(chr) => self.emit(Token::Character(chr)),
+        None => self.emit(Token::Eof),
+      },
+      State::Rcdata | State::Rawtext => match self.input.next() {
+        Some('&') if self.state == State::Rcdata => self.begin_character_reference(State::Rcdata),
+        Some('<') => self.state = match self.state {
+          State::Rcdata => State::RcdataLessThanSign,
+          _ => State::RawtextLessThanSign,
+        },
+        Some('\0') => {
+          self.parse_error(ParseErrorKind::UnexpectedNullCharacter);
+          self.emit(Token::Character('\u{FFFD}'));
+        },
+        Some(chr) => self.emit(Token::Character(chr)),
+        None => self.emit(Token::Eof),
+      },
+      State::ScriptData => match self.input.next() {
+        Some('<') => self.state = State::ScriptDataLessThanSign,
+        Some('\0') => {
+          self.parse_error(ParseErrorKind::UnexpectedNullCharacter);
+          self.emit(Token::Character('\u{FFFD}'));
+        },
+        Some(chr) => self.emit(Token::Character(chr)),
+        None => self.emit(Token::Eof),
+      },
+      State::Plaintext => match self.input.next() {
+        Some('\0') => {
+          self.parse_error(ParseErrorKind::UnexpectedNullCharacter);
+          self.emit(Token::Character('\u{FFFD}'));
+        },
+        Some(chr) => self.emit(Token::Character(chr)),
+        None => self.emit(Token::Eof),
+      },
+      State::TagOpen => match self.input.next() {
+        Some('!') => self.state = State::MarkupDeclarationOpen,
+        Some('/') => self.state = State::EndTagOpen,
+        Some(chr) if chr.is_ascii_alphabetic() => {
+          self.new_tag(false);
+          self.reconsume_in(State::TagName);
+        },
+        Some('?') => {
+          self.parse_error(ParseErrorKind::UnexpectedQuestionMarkInsteadOfTagName);
+          self.comment.clear();
+          self.processing_instruction = true;
+          self.reconsume_in(State::BogusComment);
+        },
+        None => {
+          self.parse_error(ParseErrorKind::EofBeforeTagName);
+          self.emit(Token::Character('<'));
+          self.emit(Token::Eof);
+        },
+        Some(_) => {
+          self.parse_error(ParseErrorKind::InvalidFirstCharacterOfTagName);
+          self.emit(Token::Character('<'));
+          self.reconsume_in(State::Data);
+        },
+      },
+      State::EndTagOpen => match self.input.next() {
+        Some(chr) if chr.is_ascii_alphabetic() => {
+          self.new_tag(true);
+          self.reconsume_in(State::TagName);
+        },
+        Some('>') => {
+          self.parse_error(ParseErrorKind::MissingEndTagName);
+          self.state = State::Data;
+        },
+        None => {
+          self.parse_error(ParseErrorKind::EofBeforeTagName);
+          self.emit_str("</");
+          self.emit(Token::Eof);
+        },
+        Some(_) => {
+          self.parse_error(ParseErrorKind::InvalidFirstCharacterOfTagName);
+          self.comment.clear();
+          self.reconsume_in(State::BogusComment);
+        },
+      },
+      State::TagName => match self.input.next() {
+        Some(chr) if is_whitespace(chr) => self.state = State::BeforeAttributeName,
+        Some('/') => self.state = State::SelfClosingStartTag,
+        Some('>') => {
+          self.state = State::Data;
+          self.emit_tag();
+        },
+        Some('\0') => {
+          self.parse_error(ParseErrorKind::UnexpectedNullCharacter);
+          self.tag.name.push('\u{FFFD}');
+        },
+        Some(chr) => self.tag.name.push(chr.to_ascii_lowercase()),
+        None => {
+          self.parse_error(ParseErrorKind::EofInTag);
+          self.emit(Token::Eof);
+        },
+      },
+      State::RcdataLessThanSign => self.text_less_than_sign(State::Rcdata, State::RcdataEndTagOpen),
+      State::RawtextLessThanSign => self.text_less_than_sign(State::Rawtext, State::RawtextEndTagOpen),
+      State::RcdataEndTagOpen => self.text_end_tag_open(State::Rcdata, State::RcdataEndTagName),
+      State::RawtextEndTagOpen => self.text_end_tag_open(State::Rawtext, State::RawtextEndTagName),
+      State::RcdataEndTagName => self.text_end_tag_name(State::Rcdata),
+      State::RawtextEndTagName => self.text_end_tag_name(State::Rawtext),
+      State::ScriptDataLessThanSign => match self.input.next() {
+        Some('/') => {
+          self.temp.clear();
+          self.state = State::ScriptDataEndTagOpen;
+        },
+        Some('!') => {
+          self.state = State::ScriptDataEscapeStart;
+          self.emit_str("<!");
+        },
+        _ => {
+          self.emit(Token::Character('<'));
+          self.reconsume_in(State::ScriptData);
+        },
+      },
+      State::ScriptDataEndTagOpen => self.text_end_tag_open(State::ScriptData, State::ScriptDataEndTagName),
+      State::ScriptDataEndTagName => self.text_end_tag_name(State::ScriptData),
+      State::ScriptDataEscapeStart | State::ScriptDataEscapeStartDash => match self.input.next() {
+        Some('-') => {
+          self.state = match self.state {
+            State::ScriptDataEscapeStart => State::ScriptDataEscapeStartDash,
+            _ => State::ScriptDataEscapedDashDash,
+          };
+          self.emit(Token::Character('-'));
+        },
+        _ => self.reconsume_in(State::ScriptData),
+      },
+      State::ScriptDataEscaped | State::ScriptDataEscapedDash | State::ScriptDataEscapedDashDash => {
+        match self.input.next() {
+          Some('-') => {
+            self.state = match self.state {
+              State::ScriptDataEscaped => State::ScriptDataEscapedDash,
+              _ => State::ScriptDataEscapedDashDash,
+            };
+            self.emit(Token::Character('-'));
+          },
+          Some('<') => self.state = State::ScriptDataEscapedLessThanSign,
+          Some('>') if self.state == State::ScriptDataEscapedDashDash => {
+            self.state = State::ScriptData;
+            self.emit(Token::Character('>'));
+          },
+          Some('\0') => {
+            self.parse_error(ParseErrorKind::UnexpectedNullCharacter);
+            self.state = State::ScriptDataEscaped;
+            self.emit(Token::Character('\u{FFFD}'));
+          },
+          Some(chr) => {
+            self.state = State::ScriptDataEscaped;
+            self.emit(Token::Character(chr));
+          },
+          None => {
+            self.parse_error(ParseErrorKind::EofInScriptHtmlCommentLikeText);
+            self.emit(Token::Eof);
+          },
+        }
+      },
+      State::ScriptDataEscapedLessThanSign => match self.input.next() {
+        Some('/') => {
+          self.temp.clear();
+          self.state = State::ScriptDataEscapedEndTagOpen;
+        },
+        Some(chr) if chr.is_ascii_alphabetic() => {
+          self.temp.clear();
+          self.emit(Token::Character('<'));
+          self.reconsume_in(State::ScriptDataDoubleEscapeStart);
+        },
+        _ => {
+          self.emit(Token::Character('<'));
+          self.reconsume_in(State::ScriptDataEscaped);
+        },
+      },
+      State::ScriptDataEscapedEndTagOpen => {
+        self.text_end_tag_open(State::ScriptDataEscaped, State::ScriptDataEscapedEndTagName);
+      },
+      State::ScriptDataEscapedEndTagName => self.text_end_tag_name(State::ScriptDataEscaped),
+      State::ScriptDataDoubleEscapeStart | State::ScriptDataDoubleEscapeEnd => {
+        let (matched, otherwise) = match self.state {
+          State::ScriptDataDoubleEscapeStart => (State::ScriptDataDoubleEscaped, State::ScriptDataEscaped),
+          _ => (State::ScriptDataEscaped, State::ScriptDataDoubleEscaped),
+        };
+        match self.input.next() {
+          Some(chr) if is_whitespace(chr) || chr == '/' || chr == '>' => {
+            self.state = if self.temp == "script" { matched } else { otherwise };
+            self.emit(Token::Character(chr));
+          },
+          Some(chr) if chr.is_ascii_alphabetic() => {
+            self.temp.push(chr.to_ascii_lowercase());
+            self.emit(Token::Character(chr));
+          },
+          _ => self.reconsume_in(otherwise),
+        }
+      },
+      State::ScriptDataDoubleEscaped | State::ScriptDataDoubleEscapedDash | State::ScriptDataDoubleEscapedDashDash => {
+        match self.input.next() {
+          Some('-') => {
+            self.state = match self.state {
+              State::ScriptDataDoubleEscaped => State::ScriptDataDoubleEscapedDash,
+              _ => State::ScriptDataDoubleEscapedDashDash,
+            };
+            self.emit(Token::Character('-'));
+          },
+          Some('<') => {
+            self.state = State::ScriptDataDoubleEscapedLessThanSign;
+            self.emit(Token::Character('<'));
+          },
+          Some('>') if self.state == State::ScriptDataDoubleEscapedDashDash => {
+            self.state = State::ScriptData;
+            self.emit(Token::Character('>'));
+          },
+          Some('\0') => {
+            self.parse_error(ParseErrorKind::UnexpectedNullCharacter);
+            self.state = State::ScriptDataDoubleEscaped;
+            self.emit(Token::Character('\u{FFFD}'));
+          },
+          Some(chr) => {
+            self.state = State::ScriptDataDoubleEscaped;
+            self.emit(Token::Character(chr));
+          },
+          None => {
+            self.parse_error(ParseErrorKind::EofInScriptHtmlCommentLikeText);
+            self.emit(Token::Eof);
+          },
+        }
+      },
+      State::ScriptDataDoubleEscapedLessThanSign => match self.input.next() {
+        Some('/') => {
+          self.temp.clear();
+          self.state = State::ScriptDataDoubleEscapeEnd;
+          self.emit(Token::Character('/'));
+        },
+        _ => self.reconsume_in(State::ScriptDataDoubleEscaped),
+      },
+      State::BeforeAttributeName => match self.input.next() {
+        Some(chr) if is_whitespace(chr) => (),
+        Some('/' | '>') | None => self.reconsume_in(State::AfterAttributeName),
+        Some('=') => {
+          self.parse_error(ParseErrorKind::UnexpectedEqualsSignBeforeAttributeName);
+          self.new_attribute("=");
+          self.state = State::AttributeName;
+        },
+        Some(_) => {
+          self.new_attribute("");
+          self.reconsume_in(State::AttributeName);
+        },
+      },
+      State::AttributeName => match self.input.next() {
+        Some(chr) if is_whitespace(chr) => self.reconsume_in(State::AfterAttributeName),
+        Some('/' | '>') | None => self.reconsume_in(State::AfterAttributeName),
+        Some('=') => self.state = State::BeforeAttributeValue,
+        Some('\0') => {
+          self.parse_error(ParseErrorKind::UnexpectedNullCharacter);
+          self.attribute_name().push('\u{FFFD}');
+        },
+        Some(chr) => {
+          if matches!(chr, '"' | '\'' | '<') {
+            self.parse_error(ParseErrorKind::UnexpectedCharacterInAttributeName);
+          }
+          self.attribute_name().push(chr.to_ascii_lowercase());
+        },
+      },
+      State::AfterAttributeName => match self.input.next() {
+        Some(chr) if is_whitespace(chr) => (),
+        Some('/') => self.state = State::SelfClosingStartTag,
+        Some('=') => self.state = State::BeforeAttributeValue,
+        Some('>') => {
+          self.state = State::Data;
+          self.emit_tag();
+        },
+        None => {
+          self.parse_error(ParseErrorKind::EofInTag);
+          self.emit(Token::Eof);
+        },
+        Some(_) => {
+          self.new_attribute("");
+          self.reconsume_in(State::AttributeName);
+        },
+      },
+      State::BeforeAttributeValue => match self.input.next() {
+        Some(chr) if is_whitespace(chr) => (),
+        Some('"') => self.state = State::AttributeValueDoubleQuoted,
+        Some('\'') => self.state = State::AttributeValueSingleQuoted,
+        Some('>') => {
+          self.parse_error(ParseErrorKind::MissingAttributeValue);
+          self.state = State::Data;
+          self.emit_tag();
+        },
+        _ => self.reconsume_in(State::AttributeValueUnquoted),
+      },
+      State::AttributeValueDoubleQuoted | State::AttributeValueSingleQuoted => {
+        let quote = match self.state {
+          State::AttributeValueDoubleQuoted => '"',
+          _ => '\'',
+        };
+        match self.input.next() {
+          Some(chr) if chr == quote => {
+            self.attribute_end = self.input.pos;
+            self.state = State::AfterAttributeValueQuoted;
+          },
+          Some('&') => self.begin_character_reference(self.state),
+          Some('\0') => {
+            self.parse_error(ParseErrorKind::UnexpectedNullCharacter);
+            self.attribute_value().push('\u{FFFD}');
+          },
+          Some(chr) => self.attribute_value().push(chr),
+          None => {
+            self.parse_error(ParseErrorKind::EofInTag);
+            self.emit(Token::Eof);
+          },
+        }
+      },
+      State::AttributeValueUnquoted => match self.input.next() {
+        Some(chr) if is_whitespace(chr) => self.state = State::BeforeAttributeName,
+        Some('&') => self.begin_character_reference(State::AttributeValueUnquoted),
+        Some('>') => {
+          self.state = State::Data;
+          self.emit_tag();
+        },
+        Some('\0') => {
+          self.parse_error(ParseErrorKind::UnexpectedNullCharacter);
+          self.attribute_value().push('\u{FFFD}');
+        },
+        Some(chr) => {
+          if matches!(chr, '"' | '\'' | '<' | '=' | '`') {
+            self.parse_error(ParseErrorKind::UnexpectedCharacterInUnquotedAttributeValue);
+          }
+          self.attribute_value().push(chr);
+        },
+        None => {
+          self.parse_error(ParseErrorKind::EofInTag);
+          self.emit(Token::Eof);
+        },
+      },
+      State::AfterAttributeValueQuoted => match self.input.next() {
+        Some(chr) if is_whitespace(chr) => self.state = State::BeforeAttributeName,
+        Some('/') => self.state = State::SelfClosingStartTag,
+        Some('>') => {
+          self.state = State::Data;
+          self.emit_tag();
+        },
+        None => {
+          self.parse_error(ParseErrorKind::EofInTag);
+          self.emit(Token::Eof);
+        },
+        Some(_) => {
+          self.parse_error(ParseErrorKind::MissingWhitespaceBetweenAttributes);
+          self.reconsume_in(State::BeforeAttributeName);
+        },
+      },
+      State::SelfClosingStartTag => match self.input.next() {
+        Some('>') => {
+          self.tag.self_closing = true;
+          self.state = State::Data;
+          self.emit_tag();
+        },
+        None => {
+          self.parse_error(ParseErrorKind::EofInTag);
+          self.emit(Token::Eof);
+        },
+        Some(_) => {
+          self.parse_error(ParseErrorKind::UnexpectedSolidusInTag);
+          self.reconsume_in(State::BeforeAttributeName);
+        },
+      },
+      State::BogusComment => match self.input.next() {
+        Some('>') => {
+          self.state = State::Data;
+          self.emit_comment();
+        },
+        Some('\0') => {
+          self.parse_error(ParseErrorKind::UnexpectedNullCharacter);
+          self.comment.push('\u{FFFD}');
+        },
+        Some(chr) => self.comment.push(chr),
+        None => {
+          self.emit_comment();
+          self.emit(Token::Eof);
+        },
+      },
+      State::MarkupDeclarationOpen => {
+        if self.input.eat("--", false) {
+          self.comment.clear();
+          self.state = State::CommentStart;
+        } else if self.input.eat("DOCTYPE", true) {
+          self.state = State::Doctype;
+        } else if self.input.eat("[CDATA[", false) {
+          if self.cdata_allowed {
+            self.state = State::CdataSection;
+          } else {
+            self.parse_error(ParseErrorKind::CdataInHtmlContent);
+            self.comment = "[CDATA[".into();
+            self.state = State::BogusComment;
+          }
+        } else {
+          self.parse_error(ParseErrorKind::IncorrectlyOpenedComment);
+          self.comment.clear();
+          self.state = State::BogusComment;
+        }
+      },
+      State::CommentStart => match self.input.next() {
+        Some('-') => self.state = State::CommentStartDash,
+        Some('>') => {
+          self.parse_error(ParseErrorKind::AbruptClosingOfEmptyComment);
+          self.state = State::Data;
+          self.emit_comment();
+        },
+        _ => self.reconsume_in(State::Comment),
+      },
+      State::CommentStartDash => match self.input.next() {
+        Some('-') => self.state = State::CommentEnd,
+        Some('>') => {
+          self.parse_error(ParseErrorKind::AbruptClosingOfEmptyComment);
+          self.state = State::Data;
+          self.emit_comment();
+        },
+        None => self.eof_in_comment(),
+        Some(_) => {
+          self.comment.push('-');
+          self.reconsume_in(State::Comment);
+        },
+      },
+      State::Comment => match self.input.next() {
+        Some('<') => {
+          self.comment.push('<');
+          self.state = State::CommentLessThanSign;
+        },
+        Some('-') => self.state = State::CommentEndDash,
+        Some('\0') => {
+          self.parse_error(ParseErrorKind::UnexpectedNullCharacter);
+          self.comment.push('\u{FFFD}');
+        },
+        Some(chr) => self.comment.push(chr),
+        None => self.eof_in_comment(),
+      },
+      State::CommentLessThanSign => match self.input.next() {
+        Some('!') => {
+          self.comment.push('!');
+          self.state = State::CommentLessThanSignBang;
+        },
+        Some('<') => self.comment.push('<'),
+        _ => self.reconsume_in(State::Comment),
+      },
+      State::CommentLessThanSignBang => match self.input.next() {
+        Some('-') => self.state = State::CommentLessThanSignBangDash,
+        _ => self.reconsume_in(State::Comment),
+      },
+      State::CommentLessThanSignBangDash => match self.input.next() {
+        Some('-') => self.state = State::CommentLessThanSignBangDashDash,
+        _ => self.reconsume_in(State::CommentEndDash),
+      },
+      State::CommentLessThanSignBangDashDash => match self.input.next() {
+        Some('>') | None => self.reconsume_in(State::CommentEnd),
+        Some(_) => {
+          self.parse_error(ParseErrorKind::NestedComment);
+          self.reconsume_in(State::CommentEnd);
+        },
+      },
+      State::CommentEndDash => match self.input.next() {
+        Some('-') => self.state = State::CommentEnd,
+        None => self.eof_in_comment(),
+        Some(_) => {
+          self.comment.push('-');
+          self.reconsume_in(State::Comment);
+        },
+      },
+      State::CommentEnd => match self.input.next() {
+        Some('>') => {
+          self.state = State::Data;
+          self.emit_comment();
+        },
+        Some('!') => self.state = State::CommentEndBang,
+        Some('-') => self.comment.push('-'),
+        None => self.eof_in_comment(),
+        Some(_) => {
+          self.comment.push_str("--");
+          self.reconsume_in(State::Comment);
+        },
+      },
+      State::CommentEndBang => match self.input.next() {
+        Some('-') => {
+          self.comment.push_str("--!");
+          self.state = State::CommentEndDash;
+        },
+        Some('>') => {
+          self.parse_error(ParseErrorKind::IncorrectlyClosedComment);
+          self.state = State::Data;
+          self.emit_comment();
+        },
+        None => self.eof_in_comment(),
+        Some(_) => {
+          self.comment.push_str("--!");
+          self.reconsume_in(State::Comment);
+        },
+      },
+      State::Doctype => match self.input.next() {
+        Some(chr) if is_whitespace(chr) => self.state = State::BeforeDoctypeName,
+        Some('>') => self.reconsume_in(State::BeforeDoctypeName),
+        None => {
+          self.parse_error(ParseErrorKind::EofInDoctype);
+          self.doctype = Doctype {
+            force_quirks: true,
+            ..Default::default()
+          };
+          self.emit_doctype();
+          self.emit(Token::Eof);
+        },
+        Some(_) => {
+          self.parse_error(ParseErrorKind::MissingWhitespaceBeforeDoctypeName);
+          self.reconsume_in(State::BeforeDoctypeName);
+        },
+      },
+      State::BeforeDoctypeName => match self.input.next() {
+        Some(chr) if is_whitespace(chr) => (),
+        Some('>') => {
+          self.parse_error(ParseErrorKind::MissingDoctypeName);
+          self.doctype = Doctype {
+            force_quirks: true,
+            ..Default::default()
+          };
+          self.state = State::Data;
+          self.emit_doctype();
+        },
+        None => {
+          self.parse_error(ParseErrorKind::EofInDoctype);
+          self.doctype = Doctype {
+            force_quirks: true,
+            ..Default::default()
+          };
+          self.emit_doctype();
+          self.emit(Token::Eof);
+        },
+        Some(chr) => {
+          let chr = match chr {
+            '\0' => {
+              self.parse_error(ParseErrorKind::UnexpectedNullCharacter);
+              '\u{FFFD}'
+            },
+            chr => chr.to_ascii_lowercase(),
+          };
+          self.doctype = Doctype {
+            name: Some(chr.into()),
+            ..Default::default()
+          };
+          self.state = State::DoctypeName;
+        },
+      },
+      State::DoctypeName => match self.input.next() {
+        Some(chr) if is_whitespace(chr) => self.state = State::AfterDoctypeName,
+        Some('>') => {
+          self.state = State::Data;
+          self.emit_doctype();
+        },
+        Some('\0') => {
+          self.parse_error(ParseErrorKind::UnexpectedNullCharacter);
+          self.doctype.name.get_or_insert_with(String::new).push('\u{FFFD}');
+        },
+        Some(chr) => self.doctype.name.get_or_insert_with(String::new).push(chr.to_ascii_lowercase()),
+        None => self.eof_in_doctype(),
+      },
+      State::AfterDoctypeName => match self.input.next() {
+        Some(chr) if is_whitespace(chr) => (),
+        Some('>') => {
+          self.state = State::Data;
+          self.emit_doctype();
+        },
+        None => self.eof_in_doctype(),
+        Some(_) => {
+          self.input.back();
+          if self.input.eat("PUBLIC", true) {
+            self.state = State::AfterDoctypePublicKeyword;
+          } else if self.input.eat("SYSTEM", true) {
+            self.state = State::AfterDoctypeSystemKeyword;
+          } else {
+            self.parse_error(ParseErrorKind::InvalidCharacterSequenceAfterDoctypeName);
+            self.doctype.force_quirks = true;
+            self.reconsume_in(State::BogusDoctype);
+          }
+        },
+      },
+      State::AfterDoctypePublicKeyword | State::AfterDoctypeSystemKeyword => {
+        let public = self.state == State::AfterDoctypePublicKeyword;
+        match self.input.next() {
+          Some(chr) if is_whitespace(chr) => {
+            self.state = match public {
+              true => State::BeforeDoctypePublicIdentifier,
+              false => State::BeforeDoctypeSystemIdentifier,
+            };
+          },
+          Some(quote @ ('"' | '\'')) => {
+            self.parse_error(match public {
+              true => ParseErrorKind::MissingWhitespaceAfterDoctypePublicKeyword,
+              false => ParseErrorKind::MissingWhitespaceAfterDoctypeSystemKeyword,
+            });
+            self.begin_doctype_identifier(public, quote);
+          },
+          Some('>') => self.missing_doctype_identifier(public),
+          None => self.eof_in_doctype(),
+          Some(_) => self.missing_quote_before_doctype_identifier(public),
+        }
+      },
+      State::BeforeDoctypePublicIdentifier | State::BeforeDoctypeSystemIdentifier => {
+        let public = self.state == State::BeforeDoctypePublicIdentifier;
+        match self.input.next() {
+          Some(chr) if is_whitespace(chr) => (),
+          Some(quote @ ('"' | '\'')) => self.begin_doctype_identifier(public, quote),
+          Some('>') => self.missing_doctype_identifier(public),
+          None => self.eof_in_doctype(),
+          Some(_) => self.missing_quote_before_doctype_identifier(public),
+        }
+      },
+      State::DoctypePublicIdentifierDoubleQuoted | State::DoctypePublicIdentifierSingleQuoted |
+      State::DoctypeSystemIdentifierDoubleQuoted | State::DoctypeSystemIdentifierSingleQuoted => {
+        let public = matches!(self.state, State::DoctypePublicIdentifierDoubleQuoted | State::DoctypePublicIdentifierSingleQuoted);
+        let quote = match self.state {
+          State::DoctypePublicIdentifierDoubleQuoted | State::DoctypeSystemIdentifierDoubleQuoted => '"',
+          _ => '\'',
+        };
+        let identifier = match public {
+          true => &mut self.doctype.public_id,
+          false => &mut self.doctype.system_id,
+        };
+        match self.input.next() {
+          Some(chr) if chr == quote => {
+            self.state = match public {
+              true => State::AfterDoctypePublicIdentifier,
+              false => State::AfterDoctypeSystemIdentifier,
+            };
+          },
+          Some('\0') => {
+            identifier.get_or_insert_with(String::new).push('\u{FFFD}');
+            self.parse_error(ParseErrorKind::UnexpectedNullCharacter);
+          },
+          Some('>') => {
+            self.parse_error(match public {
+              true => ParseErrorKind::AbruptDoctypePublicIdentifier,
+              false => ParseErrorKind::AbruptDoctypeSystemIdentifier,
+            });
+            self.doctype.force_quirks = true;
+            self.state = State::Data;
+            self.emit_doctype();
+          },
+          Some(chr) => identifier.get_or_insert_with(String::new).push(chr),
+          None => self.eof_in_doctype(),
+        }
+      },
+      State::AfterDoctypePublicIdentifier | State::BetweenDoctypePublicAndSystemIdentifiers => {
+        let between = self.state == State::BetweenDoctypePublicAndSystemIdentifiers;
+        match self.input.next() {
+          Some(chr) if is_whitespace(chr) => self.state = State::BetweenDoctypePublicAndSystemIdentifiers,
+          Some('>') => {
+            self.state = State::Data;
+            self.emit_doctype();
+          },
+          Some(quote @ ('"' | '\'')) => {
+            if !between {
+              self.parse_error(ParseErrorKind::MissingWhitespaceBetweenDoctypePublicAndSystemIdentifiers);
+            }
+            self.begin_doctype_identifier(false, quote);
+          },
+          None => self.eof_in_doctype(),
+          Some(_) => self.missing_quote_before_doctype_identifier(false),
+        }
+      },
+      State::AfterDoctypeSystemIdentifier => match self.input.next() {
+        Some(chr) if is_whitespace(chr) => (),
+        Some('>') => {
+          self.state = State::Data;
+          self.emit_doctype();
+        },
+        None => self.eof_in_doctype(),
+        Some(_) => {
+          self.parse_error(ParseErrorKind::UnexpectedCharacterAfterDoctypeSystemIdentifier);
+          self.reconsume_in(State::BogusDoctype);
+        },
+      },
+      State::BogusDoctype => match self.input.next() {
+        Some('>') => {
+          self.state = State::Data;
+          self.emit_doctype();
+        },
+        Some('\0') => self.parse_error(ParseErrorKind::UnexpectedNullCharacter),
+        Some(_) => (),
+        None => {
+          self.emit_doctype();
+          self.emit(Token::Eof);
+        },
+      },
+      State::CdataSection => match self.input.next() {
+        Some(']') => self.state = State::CdataSectionBracket,
+        Some(chr) => self.emit(Token::Character(chr)),
+        None => {
+          self.parse_error(ParseErrorKind::EofInCdata);
+          self.emit(Token::Eof);
+        },
+      },
+      State::CdataSectionBracket => match self.input.next() {
+        Some(']') => self.state = State::CdataSectionEnd,
+        _ => {
+          self.emit(Token::Character(']'));
+          self.reconsume_in(State::CdataSection);
+        },
+      },
+      State::CdataSectionEnd => match self.input.next() {
+        Some(']') => self.emit(Token::Character(']')),
+        Some('>') => self.state = State::Data,
+        _ => {
+          self.emit(Token::Character(']'));
+          self.emit(Token::Character(']'));
+          self.reconsume_in(State::CdataSection);
+        },
+      },
+      State::CharacterReference => match self.input.next() {
+        Some(chr) if chr.is_ascii_alphanumeric() => self.reconsume_in(State::NamedCharacterReference),
+        Some('#') => {
+          self.temp.push('#');
+          self.state = State::NumericCharacterReference;
+        },
+        _ => {
+          self.flush_character_reference();
+          self.reconsume_in(self.return_state);
+        },
+      },
+      State::NamedCharacterReference => {
+        match self.match_named_character_reference() {
+          Some((name, value)) => {
+            let next = self.input.rest()[name.len()..].chars().next();
+            self.input.eat(name, false);
+            self.temp.push_str(name);
+            // Historical quirk: `&amp=` and `&ampx` are left alone in attribute values
+            if self.in_attribute() && !name.ends_with(';') && next.is_some_and(|x| x == '=' || x.is_ascii_alphanumeric()) {
+              self.flush_character_reference();
+            } else {
+              if !name.ends_with(';') {
+                self.parse_error(ParseErrorKind::MissingSemicolonAfterCharacterReference);
+              }
+              self.temp = value.into();
+              self.flush_character_reference();
+            }
+            self.state = self.return_state;
+          },
+          None => {
+            self.flush_character_reference();
+            self.state = State::AmbiguousAmpersand;
+          },
+        }
+      },
+      State::AmbiguousAmpersand => match self.input.next() {
+        Some(chr) if chr.is_ascii_alphanumeric() => {
+          if self.in_attribute() {
+            self.attribute_value().push(chr);
+          } else {
+            self.emit(Token::Character(chr));
+          }
+        },
+        Some(';') => {
+          self.parse_error(ParseErrorKind::UnknownNamedCharacterReference);
+          self.reconsume_in(self.return_state);
+        },
+        _ => self.reconsume_in(self.return_state),
+      },
+      State::NumericCharacterReference => {
+        self.char_ref_code = 0;
+        match self.input.next() {
+          Some(chr @ ('x' | 'X')) => {
+            self.temp.push(chr);
+            self.state = State::HexadecimalCharacterReferenceStart;
+          },
+          _ => self.reconsume_in(State::DecimalCharacterReferenceStart),
+        }
+      },
+      State::HexadecimalCharacterReferenceStart | State::DecimalCharacterReferenceStart => {
+        let (radix, next_state) = match self.state {
+          State::HexadecimalCharacterReferenceStart => (16, State::HexadecimalCharacterReference),
+          _ => (10, State::DecimalCharacterReference),
+        };
+        match self.input.next() {
+          Some(chr) if chr.is_digit(radix) => self.reconsume_in(next_state),
+          _ => {
+            self.parse_error(ParseErrorKind::AbsenceOfDigitsInNumericCharacterReference);
+            self.flush_character_reference();
+            self.reconsume_in(self.return_state);
+          },
+        }
+      },
+      State::HexadecimalCharacterReference | State::DecimalCharacterReference => {
+        let radix = match self.state {
+          State::HexadecimalCharacterReference => 16,
+          _ => 10,
+        };
+        match self.input.next() {
+          Some(chr) if chr.is_digit(radix) => {
+            // Saturate, anything above 0x10FFFF is an error anyway
+            self.char_ref_code = (self.char_ref_code * radix + chr.to_digit(radix).unwrap()).min(0x110000);
+          },
+          Some(';') => self.state = State::NumericCharacterReferenceEnd,
+          _ => {
+            self.parse_error(ParseErrorKind::MissingSemicolonAfterCharacterReference);
+            self.reconsume_in(State::NumericCharacterReferenceEnd);
+          },
+        }
+      },
+      State::NumericCharacterReferenceEnd => {
+        let chr = self.numeric_character_reference_value();
+        self.temp.clear();
+        self.temp.push(chr);
+        self.flush_character_reference();
+        self.state = self.return_state;
+      },
+    }
+    true
+  }
+
+  fn begin_character_reference(&mut self, return_state: State) {
+    self.return_state = return_state;
+    self.temp.clear();
+    self.temp.push('&');
+    self.state = State::CharacterReference;
+  }
+
+  fn in_attribute(&self) -> bool {
+    matches!(
+      self.return_state,
+      State::AttributeValueDoubleQuoted | State::AttributeValueSingleQuoted | State::AttributeValueUnquoted
+    )
+  }
+
+  fn flush_character_reference(&mut self) {
+    let temp = std::mem::take(&mut self.temp);
+    if self.in_attribute() {
+      self.attribute_value().push_str(&temp);
+    } else {
+      self.emit_str(&temp);
+    }
+  }
+
+  /// Find the longest named character reference the input starts with
+  fn match_named_character_reference(&self) -> Option<(&'static str, &'static str)> {
+    let rest = self.input.rest();
+    let mut len = rest.bytes().take_while(|x| x.is_ascii_alphanumeric()).count();
+    if rest[len..].starts_with(';') {
+      len += 1;
+    }
+    (1..=len).rev().find_map(|len| {
+      let index = NAMED_CHARACTER_REFERENCES.binary_search_by(|(name, _)| name.cmp(&&rest[..len])).ok()?;
+      Some(NAMED_CHARACTER_REFERENCES[index])
+    })
+  }
+
+  fn numeric_character_reference_value(&mut self) -> char {
+    // Windows-1252 mappings for the C1 control range
+    const C1_REPLACEMENTS: [u32; 32] = [
+      0x20AC, 0x81, 0x201A, 0x0192, 0x201E, 0x2026, 0x2020, 0x2021, 0x02C6, 0x2030, 0x0160, 0x2039, 0x0152, 0x8D, 0x017D, 0x8F,
+      0x90, 0x2018, 0x2019, 0x201C, 0x201D, 0x2022, 0x2013, 0x2014, 0x02DC, 0x2122, 0x0161, 0x203A, 0x0153, 0x9D, 0x017E, 0x0178,
+    ];
+    let code = self.char_ref_code;
+    match code {
+      0 => {
+        self.parse_error(ParseErrorKind::NullCharacterReference);
+        '\u{FFFD}'
+      },
+      0x110000.. => {
+        self.parse_error(ParseErrorKind::CharacterReferenceOutsideUnicodeRange);
+        '\u{FFFD}'
+      },
+      0xD800..=0xDFFF => {
+        self.parse_error(ParseErrorKind::SurrogateCharacterReference);
+        '\u{FFFD}'
+      },
+      _ => {
+        if (0xFDD0..=0xFDEF).contains(&code) || (code & 0xFFFE) == 0xFFFE {
+          self.parse_error(ParseErrorKind::NoncharacterCharacterReference);
+        }
+        let is_control = (code < 0x20 && !matches!(code, 0x09 | 0x0A | 0x0C)) || (0x7F..=0x9F).contains(&code);
+        if is_control {
+          self.parse_error(ParseErrorKind::ControlCharacterReference);
+        }
+        let code = match code {
+          0x80..=0x9F => C1_REPLACEMENTS[(code - 0x80) as usize],
+          _ => code,
+        };
+        char::from_u32(code).unwrap()
+      },
+    }
+  }
+
+  fn eof_in_comment(&mut self) {
+    self.parse_error(ParseErrorKind::EofInComment);
+    self.emit_comment();
+    self.emit(Token::Eof);
+  }
+
+  /// Start a public or system identifier quoted with `quote`
+  fn begin_doctype_identifier(&mut self, public: bool, quote: char) {
+    let (identifier, state) = match (public, quote) {
+      (true, '"') => (&mut self.doctype.public_id, State::DoctypePublicIdentifierDoubleQuoted),
+      (true, _) => (&mut self.doctype.public_id, State::DoctypePublicIdentifierSingleQuoted),
+      (false, '"') => (&mut self.doctype.system_id, State::DoctypeSystemIdentifierDoubleQuoted),
+      (false, _) => (&mut self.doctype.system_id, State::DoctypeSystemIdentifierSingleQuoted),
+    };
+    *identifier = Some(String::new());
+    self.state = state;
+  }
+
+  fn missing_doctype_identifier(&mut self, public: bool) {
+    self.parse_error(match public {
+      true => ParseErrorKind::MissingDoctypePublicIdentifier,
+      false => ParseErrorKind::MissingDoctypeSystemIdentifier,
+    });
+    self.doctype.force_quirks = true;
+    self.state = State::Data;
+    self.emit_doctype();
+  }
+
+  fn missing_quote_before_doctype_identifier(&mut self, public: bool) {
+    self.parse_error(match public {
+      true => ParseErrorKind::MissingQuoteBeforeDoctypePublicIdentifier,
+      false => ParseErrorKind::MissingQuoteBeforeDoctypeSystemIdentifier,
+    });
+    self.doctype.force_quirks = true;
+    self.reconsume_in(State::BogusDoctype);
+  }
+
+  fn eof_in_doctype(&mut self) {
+    self.parse_error(ParseErrorKind::EofInDoctype);
+    self.doctype.force_quirks = true;
+    self.emit_doctype();
+    self.emit(Token::Eof);
+  }
+
+  /// RCDATA/RAWTEXT less-than sign state
+  fn text_less_than_sign(&mut self, text_state: State, end_tag_open_state: State) {
+    match self.input.next() {
+      Some('/') => {
+        self.temp.clear();
+        self.state = end_tag_open_state;
+      },
+      _ => {
+        self.emit(Token::Character('<'));
+        self.reconsume_in(text_state);
+      },
+    }
+  }
+
+  /// RCDATA/RAWTEXT/script data end tag open state
+  fn text_end_tag_open(&mut self, text_state: State, end_tag_name_state: State) {
+    match self.input.next() {
+      Some(chr) if chr.is_ascii_alphabetic() => {
+        self.new_tag(true);
+        self.reconsume_in(end_tag_name_state);
+      },
+      _ => {
+        self.emit_str("</");
+        self.reconsume_in(text_state);
+      },
+    }
+  }
+
+  /// RCDATA/RAWTEXT/script data end tag name state
+  fn text_end_tag_name(&mut self, text_state: State) {
+    match self.input.next() {
+      Some(chr) if is_whitespace(chr) && self.is_appropriate_end_tag() => {
+        self.state = State::BeforeAttributeName;
+      },
+      Some('/') if self.is_appropriate_end_tag() => {
+        self.state = State::SelfClosingStartTag;
+      },
+      Some('>') if self.is_appropriate_end_tag() => {
+        self.state = State::Data;
+        self.emit_tag();
+      },
+      Some(chr) if chr.is_ascii_alphabetic() => {
+        self.tag.name.push(chr.to_ascii_lowercase());
+        self.temp.push(chr);
+      },
+      _ => {
+        self.emit_str("</");
+        let temp = std::mem::take(&mut self.temp);
+        self.emit_str(&temp);
+        self.reconsume_in(text_state);
+      },
+    }
+  }
+}
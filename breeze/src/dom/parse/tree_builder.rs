@@ -0,0 +1,2266 @@
+//! HTML tree construction, see <https://html.spec.whatwg.org/multipage/parsing.html#tree-construction>
+
+use std::ops::Range;
+use rustc_hash::FxHashMap;
+use super::{foreign, quirks, tokenizer::{Comment, State, Tag, Token}};
+use crate::dom::{
+  Dom, Node, NodeId, NodeRef, ElementNode, TextNode, CommentNode, DoctypeNode, ProcessingInstructionNode,
+  DocumentMode, Namespace, ParseErrorKind, ElementSource, SourceSpan, AttributeMap,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum InsertionMode {
+  Initial,
+  BeforeHtml,
+  BeforeHead,
+  InHead,
+  InHeadNoscript,
+  AfterHead,
+  InBody,
+  Text,
+  InTable,
+  InTableText,
+  InCaption,
+  InColumnGroup,
+  InTableBody,
+  InRow,
+  InCell,
+  InSelect,
+  InSelectInTable,
+  InTemplate,
+  AfterBody,
+  InFrameset,
+  AfterFrameset,
+  AfterAfterBody,
+  AfterAfterFrameset,
+}
+
+enum FormattingEntry {
+  Marker,
+  Element(NodeId, Tag),
+}
+
+/// Stack of open elements, which also counts the HTML elements on it by tag name
+///
+/// Checking if an element is in scope walks down the stack, knowing that it
+/// isn't on the stack at all keeps deeply nested documents from taking quadratic time.
+#[derive(Default)]
+struct OpenElements {
+  nodes: Vec<NodeId>,
+  counts: FxHashMap<String, usize>,
+}
+
+impl OpenElements {
+  fn count(&mut self, dom: &Dom, node: NodeId, added: bool) {
+    let Some(name) = html_tag_name(&dom[node]) else { return };
+    if added {
+      *self.counts.entry(name.into()).or_default() += 1;
+    } else if let Some(count) = self.counts.get_mut(name) {
+      *count -= 1;
+      if *count == 0 {
+        self.counts.remove(name);
+      }
+    }
+  }
+
+  /// Check if there's an HTML element with the tag name anywhere on the stack
+  fn contains_name(&self, name: &str) -> bool {
+    self.counts.contains_key(name)
+  }
+
+  fn push(&mut self, dom: &Dom, node: NodeId) {
+    self.count(dom, node, true);
+    self.nodes.push(node);
+  }
+
+  fn pop(&mut self, dom: &Dom) -> Option<NodeId> {
+    let node = self.nodes.pop()?;
+    self.count(dom, node, false);
+    Some(node)
+  }
+
+  fn insert(&mut self, dom: &Dom, index: usize, node: NodeId) {
+    self.count(dom, node, true);
+    self.nodes.insert(index, node);
+  }
+
+  fn remove(&mut self, dom: &Dom, index: usize) -> NodeId {
+    let node = self.nodes.remove(index);
+    self.count(dom, node, false);
+    node
+  }
+
+  fn replace(&mut self, dom: &Dom, index: usize, node: NodeId) {
+    self.count(dom, node, true);
+    let old = std::mem::replace(&mut self.nodes[index], node);
+    self.count(dom, old, false);
+  }
+
+  fn truncate(&mut self, dom: &Dom, length: usize) {
+    while self.nodes.len() > length {
+      self.pop(dom);
+    }
+  }
+
+  fn clear(&mut self) {
+    self.nodes.clear();
+    self.counts.clear();
+  }
+}
+
+impl std::ops::Deref for OpenElements {
+  type Target = [NodeId];
+  fn deref(&self) -> &[NodeId] {
+    &self.nodes
+  }
+}
+
+/// Where a new node should be inserted
+struct InsertionPoint {
+  parent: NodeId,
+  before: Option<NodeId>,
+}
+
+const SPECIAL: &[&str] = &[
+  "address", "applet", "area", "article", "aside", "base", "basefont", "bgsound", "blockquote", "body", "br",
+  "button", "caption", "center", "col", "colgroup", "dd", "details", "dir", "div", "dl", "dt", "embed",
+  "fieldset", "figcaption", "figure", "footer", "form", "frame", "frameset", "h1", "h2", "h3", "h4", "h5",
+  "h6", "head", "header", "hgroup", "hr", "html", "iframe", "img", "input", "keygen", "li", "link", "listing",
+  "main", "marquee", "menu", "meta", "nav", "noembed", "noframes", "noscript", "object", "ol", "p", "param",
+  "plaintext", "pre", "script", "search", "section", "select", "source", "style", "summary", "table", "tbody",
+  "td", "template", "textarea", "tfoot", "th", "thead", "title", "tr", "track", "ul", "wbr", "xmp",
+];
+
+const IMPLIED_END_TAGS: &[&str] = &["dd", "dt", "li", "optgroup", "option", "p", "rb", "rp", "rt", "rtc"];
+
+const IMPLIED_END_TAGS_THOROUGH: &[&str] = &[
+  "caption", "colgroup", "dd", "dt", "li", "optgroup", "option", "p", "rb", "rp", "rt", "rtc", "tbody", "td",
+  "tfoot", "th", "thead", "tr",
+];
+
+const HEADINGS: &[&str] = &["h1", "h2", "h3", "h4", "h5", "h6"];
+
+const DEFAULT_SCOPE: &[&str] = &["applet", "caption", "html", "table", "td", "th", "marquee", "object", "template"];
+
+const MATHML_TEXT_INTEGRATION_POINTS: &[&str] = &["mi", "mo", "mn", "ms", "mtext"];
+
+const MATHML_SPECIAL: &[&str] = &["mi", "mo", "mn", "ms", "mtext", "annotation-xml"];
+
+const SVG_HTML_INTEGRATION_POINTS: &[&str] = &["foreignObject", "desc", "title"];
+
+/// Start tags that break out of foreign content
+const FOREIGN_BREAKOUT: &[&str] = &[
+  "b", "big", "blockquote", "body", "br", "center", "code", "dd", "div", "dl", "dt", "em", "embed", "h1", "h2",
+  "h3", "h4", "h5", "h6", "head", "hr", "i", "img", "li", "listing", "menu", "meta", "nobr", "ol", "p", "pre",
+  "ruby", "s", "small", "span", "strong", "strike", "sub", "sup", "table", "tt", "u", "ul", "var",
+];
+
+#[derive(Clone, Copy)]
+enum Scope {
+  Default,
+  ListItem,
+  Button,
+  Table,
+  Select,
+}
+
+fn is_whitespace(chr: char) -> bool {
+  matches!(chr, '\t' | '\n' | '\x0C' | '\r' | ' ')
+}
+
+fn namespace(node: &Node) -> Option<Namespace> {
+  match node {
+    Node::Element(element) => Some(element.namespace.clone()),
+    _ => None,
+  }
+}
+
+/// Check if the node is an element in `namespace` with one of the given tag names
+fn is_in(node: &Node, namespace: Namespace, names: &[&str]) -> bool {
+  match node {
+    Node::Element(element) => element.namespace == namespace && names.contains(&element.tag_name.as_str()),
+    _ => false,
+  }
+}
+
+/// Check if the node is an HTML element with one of the given tag names
+fn is_one_of(node: &Node, names: &[&str]) -> bool {
+  is_in(node, Namespace::Html, names)
+}
+
+fn is(node: &Node, name: &str) -> bool {
+  is_one_of(node, &[name])
+}
+
+fn is_special(node: &Node) -> bool {
+  is_one_of(node, SPECIAL) ||
+    is_in(node, Namespace::MathMl, MATHML_SPECIAL) ||
+    is_in(node, Namespace::Svg, SVG_HTML_INTEGRATION_POINTS)
+}
+
+/// Elements that limit the default scope
+fn is_scope_boundary(node: &Node) -> bool {
+  is_one_of(node, DEFAULT_SCOPE) ||
+    is_in(node, Namespace::MathMl, MATHML_SPECIAL) ||
+    is_in(node, Namespace::Svg, SVG_HTML_INTEGRATION_POINTS)
+}
+
+fn is_mathml_text_integration_point(node: &Node) -> bool {
+  is_in(node, Namespace::MathMl, MATHML_TEXT_INTEGRATION_POINTS)
+}
+
+fn is_html_integration_point(node: &Node) -> bool {
+  match node {
+    Node::Element(element) => match element.namespace {
+      Namespace::MathMl => element.tag_name == "annotation-xml" && element.attribute("encoding").is_some_and(|x| {
+        x.eq_ignore_ascii_case("text/html") || x.eq_ignore_ascii_case("application/xhtml+xml")
+      }),
+      Namespace::Svg => SVG_HTML_INTEGRATION_POINTS.contains(&element.tag_name.as_str()),
+      _ => false,
+    },
+    _ => false,
+  }
+}
+
+/// Tag name of an HTML element
+fn html_tag_name(node: &Node) -> Option<&str> {
+  match node {
+    Node::Element(element) if element.namespace == Namespace::Html => Some(&element.tag_name),
+    _ => None,
+  }
+}
+
+pub(crate) struct TreeBuilder {
+  pub dom: Dom,
+  mode: InsertionMode,
+  original_mode: InsertionMode,
+  template_modes: Vec<InsertionMode>,
+  open_elements: OpenElements,
+  active_formatting: Vec<FormattingEntry>,
+  head: Option<NodeId>,
+  form: Option<NodeId>,
+  frameset_ok: bool,
+  foster_parenting: bool,
+  pending_table_text: Vec<(char, SourceSpan)>,
+  self_closing_acknowledged: bool,
+  /// Drop a newline right after `<pre>`, `<listing>` or `<textarea>`
+  ignore_newline: bool,
+  tokenizer_state: Option<State>,
+  pub document_mode: DocumentMode,
+  /// Context element when parsing a fragment
+  context: Option<NodeId>,
+  /// Source location of the token being processed
+  span: SourceSpan,
+  /// Name of the end tag being processed, the first element it closes gets its position
+  end_tag: Option<String>,
+  /// The end-of-file token has to be processed again in the current insertion mode
+  reprocess_eof: bool,
+  pub errors: Vec<(ParseErrorKind, Range<usize>)>,
+}
+
+impl TreeBuilder {
+  pub fn new() -> Self {
+    Self {
+      dom: Dom::new(),
+      mode: InsertionMode::Initial,
+      original_mode: InsertionMode::Initial,
+      template_modes: Vec::new(),
+      open_elements: OpenElements::default(),
+      active_formatting: Vec::new(),
+      head: None,
+      form: None,
+      frameset_ok: true,
+      foster_parenting: false,
+      pending_table_text: Vec::new(),
+      self_closing_acknowledged: false,
+      ignore_newline: false,
+      tokenizer_state: None,
+      document_mode: DocumentMode::NoQuirks,
+      context: None,
+      span: SourceSpan::default(),
+      end_tag: None,
+      reprocess_eof: false,
+      errors: Vec::new(),
+    }
+  }
+
+  /// Set up the HTML fragment parsing algorithm, with `context` as the context element
+  ///
+  /// The context element is in another document, a detached copy of it (and
+  /// of its nearest form ancestor) takes its place.
+  pub fn new_fragment(context: NodeRef) -> Self {
+    let mut this = Self::new();
+    this.tokenizer_state = Some(match html_tag_name(&context) {
+      Some("title" | "textarea") => State::Rcdata,
+      Some("style" | "xmp" | "iframe" | "noembed" | "noframes") => State::Rawtext,
+      Some("script") => State::ScriptData,
+      Some("plaintext") => State::Plaintext,
+      _ => State::Data,
+    });
+    let root = this.create_element(&Tag::new("html"), Namespace::Html);
+    this.dom.link_child(this.dom.tree, root);
+    this.open_elements.push(&this.dom, root);
+    if is(&context, "template") {
+      this.template_modes.push(InsertionMode::InTemplate);
+    }
+    let form = std::iter::successors(Some(context), |x| x.parent()).find(|x| is(x, "form"));
+    this.form = form.map(|x| this.copy_element(x));
+    this.context = Some(this.copy_element(context));
+    this.reset_insertion_mode();
+    this
+  }
+
+  /// Detached copy of an element from another document, without its children
+  fn copy_element(&mut self, node: NodeRef) -> NodeId {
+    let copy = match node.value() {
+      Node::Element(element) => {
+        let mut copy = ElementNode::new_with_tag_and_attributes(element.tag_name.clone(), element.attributes.clone());
+        copy.attribute_namespaces = element.attribute_namespaces.clone();
+        copy.namespace = element.namespace.clone();
+        copy.xml = element.xml;
+        copy
+      },
+      //anything else, like a document fragment, parses like the contents of body
+      _ => ElementNode::new_with_tag("body".into()),
+    };
+    self.dom.create_node(Node::Element(copy))
+  }
+
+  /// Tokenizer state switch requested by the last processed token
+  pub fn take_tokenizer_state(&mut self) -> Option<State> {
+    self.tokenizer_state.take()
+  }
+
+  pub fn process(&mut self, token: Token, span: SourceSpan) {
+    self.span = span;
+    self.end_tag = match &token {
+      Token::EndTag(tag) => Some(tag.name.clone()),
+      _ => None,
+    };
+    if std::mem::take(&mut self.ignore_newline) && matches!(token, Token::Character('\n')) {
+      return
+    }
+    let self_closing = matches!(&token, Token::StartTag(tag) if tag.self_closing);
+    self.self_closing_acknowledged = false;
+    match self.is_foreign_token(&token) {
+      true => self.foreign_content(token),
+      false => self.process_in(self.mode, token),
+    }
+    while std::mem::take(&mut self.reprocess_eof) {
+      self.process_in(self.mode, Token::Eof);
+    }
+    if self_closing && !self.self_closing_acknowledged {
+      self.parse_error(ParseErrorKind::NonVoidHtmlElementStartTagWithTrailingSolidus);
+    }
+  }
+
+  fn parse_error(&mut self, kind: ParseErrorKind) {
+    self.errors.push((kind, self.span.range.clone()));
+  }
+
+  fn process_in(&mut self, mode: InsertionMode, token: Token) {
+    match mode {
+      InsertionMode::Initial => self.initial(token),
+      InsertionMode::BeforeHtml => self.before_html(token),
+      InsertionMode::BeforeHead => self.before_head(token),
+      InsertionMode::InHead => self.in_head(token),
+      InsertionMode::InHeadNoscript => self.in_head_noscript(token),
+      InsertionMode::AfterHead => self.after_head(token),
+      InsertionMode::InBody => self.in_body(token),
+      InsertionMode::Text => self.text(token),
+      InsertionMode::InTable => self.in_table(token),
+      InsertionMode::InTableText => self.in_table_text(token),
+      InsertionMode::InCaption => self.in_caption(token),
+      InsertionMode::InColumnGroup => self.in_column_group(token),
+      InsertionMode::InTableBody => self.in_table_body(token),
+      InsertionMode::InRow => self.in_row(token),
+      InsertionMode::InCell => self.in_cell(token),
+      InsertionMode::InSelect => self.in_select(token),
+      InsertionMode::InSelectInTable => self.in_select_in_table(token),
+      InsertionMode::InTemplate => self.in_template(token),
+      InsertionMode::AfterBody => self.after_body(token),
+      InsertionMode::InFrameset => self.in_frameset(token),
+      InsertionMode::AfterFrameset => self.after_frameset(token),
+      InsertionMode::AfterAfterBody => self.after_after_body(token),
+      InsertionMode::AfterAfterFrameset => self.after_after_frameset(token),
+    }
+  }
+
+  /// Switch to `mode` and reprocess the token
+  fn reprocess(&mut self, mode: InsertionMode, token: Token) {
+    self.mode = mode;
+    self.process_in(mode, token);
+  }
+
+  // ---- stack of open elements ----
+
+  fn current_node(&self) -> NodeId {
+    *self.open_elements.last().expect("stack of open elements is empty")
+  }
+
+  /// The context element stands in for the html element when parsing a fragment
+  fn adjusted_current_node(&self) -> Option<NodeId> {
+    match self.context {
+      Some(context) if self.open_elements.len() == 1 => Some(context),
+      _ => self.open_elements.last().copied(),
+    }
+  }
+
+  /// Whether the adjusted current node is an SVG or MathML element
+  pub fn in_foreign_element(&self) -> bool {
+    self.adjusted_current_node().and_then(|x| namespace(&self.dom[x])).is_some_and(|x| x != Namespace::Html)
+  }
+
+  /// Whether the token should be handled by the rules for foreign content
+  fn is_foreign_token(&self, token: &Token) -> bool {
+    if !self.in_foreign_element() {
+      return false
+    }
+    let node = &self.dom[self.adjusted_current_node().unwrap()];
+    let start_tag = match token {
+      Token::StartTag(tag) => Some(tag.name.as_str()),
+      _ => None,
+    };
+    if is_mathml_text_integration_point(node) &&
+      (matches!(token, Token::Character(_)) || start_tag.is_some_and(|x| x != "mglyph" && x != "malignmark"))
+    {
+      return false
+    }
+    if is_in(node, Namespace::MathMl, &["annotation-xml"]) && start_tag == Some("svg") {
+      return false
+    }
+    if is_html_integration_point(node) && (matches!(token, Token::Character(_)) || start_tag.is_some()) {
+      return false
+    }
+    !matches!(token, Token::Eof)
+  }
+
+  fn current_node_is(&self, name: &str) -> bool {
+    self.open_elements.last().is_some_and(|&x| is(&self.dom[x], name))
+  }
+
+  fn current_node_is_one_of(&self, names: &[&str]) -> bool {
+    self.open_elements.last().is_some_and(|&x| is_one_of(&self.dom[x], names))
+  }
+
+  fn pop(&mut self) -> Option<NodeId> {
+    let node = self.open_elements.pop(&self.dom)?;
+    self.record_end_tag(node);
+    Some(node)
+  }
+
+  /// Remember where the element was closed, if it's being closed by its own end tag
+  fn record_end_tag(&mut self, node: NodeId) {
+    let Node::Element(element) = &mut self.dom[node] else { return };
+    let Some(source) = &mut element.source else { return };
+    if self.end_tag.as_ref().is_some_and(|x| x.eq_ignore_ascii_case(&element.tag_name)) {
+      self.end_tag = None;
+      source.end_tag = Some(self.span.clone());
+    }
+  }
+
+  /// Pop elements until an element with one of the tag names has been popped
+  fn pop_until_one_of(&mut self, names: &[&str]) {
+    while let Some(node) = self.pop() {
+      if is_one_of(&self.dom[node], names) {
+        break
+      }
+    }
+  }
+
+  fn pop_until(&mut self, name: &str) {
+    self.pop_until_one_of(&[name]);
+  }
+
+  fn pop_until_node(&mut self, target: NodeId) {
+    while let Some(node) = self.pop() {
+      if node == target {
+        break
+      }
+    }
+  }
+
+  fn remove_from_stack(&mut self, node: NodeId) {
+    if let Some(index) = self.open_elements.iter().position(|&x| x == node) {
+      self.open_elements.remove(&self.dom, index);
+      self.record_end_tag(node);
+    }
+  }
+
+  fn stack_contains(&self, name: &str) -> bool {
+    self.open_elements.contains_name(name)
+  }
+
+  fn in_scope_one_of(&self, names: &[&str], scope: Scope) -> bool {
+    if !names.iter().any(|x| self.open_elements.contains_name(x)) {
+      return false
+    }
+    for &node in self.open_elements.iter().rev() {
+      let node = &self.dom[node];
+      if is_one_of(node, names) {
+        return true
+      }
+      let boundary = match scope {
+        Scope::Default => is_scope_boundary(node),
+        Scope::ListItem => is_scope_boundary(node) || is_one_of(node, &["ol", "ul"]),
+        Scope::Button => is_scope_boundary(node) || is(node, "button"),
+        Scope::Table => is_one_of(node, &["html", "table", "template"]),
+        Scope::Select => !is_one_of(node, &["optgroup", "option"]),
+      };
+      if boundary {
+        return false
+      }
+    }
+    false
+  }
+
+  fn in_scope(&self, name: &str, scope: Scope) -> bool {
+    self.in_scope_one_of(&[name], scope)
+  }
+
+  fn node_in_scope(&self, target: NodeId) -> bool {
+    for &node in self.open_elements.iter().rev() {
+      if node == target {
+        return true
+      }
+      if is_scope_boundary(&self.dom[node]) {
+        return false
+      }
+    }
+    false
+  }
+
+  fn generate_implied_end_tags_except(&mut self, except: Option<&str>) {
+    while self.current_node_is_one_of(IMPLIED_END_TAGS) && !except.is_some_and(|x| self.current_node_is(x)) {
+      self.pop();
+    }
+  }
+
+  fn generate_implied_end_tags(&mut self) {
+    self.generate_implied_end_tags_except(None);
+  }
+
+  fn generate_implied_end_tags_thoroughly(&mut self) {
+    while self.current_node_is_one_of(IMPLIED_END_TAGS_THOROUGH) {
+      self.pop();
+    }
+  }
+
+  fn close_p_element(&mut self) {
+    self.generate_implied_end_tags_except(Some("p"));
+    if !self.current_node_is("p") {
+      self.parse_error(ParseErrorKind::UnexpectedEndTag);
+    }
+    self.pop_until("p");
+  }
+
+  fn close_p_if_in_button_scope(&mut self) {
+    if self.in_scope("p", Scope::Button) {
+      self.close_p_element();
+    }
+  }
+
+  fn clear_stack_back_to(&mut self, names: &[&str]) {
+    while !self.current_node_is_one_of(names) {
+      self.pop();
+    }
+  }
+
+  fn reset_insertion_mode(&mut self) {
+    for (index, &node) in self.open_elements.iter().enumerate().rev() {
+      let last = index == 0;
+      let node = match self.context {
+        Some(context) if last => context,
+        _ => node,
+      };
+      let name = html_tag_name(&self.dom[node]).unwrap_or_default();
+      self.mode = match name {
+        "select" => {
+          if !last {
+            for &ancestor in self.open_elements[..index].iter().rev() {
+              if is(&self.dom[ancestor], "template") {
+                break
+              }
+              if is(&self.dom[ancestor], "table") {
+                self.mode = InsertionMode::InSelectInTable;
+                return
+              }
+            }
+          }
+          InsertionMode::InSelect
+        },
+        "td" | "th" if !last => InsertionMode::InCell,
+        "tr" => InsertionMode::InRow,
+        "tbody" | "thead" | "tfoot" => InsertionMode::InTableBody,
+        "caption" => InsertionMode::InCaption,
+        "colgroup" => InsertionMode::InColumnGroup,
+        "table" => InsertionMode::InTable,
+        "template" => *self.template_modes.last().unwrap(),
+        "head" if !last => InsertionMode::InHead,
+        "body" => InsertionMode::InBody,
+        "frameset" => InsertionMode::InFrameset,
+        "html" => match self.head {
+          None => InsertionMode::BeforeHead,
+          Some(_) => InsertionMode::AfterHead,
+        },
+        _ if last => InsertionMode::InBody,
+        _ => continue,
+      };
+      return
+    }
+  }
+
+  // ---- list of active formatting elements ----
+
+  fn push_formatting_element(&mut self, node: NodeId, mut tag: Tag) {
+    //elements recreated from the tag don't come from the source
+    tag.source = None;
+    // Noah's Ark clause
+    let mut matching = Vec::new();
+    for (index, entry) in self.active_formatting.iter().enumerate().rev() {
+      match entry {
+        FormattingEntry::Marker => break,
+        FormattingEntry::Element(_, other) => {
+          if other.name == tag.name
+            && other.attributes.len() == tag.attributes.len()
+            && other.attributes.iter().all(|x| tag.attributes.contains(x))
+          {
+            matching.push(index);
+          }
+        },
+      }
+    }
+    if matching.len() >= 3 {
+      self.active_formatting.remove(*matching.last().unwrap());
+    }
+    self.active_formatting.push(FormattingEntry::Element(node, tag));
+  }
+
+  fn formatting_position(&self, node: NodeId) -> Option<usize> {
+    self.active_formatting.iter().position(|x| matches!(x, FormattingEntry::Element(element, _) if *element == node))
+  }
+
+  fn reconstruct_active_formatting_elements(&mut self) {
+    let Some(last) = self.active_formatting.last() else { return };
+    let is_open = |entry: &FormattingEntry| match entry {
+      FormattingEntry::Marker => true,
+      // Formatting elements are usually near the top of the stack
+      FormattingEntry::Element(node, _) => self.open_elements.iter().rev().any(|x| x == node),
+    };
+    if is_open(last) {
+      return
+    }
+    let mut index = self.active_formatting.len() - 1;
+    while index > 0 && !is_open(&self.active_formatting[index - 1]) {
+      index -= 1;
+    }
+    for index in index..self.active_formatting.len() {
+      let FormattingEntry::Element(_, tag) = &self.active_formatting[index] else { unreachable!() };
+      let tag = tag.clone();
+      let node = self.insert_html_element(&tag);
+      self.active_formatting[index] = FormattingEntry::Element(node, tag);
+    }
+  }
+
+  fn clear_active_formatting_to_last_marker(&mut self) {
+    while let Some(entry) = self.active_formatting.pop() {
+      if let FormattingEntry::Marker = entry {
+        break
+      }
+    }
+  }
+
+  // ---- node creation and insertion ----
+
+  fn appropriate_place(&self, override_target: Option<NodeId>) -> InsertionPoint {
+    let point = self.foster_parented_place(override_target);
+    // Nodes inserted into a template go into its contents instead
+    match &self.dom[point.parent] {
+      Node::Element(ElementNode { template_contents: Some(contents), .. }) => InsertionPoint {
+        parent: *contents,
+        before: None,
+      },
+      _ => point,
+    }
+  }
+
+  fn foster_parented_place(&self, override_target: Option<NodeId>) -> InsertionPoint {
+    let target = override_target.unwrap_or_else(|| self.current_node());
+    if self.foster_parenting && is_one_of(&self.dom[target], &["table", "tbody", "tfoot", "thead", "tr"]) {
+      let last_template = self.open_elements.iter().rposition(|&x| is(&self.dom[x], "template"));
+      let last_table = self.open_elements.iter().rposition(|&x| is(&self.dom[x], "table"));
+      if let Some(template) = last_template {
+        if last_table.is_none_or(|table| template > table) {
+          return InsertionPoint {
+            parent: self.open_elements[template],
+            before: None,
+          }
+        }
+      }
+      let Some(table_index) = last_table else {
+        return InsertionPoint {
+          parent: self.open_elements[0],
+          before: None,
+        }
+      };
+      let table = self.open_elements[table_index];
+      if let Some(parent) = self.dom.data(table).parent {
+        return InsertionPoint {
+          parent,
+          before: Some(table),
+        }
+      }
+      return InsertionPoint {
+        parent: self.open_elements[table_index - 1],
+        before: None,
+      }
+    }
+    InsertionPoint {
+      parent: target,
+      before: None,
+    }
+  }
+
+  fn insert_at(&mut self, point: &InsertionPoint, node: NodeId) {
+    match point.before {
+      Some(before) => self.dom.link_before(point.parent, node, before),
+      None => self.dom.link_child(point.parent, node),
+    }
+  }
+
+  /// Insert a comment as the last child of `parent`, or at the appropriate place
+  fn insert_comment(&mut self, comment: Comment, parent: Option<NodeId>) {
+    let node = match comment.processing_instruction {
+      true => {
+        let data = comment.data.strip_prefix('?').unwrap_or(&comment.data);
+        let data = data.strip_suffix('?').unwrap_or(data);
+        let (target, data) = data.split_once(is_whitespace).unwrap_or((data, ""));
+        Node::ProcessingInstruction(ProcessingInstructionNode {
+          target: target.into(),
+          data: data.trim_start_matches(is_whitespace).into(),
+          source: Some(self.span.clone()),
+        })
+      },
+      false => Node::Comment(CommentNode {
+        text: comment.data,
+        source: Some(self.span.clone()),
+      }),
+    };
+    let point = match parent {
+      Some(parent) => InsertionPoint { parent, before: None },
+      None => self.appropriate_place(None),
+    };
+    let node = self.dom.create_node(node);
+    self.insert_at(&point, node);
+  }
+
+  fn create_element(&mut self, tag: &Tag, namespace: Namespace) -> NodeId {
+    let mut attributes = AttributeMap::default();
+    let mut attribute_namespaces = FxHashMap::default();
+    for attribute in &tag.attributes {
+      //the first of duplicate attributes wins
+      if attributes.contains_key(&attribute.name) {
+        continue
+      }
+      attributes.insert(attribute.name.clone(), attribute.value.clone());
+      if namespace != Namespace::Html {
+        if let Some(attribute_namespace) = foreign::foreign_attribute_namespace(&attribute.name) {
+          attribute_namespaces.insert(attribute.name.clone(), attribute_namespace);
+        }
+      }
+    }
+    let mut element = ElementNode::new_with_tag_and_attributes(tag.name.clone(), attributes);
+    element.attribute_namespaces = attribute_namespaces;
+    //attribute names may have been adjusted for foreign content since the tag was located
+    element.source = tag.source.as_ref().map(|source| Box::new(ElementSource {
+      attributes: tag.attributes.iter().zip(&source.attributes)
+        .map(|(attribute, (_, span))| (attribute.name.clone(), span.clone()))
+        .collect(),
+      ..source.clone()
+    }));
+    if namespace == Namespace::Html && tag.name == "template" {
+      element.template_contents = Some(self.dom.create_fragment());
+    }
+    element.namespace = namespace;
+    self.dom.create_node(Node::Element(element))
+  }
+
+  fn insert_element(&mut self, tag: &Tag, namespace: Namespace) -> NodeId {
+    let point = self.appropriate_place(None);
+    let node = self.create_element(tag, namespace);
+    self.insert_at(&point, node);
+    self.open_elements.push(&self.dom, node);
+    node
+  }
+
+  fn insert_html_element(&mut self, tag: &Tag) -> NodeId {
+    self.insert_element(tag, Namespace::Html)
+  }
+
+  /// Insert an element for a start tag that has no token (e.g. implied `<body>`)
+  fn insert_html_element_named(&mut self, name: &str) -> NodeId {
+    self.insert_html_element(&Tag::new(name))
+  }
+
+  fn insert_character(&mut self, chr: char) {
+    let point = self.appropriate_place(None);
+    if point.parent == self.dom.tree {
+      return
+    }
+    let previous = match point.before {
+      Some(before) => self.dom.data(before).previous_sibling,
+      None => self.dom.data(point.parent).last_child,
+    };
+    if let Some(Node::Text(text)) = previous.map(|x| &mut self.dom[x]) {
+      text.text.push(chr);
+      if let Some(source) = &mut text.source {
+        source.range.end = self.span.range.end;
+        source.end = self.span.end;
+      }
+      return
+    }
+    let node = self.dom.create_node(Node::Text(TextNode {
+      text: chr.into(),
+      source: Some(self.span.clone()),
+    }));
+    self.insert_at(&point, node);
+  }
+
+  /// Generic raw text and generic RCDATA element parsing algorithms
+  fn parse_text_element(&mut self, tag: &Tag, state: State) {
+    self.insert_html_element(tag);
+    self.tokenizer_state = Some(state);
+    self.original_mode = self.mode;
+    self.mode = InsertionMode::Text;
+  }
+
+  fn add_missing_attributes(&mut self, node: NodeId, tag: &Tag) {
+    if let Node::Element(element) = &mut self.dom[node] {
+      for attribute in &tag.attributes {
+        if element.attribute(&attribute.name).is_none() {
+          element.set_attribute(&attribute.name, Some(attribute.value.clone()));
+        }
+      }
+    }
+  }
+
+  fn stop_parsing(&mut self) {
+    self.open_elements.clear();
+  }
+
+  // ---- insertion modes ----
+
+  fn initial(&mut self, token: Token) {
+    match token {
+      Token::Character(chr) if is_whitespace(chr) => (),
+      Token::Comment(comment) => self.insert_comment(comment, Some(self.dom.tree)),
+      Token::Doctype(doctype) => {
+        if doctype.name.as_deref() != Some("html") ||
+          doctype.public_id.is_some() ||
+          doctype.system_id.as_deref().is_some_and(|x| x != "about:legacy-compat")
+        {
+          self.parse_error(ParseErrorKind::NonConformingDoctype);
+        }
+        self.document_mode = quirks::document_mode(&doctype);
+        let node = Node::Doctype(DoctypeNode {
+          name: doctype.name.unwrap_or_default(),
+          public_id: doctype.public_id.unwrap_or_default(),
+          system_id: doctype.system_id.unwrap_or_default(),
+          source: Some(self.span.clone()),
+        });
+        let node = self.dom.create_node(node);
+        self.dom.link_child(self.dom.tree, node);
+        self.mode = InsertionMode::BeforeHtml;
+      },
+      token => {
+        self.parse_error(ParseErrorKind::MissingDoctype);
+        self.document_mode = DocumentMode::Quirks;
+        self.reprocess(InsertionMode::BeforeHtml, token);
+      },
+    }
+  }
+
+  fn before_html(&mut self, token: Token) {
+    match token {
+      Token::Doctype(_) => self.parse_error(ParseErrorKind::UnexpectedDoctype),
+      Token::Comment(comment) => self.insert_comment(comment, Some(self.dom.tree)),
+      Token::Character(chr) if is_whitespace(chr) => (),
+      Token::StartTag(tag) if tag.name == "html" => {
+        let node = self.create_element(&tag, Namespace::Html);
+        self.dom.link_child(self.dom.tree, node);
+        self.open_elements.push(&self.dom, node);
+        self.mode = InsertionMode::BeforeHead;
+      },
+      Token::EndTag(tag) if !matches!(tag.name.as_str(), "head" | "body" | "html" | "br") => {
+        self.parse_error(ParseErrorKind::UnexpectedEndTag);
+      },
+      token => {
+        let node = self.create_element(&Tag::new("html"), Namespace::Html);
+        self.dom.link_child(self.dom.tree, node);
+        self.open_elements.push(&self.dom, node);
+        self.reprocess(InsertionMode::BeforeHead, token);
+      },
+    }
+  }
+
+  fn before_head(&mut self, token: Token) {
+    match token {
+      Token::Character(chr) if is_whitespace(chr) => (),
+      Token::Comment(comment) => self.insert_comment(comment, None),
+      Token::Doctype(_) => self.parse_error(ParseErrorKind::UnexpectedDoctype),
+      Token::StartTag(tag) if tag.name == "html" => self.in_body(Token::StartTag(tag)),
+      Token::StartTag(tag) if tag.name == "head" => {
+        self.head = Some(self.insert_html_element(&tag));
+        self.mode = InsertionMode::InHead;
+      },
+      Token::EndTag(tag) if !matches!(tag.name.as_str(), "head" | "body" | "html" | "br") => {
+        self.parse_error(ParseErrorKind::UnexpectedEndTag);
+      },
+      token => {
+        self.head = Some(self.insert_html_element_named("head"));
+        self.reprocess(InsertionMode::InHead, token);
+      },
+    }
+  }
+
+  fn in_head(&mut self, token: Token) {
+    match token {
+      Token::Character(chr) if is_whitespace(chr) => self.insert_character(chr),
+      Token::Comment(comment) => self.insert_comment(comment, None),
+      Token::Doctype(_) => self.parse_error(ParseErrorKind::UnexpectedDoctype),
+      Token::StartTag(tag) => match tag.name.as_str() {
+        "html" => self.in_body(Token::StartTag(tag)),
+        "base" | "basefont" | "bgsound" | "link" | "meta" => {
+          self.insert_html_element(&tag);
+          self.pop();
+          self.self_closing_acknowledged = true;
+        },
+        "title" => self.parse_text_element(&tag, State::Rcdata),
+        "noframes" | "style" => self.parse_text_element(&tag, State::Rawtext),
+        "noscript" => {
+          // scripting is always disabled
+          self.insert_html_element(&tag);
+          self.mode = InsertionMode::InHeadNoscript;
+        },
+        "script" => self.parse_text_element(&tag, State::ScriptData),
+        "template" => {
+          self.insert_html_element(&tag);
+          self.active_formatting.push(FormattingEntry::Marker);
+          self.frameset_ok = false;
+          self.mode = InsertionMode::InTemplate;
+          self.template_modes.push(InsertionMode::InTemplate);
+        },
+        "head" => self.parse_error(ParseErrorKind::UnexpectedStartTag),
+        _ => {
+          self.pop();
+          self.reprocess(InsertionMode::AfterHead, Token::StartTag(tag));
+        },
+      },
+      Token::EndTag(tag) => match tag.name.as_str() {
+        "head" => {
+          self.pop();
+          self.mode = InsertionMode::AfterHead;
+        },
+        "body" | "html" | "br" => {
+          self.pop();
+          self.reprocess(InsertionMode::AfterHead, Token::EndTag(tag));
+        },
+        "template" => {
+          if !self.stack_contains("template") {
+            self.parse_error(ParseErrorKind::UnexpectedEndTag);
+            return
+          }
+          self.generate_implied_end_tags_thoroughly();
+          if !self.current_node_is("template") {
+            self.parse_error(ParseErrorKind::UnexpectedEndTag);
+          }
+          self.pop_until("template");
+          self.clear_active_formatting_to_last_marker();
+          self.template_modes.pop();
+          self.reset_insertion_mode();
+        },
+        _ => self.parse_error(ParseErrorKind::UnexpectedEndTag),
+      },
+      token => {
+        self.pop();
+        self.reprocess(InsertionMode::AfterHead, token);
+      },
+    }
+  }
+
+  fn in_head_noscript(&mut self, token: Token) {
+    match token {
+      Token::Doctype(_) => self.parse_error(ParseErrorKind::UnexpectedDoctype),
+      Token::StartTag(tag) if tag.name == "html" => self.in_body(Token::StartTag(tag)),
+      Token::EndTag(tag) if tag.name == "noscript" => {
+        self.pop();
+        self.mode = InsertionMode::InHead;
+      },
+      Token::Character(chr) if is_whitespace(chr) => self.in_head(token),
+      Token::Comment(_) => self.in_head(token),
+      Token::StartTag(tag) if matches!(tag.name.as_str(), "basefont" | "bgsound" | "link" | "meta" | "noframes" | "style") => {
+        self.in_head(Token::StartTag(tag));
+      },
+      Token::StartTag(tag) if matches!(tag.name.as_str(), "head" | "noscript") => {
+        self.parse_error(ParseErrorKind::UnexpectedStartTag);
+      },
+      Token::EndTag(tag) if tag.name != "br" => self.parse_error(ParseErrorKind::UnexpectedEndTag),
+      token => {
+        self.parse_error(ParseErrorKind::UnexpectedTokenInNoscript);
+        self.pop();
+        self.reprocess(InsertionMode::InHead, token);
+      },
+    }
+  }
+
+  fn after_head(&mut self, token: Token) {
+    match token {
+      Token::Character(chr) if is_whitespace(chr) => self.insert_character(chr),
+      Token::Comment(comment) => self.insert_comment(comment, None),
+      Token::Doctype(_) => self.parse_error(ParseErrorKind::UnexpectedDoctype),
+      Token::StartTag(tag) => match tag.name.as_str() {
+        "html" => self.in_body(Token::StartTag(tag)),
+        "body" => {
+          self.insert_html_element(&tag);
+          self.frameset_ok = false;
+          self.mode = InsertionMode::InBody;
+        },
+        "frameset" => {
+          self.insert_html_element(&tag);
+          self.mode = InsertionMode::InFrameset;
+        },
+        "base" | "basefont" | "bgsound" | "link" | "meta" | "noframes" | "script" | "style" | "template" | "title" => {
+          self.parse_error(ParseErrorKind::UnexpectedStartTag);
+          let head = self.head.unwrap();
+          self.open_elements.push(&self.dom, head);
+          self.in_head(Token::StartTag(tag));
+          self.remove_from_stack(head);
+        },
+        "head" => self.parse_error(ParseErrorKind::UnexpectedStartTag),
+        _ => {
+          self.insert_html_element_named("body");
+          self.reprocess(InsertionMode::InBody, Token::StartTag(tag));
+        },
+      },
+      Token::EndTag(tag) => match tag.name.as_str() {
+        "template" => self.in_head(Token::EndTag(tag)),
+        "body" | "html" | "br" => {
+          self.insert_html_element_named("body");
+          self.reprocess(InsertionMode::InBody, Token::EndTag(tag));
+        },
+        _ => self.parse_error(ParseErrorKind::UnexpectedEndTag),
+      },
+      token => {
+        self.insert_html_element_named("body");
+        self.reprocess(InsertionMode::InBody, token);
+      },
+    }
+  }
+
+  fn in_body(&mut self, token: Token) {
+    match token {
+      Token::Character('\0') => self.parse_error(ParseErrorKind::UnexpectedNullCharacter),
+      Token::Character(chr) => {
+        self.reconstruct_active_formatting_elements();
+        self.insert_character(chr);
+        if !is_whitespace(chr) {
+          self.frameset_ok = false;
+        }
+      },
+      Token::Comment(comment) => self.insert_comment(comment, None),
+      Token::Doctype(_) => self.parse_error(ParseErrorKind::UnexpectedDoctype),
+      Token::StartTag(tag) => self.in_body_start_tag(tag),
+      Token::EndTag(tag) => self.in_body_end_tag(tag),
+      Token::Eof => {
+        if !self.template_modes.is_empty() {
+          self.in_template(Token::Eof);
+          return
+        }
+        self.check_unclosed_elements();
+        self.stop_parsing();
+      },
+    }
+  }
+
+  fn check_unclosed_elements(&mut self) {
+    const ALLOWED: &[&str] = &[
+      "dd", "dt", "li", "optgroup", "option", "p", "rb", "rp", "rt", "rtc", "tbody", "td", "tfoot", "th",
+      "thead", "tr", "body", "html",
+    ];
+    if self.open_elements.iter().any(|&x| !is_one_of(&self.dom[x], ALLOWED)) {
+      self.parse_error(ParseErrorKind::UnclosedElements);
+    }
+  }
+
+  fn in_body_start_tag(&mut self, mut tag: Tag) {
+    match tag.name.as_str() {
+      "html" => {
+        self.parse_error(ParseErrorKind::UnexpectedStartTag);
+        if !self.stack_contains("template") {
+          self.add_missing_attributes(self.open_elements[0], &tag);
+        }
+      },
+      "base" | "basefont" | "bgsound" | "link" | "meta" | "noframes" | "script" | "style" | "template" | "title" => {
+        self.in_head(Token::StartTag(tag));
+      },
+      "body" => {
+        self.parse_error(ParseErrorKind::UnexpectedStartTag);
+        if self.open_elements.len() == 1 || !is(&self.dom[self.open_elements[1]], "body") || self.stack_contains("template") {
+          return
+        }
+        self.frameset_ok = false;
+        self.add_missing_attributes(self.open_elements[1], &tag);
+      },
+      "frameset" => {
+        self.parse_error(ParseErrorKind::UnexpectedStartTag);
+        if self.open_elements.len() == 1 || !is(&self.dom[self.open_elements[1]], "body") || !self.frameset_ok {
+          return
+        }
+        self.dom.unlink(self.open_elements[1]);
+        self.open_elements.truncate(&self.dom, 1);
+        self.insert_html_element(&tag);
+        self.mode = InsertionMode::InFrameset;
+      },
+      "address" | "article" | "aside" | "blockquote" | "center" | "details" | "dialog" | "dir" | "div" | "dl" |
+      "fieldset" | "figcaption" | "figure" | "footer" | "header" | "hgroup" | "main" | "menu" | "nav" | "ol" |
+      "p" | "search" | "section" | "summary" | "ul" => {
+        self.close_p_if_in_button_scope();
+        self.insert_html_element(&tag);
+      },
+      "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+        self.close_p_if_in_button_scope();
+        if self.current_node_is_one_of(HEADINGS) {
+          self.parse_error(ParseErrorKind::UnexpectedStartTag);
+          self.pop();
+        }
+        self.insert_html_element(&tag);
+      },
+      "pre" | "listing" => {
+        self.close_p_if_in_button_scope();
+        self.insert_html_element(&tag);
+        self.ignore_newline = true;
+        self.frameset_ok = false;
+      },
+      "form" => {
+        let has_template = self.stack_contains("template");
+        if self.form.is_some() && !has_template {
+          self.parse_error(ParseErrorKind::UnexpectedStartTag);
+          return
+        }
+        self.close_p_if_in_button_scope();
+        let node = self.insert_html_element(&tag);
+        if !has_template {
+          self.form = Some(node);
+        }
+      },
+      "li" | "dd" | "dt" => {
+        self.frameset_ok = false;
+        let names: &[&str] = match tag.name.as_str() {
+          "li" => &["li"],
+          _ => &["dd", "dt"],
+        };
+        let open = self.open_elements.iter().rev()
+          .map(|&x| &self.dom[x])
+          .take_while(|x| is_one_of(x, names) || !is_special(x) || is_one_of(x, &["address", "div", "p"]))
+          .find_map(|x| html_tag_name(x).filter(|name| names.contains(name)))
+          .map(str::to_owned);
+        if let Some(name) = open {
+          self.generate_implied_end_tags_except(Some(&name));
+          if !self.current_node_is(&name) {
+            self.parse_error(ParseErrorKind::UnexpectedStartTag);
+          }
+          self.pop_until(&name);
+        }
+        self.close_p_if_in_button_scope();
+        self.insert_html_element(&tag);
+      },
+      "plaintext" => {
+        self.close_p_if_in_button_scope();
+        self.insert_html_element(&tag);
+        self.tokenizer_state = Some(State::Plaintext);
+      },
+      "math" | "svg" => {
+        self.reconstruct_active_formatting_elements();
+        let namespace = match tag.name.as_str() {
+          "math" => {
+            foreign::adjust_mathml_attributes(&mut tag);
+            Namespace::MathMl
+          },
+          _ => {
+            foreign::adjust_svg_attributes(&mut tag);
+            Namespace::Svg
+          },
+        };
+        self.insert_element(&tag, namespace);
+        if tag.self_closing {
+          self.pop();
+          self.self_closing_acknowledged = true;
+        }
+      },
+      "button" => {
+        if self.in_scope("button", Scope::Default) {
+          self.parse_error(ParseErrorKind::UnexpectedStartTag);
+          self.generate_implied_end_tags();
+          self.pop_until("button");
+        }
+        self.reconstruct_active_formatting_elements();
+        self.insert_html_element(&tag);
+        self.frameset_ok = false;
+      },
+      "a" => {
+        let existing = self.active_formatting.iter().rev()
+          .take_while(|x| !matches!(x, FormattingEntry::Marker))
+          .find_map(|x| match x {
+            FormattingEntry::Element(node, _) if is(&self.dom[*node], "a") => Some(*node),
+            _ => None,
+          });
+        if let Some(existing) = existing {
+          self.parse_error(ParseErrorKind::UnexpectedStartTag);
+          self.adoption_agency("a");
+          if let Some(index) = self.formatting_position(existing) {
+            self.active_formatting.remove(index);
+          }
+          self.remove_from_stack(existing);
+        }
+        self.reconstruct_active_formatting_elements();
+        let node = self.insert_html_element(&tag);
+        self.push_formatting_element(node, tag);
+      },
+      "b" | "big" | "code" | "em" | "font" | "i" | "s" | "small" | "strike" | "strong" | "tt" | "u" => {
+        self.reconstruct_active_formatting_elements();
+        let node = self.insert_html_element(&tag);
+        self.push_formatting_element(node, tag);
+      },
+      "nobr" => {
+        self.reconstruct_active_formatting_elements();
+        if self.in_scope("nobr", Scope::Default) {
+          self.parse_error(ParseErrorKind::UnexpectedStartTag);
+          self.adoption_agency("nobr");
+          self.reconstruct_active_formatting_elements();
+        }
+        let node = self.insert_html_element(&tag);
+        self.push_formatting_element(node, tag);
+      },
+      "applet" | "marquee" | "object" => {
+        self.reconstruct_active_formatting_elements();
+        self.insert_html_element(&tag);
+        self.active_formatting.push(FormattingEntry::Marker);
+        self.frameset_ok = false;
+      },
+      "table" => {
+        if self.document_mode != DocumentMode::Quirks {
+          self.close_p_if_in_button_scope();
+        }
+        self.insert_html_element(&tag);
+        self.frameset_ok = false;
+        self.mode = InsertionMode::InTable;
+      },
+      "area" | "br" | "embed" | "img" | "keygen" | "wbr" => {
+        self.reconstruct_active_formatting_elements();
+        self.insert_html_element(&tag);
+        self.pop();
+        self.self_closing_acknowledged = true;
+        self.frameset_ok = false;
+      },
+      "input" => {
+        self.reconstruct_active_formatting_elements();
+        self.insert_html_element(&tag);
+        self.pop();
+        self.self_closing_acknowledged = true;
+        if !tag.attribute("type").is_some_and(|x| x.eq_ignore_ascii_case("hidden")) {
+          self.frameset_ok = false;
+        }
+      },
+      "param" | "source" | "track" => {
+        self.insert_html_element(&tag);
+        self.pop();
+        self.self_closing_acknowledged = true;
+      },
+      "hr" => {
+        self.close_p_if_in_button_scope();
+        self.insert_html_element(&tag);
+        self.pop();
+        self.self_closing_acknowledged = true;
+        self.frameset_ok = false;
+      },
+      "image" => {
+        self.parse_error(ParseErrorKind::UnexpectedStartTag);
+        tag.name = "img".into();
+        self.in_body_start_tag(tag);
+      },
+      "textarea" => {
+        self.insert_html_element(&tag);
+        self.ignore_newline = true;
+        self.tokenizer_state = Some(State::Rcdata);
+        self.original_mode = self.mode;
+        self.frameset_ok = false;
+        self.mode = InsertionMode::Text;
+      },
+      "xmp" => {
+        self.close_p_if_in_button_scope();
+        self.reconstruct_active_formatting_elements();
+        self.frameset_ok = false;
+        self.parse_text_element(&tag, State::Rawtext);
+      },
+      "iframe" => {
+        self.frameset_ok = false;
+        self.parse_text_element(&tag, State::Rawtext);
+      },
+      "noembed" => self.parse_text_element(&tag, State::Rawtext),
+      "select" => {
+        self.reconstruct_active_formatting_elements();
+        self.insert_html_element(&tag);
+        self.frameset_ok = false;
+        self.mode = match self.mode {
+          InsertionMode::InTable |
+          InsertionMode::InCaption |
+          InsertionMode::InTableBody |
+          InsertionMode::InRow |
+          InsertionMode::InCell => InsertionMode::InSelectInTable,
+          _ => InsertionMode::InSelect,
+        };
+      },
+      "optgroup" | "option" => {
+        if self.current_node_is("option") {
+          self.pop();
+        }
+        self.reconstruct_active_formatting_elements();
+        self.insert_html_element(&tag);
+      },
+      "rb" | "rtc" => {
+        if self.in_scope("ruby", Scope::Default) {
+          self.generate_implied_end_tags();
+          if !self.current_node_is("ruby") {
+            self.parse_error(ParseErrorKind::UnexpectedStartTag);
+          }
+        }
+        self.insert_html_element(&tag);
+      },
+      "rp" | "rt" => {
+        if self.in_scope("ruby", Scope::Default) {
+          self.generate_implied_end_tags_except(Some("rtc"));
+          if !self.current_node_is_one_of(&["rtc", "ruby"]) {
+            self.parse_error(ParseErrorKind::UnexpectedStartTag);
+          }
+        }
+        self.insert_html_element(&tag);
+      },
+      "caption" | "col" | "colgroup" | "frame" | "head" | "tbody" | "td" | "tfoot" | "th" | "thead" | "tr" => {
+        self.parse_error(ParseErrorKind::UnexpectedStartTag);
+      },
+      _ => {
+        self.reconstruct_active_formatting_elements();
+        self.insert_html_element(&tag);
+      },
+    }
+  }
+
+  fn in_body_end_tag(&mut self, tag: Tag) {
+    match tag.name.as_str() {
+      "template" => self.in_head(Token::EndTag(tag)),
+      "body" | "html" => {
+        if !self.in_scope("body", Scope::Default) {
+          self.parse_error(ParseErrorKind::UnexpectedEndTag);
+          return
+        }
+        self.check_unclosed_elements();
+        if tag.name == "body" {
+          if let Some(&body) = self.open_elements.iter().rev().find(|&&x| is(&self.dom[x], "body")) {
+            self.record_end_tag(body);
+          }
+        }
+        self.mode = InsertionMode::AfterBody;
+        if tag.name == "html" {
+          self.after_body(Token::EndTag(tag));
+        }
+      },
+      "address" | "article" | "aside" | "blockquote" | "button" | "center" | "details" | "dialog" | "dir" |
+      "div" | "dl" | "fieldset" | "figcaption" | "figure" | "footer" | "header" | "hgroup" | "listing" |
+      "main" | "menu" | "nav" | "ol" | "pre" | "search" | "section" | "summary" | "ul" => {
+        if !self.in_scope(&tag.name, Scope::Default) {
+          self.parse_error(ParseErrorKind::UnexpectedEndTag);
+          return
+        }
+        self.generate_implied_end_tags();
+        if !self.current_node_is(&tag.name) {
+          self.parse_error(ParseErrorKind::UnexpectedEndTag);
+        }
+        self.pop_until(&tag.name);
+      },
+      "form" => {
+        if !self.stack_contains("template") {
+          let node = self.form.take();
+          let Some(node) = node.filter(|&x| self.node_in_scope(x)) else {
+            self.parse_error(ParseErrorKind::UnexpectedEndTag);
+            return
+          };
+          self.generate_implied_end_tags();
+          if self.current_node() != node {
+            self.parse_error(ParseErrorKind::UnexpectedEndTag);
+          }
+          self.remove_from_stack(node);
+        } else {
+          if !self.in_scope("form", Scope::Default) {
+            self.parse_error(ParseErrorKind::UnexpectedEndTag);
+            return
+          }
+          self.generate_implied_end_tags();
+          if !self.current_node_is("form") {
+            self.parse_error(ParseErrorKind::UnexpectedEndTag);
+          }
+          self.pop_until("form");
+        }
+      },
+      "p" => {
+        if !self.in_scope("p", Scope::Button) {
+          self.parse_error(ParseErrorKind::UnexpectedEndTag);
+          self.insert_html_element_named("p");
+        }
+        self.close_p_element();
+      },
+      "li" => {
+        if !self.in_scope("li", Scope::ListItem) {
+          self.parse_error(ParseErrorKind::UnexpectedEndTag);
+          return
+        }
+        self.generate_implied_end_tags_except(Some("li"));
+        if !self.current_node_is("li") {
+          self.parse_error(ParseErrorKind::UnexpectedEndTag);
+        }
+        self.pop_until("li");
+      },
+      "dd" | "dt" => {
+        if !self.in_scope(&tag.name, Scope::Default) {
+          self.parse_error(ParseErrorKind::UnexpectedEndTag);
+          return
+        }
+        self.generate_implied_end_tags_except(Some(&tag.name));
+        if !self.current_node_is(&tag.name) {
+          self.parse_error(ParseErrorKind::UnexpectedEndTag);
+        }
+        self.pop_until(&tag.name);
+      },
+      "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+        if !self.in_scope_one_of(HEADINGS, Scope::Default) {
+          self.parse_error(ParseErrorKind::UnexpectedEndTag);
+          return
+        }
+        self.generate_implied_end_tags();
+        if !self.current_node_is(&tag.name) {
+          self.parse_error(ParseErrorKind::UnexpectedEndTag);
+        }
+        self.pop_until_one_of(HEADINGS);
+      },
+      "a" | "b" | "big" | "code" | "em" | "font" | "i" | "nobr" | "s" | "small" | "strike" | "strong" | "tt" | "u" => {
+        if !self.adoption_agency(&tag.name) {
+          self.any_other_end_tag(&tag.name);
+        }
+      },
+      "applet" | "marquee" | "object" => {
+        if !self.in_scope(&tag.name, Scope::Default) {
+          self.parse_error(ParseErrorKind::UnexpectedEndTag);
+          return
+        }
+        self.generate_implied_end_tags();
+        if !self.current_node_is(&tag.name) {
+          self.parse_error(ParseErrorKind::UnexpectedEndTag);
+        }
+        self.pop_until(&tag.name);
+        self.clear_active_formatting_to_last_marker();
+      },
+      "br" => {
+        self.parse_error(ParseErrorKind::UnexpectedEndTag);
+        self.in_body_start_tag(Tag::new("br"));
+      },
+      _ => self.any_other_end_tag(&tag.name),
+    }
+  }
+
+  fn any_other_end_tag(&mut self, name: &str) {
+    for index in (0..self.open_elements.len()).rev() {
+      let node = self.open_elements[index];
+      if is(&self.dom[node], name) {
+        self.generate_implied_end_tags_except(Some(name));
+        if self.current_node() != node {
+          self.parse_error(ParseErrorKind::UnexpectedEndTag);
+        }
+        self.pop_until_node(node);
+        return
+      }
+      if is_special(&self.dom[node]) {
+        self.parse_error(ParseErrorKind::UnexpectedEndTag);
+        return
+      }
+    }
+  }
+
+  /// The adoption agency algorithm
+  ///
+  /// Returns `false` if the token should be handled as "any other end tag"
+  fn adoption_agency(&mut self, subject: &str) -> bool {
+    let current = self.current_node();
+    if is(&self.dom[current], subject) && self.formatting_position(current).is_none() {
+      self.pop();
+      return true
+    }
+    for _ in 0..8 {
+      let Some(formatting_index) = self.active_formatting.iter().rev()
+        .take_while(|x| !matches!(x, FormattingEntry::Marker))
+        .position(|x| matches!(x, FormattingEntry::Element(node, _) if is(&self.dom[*node], subject)))
+        .map(|x| self.active_formatting.len() - 1 - x)
+      else {
+        return false
+      };
+      let FormattingEntry::Element(formatting_element, formatting_tag) = &self.active_formatting[formatting_index] else {
+        unreachable!()
+      };
+      let formatting_element = *formatting_element;
+      let formatting_tag = formatting_tag.clone();
+
+      let Some(stack_index) = self.open_elements.iter().position(|&x| x == formatting_element) else {
+        self.parse_error(ParseErrorKind::UnexpectedEndTag);
+        self.active_formatting.remove(formatting_index);
+        return true
+      };
+      if !self.node_in_scope(formatting_element) {
+        self.parse_error(ParseErrorKind::UnexpectedEndTag);
+        return true
+      }
+      if self.current_node() != formatting_element {
+        self.parse_error(ParseErrorKind::UnexpectedEndTag);
+      }
+
+      let Some(furthest_block_index) = (stack_index + 1..self.open_elements.len())
+        .find(|&x| is_special(&self.dom[self.open_elements[x]]))
+      else {
+        self.open_elements.truncate(&self.dom, stack_index);
+        self.record_end_tag(formatting_element);
+        self.active_formatting.remove(formatting_index);
+        return true
+      };
+      let furthest_block = self.open_elements[furthest_block_index];
+      let common_ancestor = self.open_elements[stack_index - 1];
+      let mut bookmark = formatting_index;
+
+      let mut node_index = furthest_block_index;
+      let mut last_node = furthest_block;
+      let mut inner_counter = 0;
+      loop {
+        inner_counter += 1;
+        node_index -= 1;
+        let node = self.open_elements[node_index];
+        if node == formatting_element {
+          break
+        }
+        let mut node_formatting_index = self.formatting_position(node);
+        if inner_counter > 3 {
+          if let Some(index) = node_formatting_index.take() {
+            self.active_formatting.remove(index);
+            if index < bookmark {
+              bookmark -= 1;
+            }
+          }
+        }
+        let Some(node_formatting_index) = node_formatting_index else {
+          self.open_elements.remove(&self.dom, node_index);
+          continue
+        };
+        let FormattingEntry::Element(_, node_tag) = &self.active_formatting[node_formatting_index] else {
+          unreachable!()
+        };
+        let node_tag = node_tag.clone();
+        let new_node = self.create_element(&node_tag, Namespace::Html);
+        self.active_formatting[node_formatting_index] = FormattingEntry::Element(new_node, node_tag);
+        self.open_elements.replace(&self.dom, node_index, new_node);
+        if last_node == furthest_block {
+          bookmark = node_formatting_index + 1;
+        }
+        self.dom.unlink(last_node);
+        self.dom.link_child(new_node, last_node);
+        last_node = new_node;
+      }
+
+      self.dom.unlink(last_node);
+      let point = self.appropriate_place(Some(common_ancestor));
+      self.insert_at(&point, last_node);
+
+      let new_element = self.create_element(&formatting_tag, Namespace::Html);
+      self.dom.move_children(furthest_block, new_element);
+      self.dom.link_child(furthest_block, new_element);
+
+      let formatting_index = self.formatting_position(formatting_element).unwrap();
+      self.active_formatting.remove(formatting_index);
+      if formatting_index < bookmark {
+        bookmark -= 1;
+      }
+      self.active_formatting.insert(bookmark, FormattingEntry::Element(new_element, formatting_tag));
+
+      self.remove_from_stack(formatting_element);
+      let furthest_block_index = self.open_elements.iter().position(|&x| x == furthest_block).unwrap();
+      self.open_elements.insert(&self.dom, furthest_block_index + 1, new_element);
+    }
+    true
+  }
+
+  fn text(&mut self, token: Token) {
+    match token {
+      Token::Character(chr) => self.insert_character(chr),
+      Token::Eof => {
+        self.parse_error(ParseErrorKind::EofInElementThatCanHoldText);
+        self.pop();
+        self.reprocess(self.original_mode, Token::Eof);
+      },
+      Token::EndTag(_) => {
+        self.pop();
+        self.mode = self.original_mode;
+      },
+      _ => unreachable!(),
+    }
+  }
+
+  fn in_table(&mut self, token: Token) {
+    match token {
+      Token::Character(_) if self.current_node_is_one_of(&["table", "tbody", "template", "tfoot", "thead", "tr"]) => {
+        self.pending_table_text.clear();
+        self.original_mode = self.mode;
+        self.reprocess(InsertionMode::InTableText, token);
+      },
+      Token::Comment(comment) => self.insert_comment(comment, None),
+      Token::Doctype(_) => self.parse_error(ParseErrorKind::UnexpectedDoctype),
+      Token::StartTag(tag) => match tag.name.as_str() {
+        "caption" => {
+          self.clear_stack_back_to(&["table", "template", "html"]);
+          self.active_formatting.push(FormattingEntry::Marker);
+          self.insert_html_element(&tag);
+          self.mode = InsertionMode::InCaption;
+        },
+        "colgroup" => {
+          self.clear_stack_back_to(&["table", "template", "html"]);
+          self.insert_html_element(&tag);
+          self.mode = InsertionMode::InColumnGroup;
+        },
+        "col" => {
+          self.clear_stack_back_to(&["table", "template", "html"]);
+          self.insert_html_element_named("colgroup");
+          self.reprocess(InsertionMode::InColumnGroup, Token::StartTag(tag));
+        },
+        "tbody" | "tfoot" | "thead" => {
+          self.clear_stack_back_to(&["table", "template", "html"]);
+          self.insert_html_element(&tag);
+          self.mode = InsertionMode::InTableBody;
+        },
+        "td" | "th" | "tr" => {
+          self.clear_stack_back_to(&["table", "template", "html"]);
+          self.insert_html_element_named("tbody");
+          self.reprocess(InsertionMode::InTableBody, Token::StartTag(tag));
+        },
+        "table" => {
+          self.parse_error(ParseErrorKind::UnexpectedStartTag);
+          if !self.in_scope("table", Scope::Table) {
+            return
+          }
+          self.pop_until("table");
+          self.reset_insertion_mode();
+          self.process_in(self.mode, Token::StartTag(tag));
+        },
+        "style" | "script" | "template" => self.in_head(Token::StartTag(tag)),
+        "input" if tag.attribute("type").is_some_and(|x| x.eq_ignore_ascii_case("hidden")) => {
+          self.parse_error(ParseErrorKind::UnexpectedStartTag);
+          self.insert_html_element(&tag);
+          self.pop();
+          self.self_closing_acknowledged = true;
+        },
+        "form" => {
+          self.parse_error(ParseErrorKind::UnexpectedStartTag);
+          if self.stack_contains("template") || self.form.is_some() {
+            return
+          }
+          self.form = Some(self.insert_html_element(&tag));
+          self.pop();
+        },
+        _ => self.in_table_anything_else(Token::StartTag(tag)),
+      },
+      Token::EndTag(tag) => match tag.name.as_str() {
+        "table" => {
+          if !self.in_scope("table", Scope::Table) {
+            self.parse_error(ParseErrorKind::UnexpectedEndTag);
+            return
+          }
+          self.pop_until("table");
+          self.reset_insertion_mode();
+        },
+        "body" | "caption" | "col" | "colgroup" | "html" | "tbody" | "td" | "tfoot" | "th" | "thead" | "tr" => {
+          self.parse_error(ParseErrorKind::UnexpectedEndTag);
+        },
+        "template" => self.in_head(Token::EndTag(tag)),
+        _ => self.in_table_anything_else(Token::EndTag(tag)),
+      },
+      Token::Eof => self.in_body(Token::Eof),
+      token => self.in_table_anything_else(token),
+    }
+  }
+
+  fn in_table_anything_else(&mut self, token: Token) {
+    self.parse_error(ParseErrorKind::UnexpectedTokenInTable);
+    self.foster_parenting = true;
+    self.in_body(token);
+    self.foster_parenting = false;
+  }
+
+  fn in_table_text(&mut self, token: Token) {
+    match token {
+      Token::Character('\0') => self.parse_error(ParseErrorKind::UnexpectedNullCharacter),
+      Token::Character(chr) => self.pending_table_text.push((chr, self.span.clone())),
+      token => {
+        let pending = std::mem::take(&mut self.pending_table_text);
+        let span = std::mem::take(&mut self.span);
+        if pending.iter().any(|&(x, _)| !is_whitespace(x)) {
+          for (chr, span) in pending {
+            self.span = span;
+            self.in_table_anything_else(Token::Character(chr));
+          }
+        } else {
+          for (chr, span) in pending {
+            self.span = span;
+            self.insert_character(chr);
+          }
+        }
+        self.span = span;
+        self.reprocess(self.original_mode, token);
+      },
+    }
+  }
+
+  fn in_caption(&mut self, token: Token) {
+    match token {
+      Token::EndTag(tag) if tag.name == "caption" => {
+        self.close_caption();
+      },
+      Token::StartTag(ref tag) if matches!(tag.name.as_str(), "caption" | "col" | "colgroup" | "tbody" | "td" | "tfoot" | "th" | "thead" | "tr") => {
+        if self.close_caption() {
+          self.process_in(self.mode, token);
+        }
+      },
+      Token::EndTag(ref tag) if tag.name == "table" => {
+        if self.close_caption() {
+          self.process_in(self.mode, token);
+        }
+      },
+      Token::EndTag(tag) if matches!(tag.name.as_str(), "body" | "col" | "colgroup" | "html" | "tbody" | "td" | "tfoot" | "th" | "thead" | "tr") => {
+        self.parse_error(ParseErrorKind::UnexpectedEndTag);
+      },
+      token => self.in_body(token),
+    }
+  }
+
+  fn close_caption(&mut self) -> bool {
+    if !self.in_scope("caption", Scope::Table) {
+      self.parse_error(ParseErrorKind::UnexpectedEndTag);
+      return false
+    }
+    self.generate_implied_end_tags();
+    if !self.current_node_is("caption") {
+      self.parse_error(ParseErrorKind::UnexpectedEndTag);
+    }
+    self.pop_until("caption");
+    self.clear_active_formatting_to_last_marker();
+    self.mode = InsertionMode::InTable;
+    true
+  }
+
+  fn in_column_group(&mut self, token: Token) {
+    match token {
+      Token::Character(chr) if is_whitespace(chr) => self.insert_character(chr),
+      Token::Comment(comment) => self.insert_comment(comment, None),
+      Token::Doctype(_) => self.parse_error(ParseErrorKind::UnexpectedDoctype),
+      Token::StartTag(tag) if tag.name == "html" => self.in_body(Token::StartTag(tag)),
+      Token::StartTag(tag) if tag.name == "col" => {
+        self.insert_html_element(&tag);
+        self.pop();
+        self.self_closing_acknowledged = true;
+      },
+      Token::EndTag(tag) if tag.name == "colgroup" => {
+        if !self.current_node_is("colgroup") {
+          self.parse_error(ParseErrorKind::UnexpectedEndTag);
+          return
+        }
+        self.pop();
+        self.mode = InsertionMode::InTable;
+      },
+      Token::EndTag(tag) if tag.name == "col" => self.parse_error(ParseErrorKind::UnexpectedEndTag),
+      Token::StartTag(tag) if tag.name == "template" => self.in_head(Token::StartTag(tag)),
+      Token::EndTag(tag) if tag.name == "template" => self.in_head(Token::EndTag(tag)),
+      Token::Eof => self.in_body(Token::Eof),
+      token => {
+        if !self.current_node_is("colgroup") {
+          self.parse_error(ParseErrorKind::UnexpectedTokenInColumnGroup);
+          return
+        }
+        self.pop();
+        self.reprocess(InsertionMode::InTable, token);
+      },
+    }
+  }
+
+  fn in_table_body(&mut self, token: Token) {
+    const TABLE_BODY_CONTEXT: &[&str] = &["tbody", "tfoot", "thead", "template", "html"];
+    match token {
+      Token::StartTag(tag) if tag.name == "tr" => {
+        self.clear_stack_back_to(TABLE_BODY_CONTEXT);
+        self.insert_html_element(&tag);
+        self.mode = InsertionMode::InRow;
+      },
+      Token::StartTag(tag) if matches!(tag.name.as_str(), "th" | "td") => {
+        self.parse_error(ParseErrorKind::UnexpectedStartTag);
+        self.clear_stack_back_to(TABLE_BODY_CONTEXT);
+        self.insert_html_element_named("tr");
+        self.reprocess(InsertionMode::InRow, Token::StartTag(tag));
+      },
+      Token::EndTag(tag) if matches!(tag.name.as_str(), "tbody" | "tfoot" | "thead") => {
+        if !self.in_scope(&tag.name, Scope::Table) {
+          self.parse_error(ParseErrorKind::UnexpectedEndTag);
+          return
+        }
+        self.clear_stack_back_to(TABLE_BODY_CONTEXT);
+        self.pop();
+        self.mode = InsertionMode::InTable;
+      },
+      Token::StartTag(ref tag) if matches!(tag.name.as_str(), "caption" | "col" | "colgroup" | "tbody" | "tfoot" | "thead") => {
+        self.close_table_body(token);
+      },
+      Token::EndTag(ref tag) if tag.name == "table" => {
+        self.close_table_body(token);
+      },
+      Token::EndTag(tag) if matches!(tag.name.as_str(), "body" | "caption" | "col" | "colgroup" | "html" | "td" | "th" | "tr") => {
+        self.parse_error(ParseErrorKind::UnexpectedEndTag);
+      },
+      token => self.in_table(token),
+    }
+  }
+
+  fn close_table_body(&mut self, token: Token) {
+    if !self.in_scope_one_of(&["tbody", "thead", "tfoot"], Scope::Table) {
+      self.parse_error(ParseErrorKind::UnexpectedTokenInTableBody);
+      return
+    }
+    self.clear_stack_back_to(&["tbody", "tfoot", "thead", "template", "html"]);
+    self.pop();
+    self.reprocess(InsertionMode::InTable, token);
+  }
+
+  fn in_row(&mut self, token: Token) {
+    match token {
+      Token::StartTag(tag) if matches!(tag.name.as_str(), "th" | "td") => {
+        self.clear_stack_back_to(&["tr", "template", "html"]);
+        self.insert_html_element(&tag);
+        self.mode = InsertionMode::InCell;
+        self.active_formatting.push(FormattingEntry::Marker);
+      },
+      Token::EndTag(tag) if tag.name == "tr" => {
+        self.close_row();
+      },
+      Token::StartTag(ref tag) if matches!(tag.name.as_str(), "caption" | "col" | "colgroup" | "tbody" | "tfoot" | "thead" | "tr") => {
+        if self.close_row() {
+          self.process_in(self.mode, token);
+        }
+      },
+      Token::EndTag(ref tag) if tag.name == "table" => {
+        if self.close_row() {
+          self.process_in(self.mode, token);
+        }
+      },
+      Token::EndTag(ref tag) if matches!(tag.name.as_str(), "tbody" | "tfoot" | "thead") => {
+        if !self.in_scope(&tag.name, Scope::Table) {
+          self.parse_error(ParseErrorKind::UnexpectedEndTag);
+          return
+        }
+        if self.close_row() {
+          self.process_in(self.mode, token);
+        }
+      },
+      Token::EndTag(tag) if matches!(tag.name.as_str(), "body" | "caption" | "col" | "colgroup" | "html" | "td" | "th") => {
+        self.parse_error(ParseErrorKind::UnexpectedEndTag);
+      },
+      token => self.in_table(token),
+    }
+  }
+
+  fn close_row(&mut self) -> bool {
+    if !self.in_scope("tr", Scope::Table) {
+      self.parse_error(ParseErrorKind::UnexpectedEndTag);
+      return false
+    }
+    self.clear_stack_back_to(&["tr", "template", "html"]);
+    self.pop();
+    self.mode = InsertionMode::InTableBody;
+    true
+  }
+
+  fn in_cell(&mut self, token: Token) {
+    match token {
+      Token::EndTag(tag) if matches!(tag.name.as_str(), "td" | "th") => {
+        if !self.in_scope(&tag.name, Scope::Table) {
+          self.parse_error(ParseErrorKind::UnexpectedEndTag);
+          return
+        }
+        self.generate_implied_end_tags();
+        if !self.current_node_is(&tag.name) {
+          self.parse_error(ParseErrorKind::UnexpectedEndTag);
+        }
+        self.pop_until(&tag.name);
+        self.clear_active_formatting_to_last_marker();
+        self.mode = InsertionMode::InRow;
+      },
+      Token::StartTag(ref tag) if matches!(tag.name.as_str(), "caption" | "col" | "colgroup" | "tbody" | "td" | "tfoot" | "th" | "thead" | "tr") => {
+        if !self.in_scope_one_of(&["td", "th"], Scope::Table) {
+          self.parse_error(ParseErrorKind::UnexpectedStartTag);
+          return
+        }
+        self.close_cell();
+        self.process_in(self.mode, token);
+      },
+      Token::EndTag(tag) if matches!(tag.name.as_str(), "body" | "caption" | "col" | "colgroup" | "html") => {
+        self.parse_error(ParseErrorKind::UnexpectedEndTag);
+      },
+      Token::EndTag(ref tag) if matches!(tag.name.as_str(), "table" | "tbody" | "tfoot" | "thead" | "tr") => {
+        if !self.in_scope(&tag.name, Scope::Table) {
+          self.parse_error(ParseErrorKind::UnexpectedEndTag);
+          return
+        }
+        self.close_cell();
+        self.process_in(self.mode, token);
+      },
+      token => self.in_body(token),
+    }
+  }
+
+  fn close_cell(&mut self) {
+    self.generate_implied_end_tags();
+    if !self.current_node_is_one_of(&["td", "th"]) {
+      self.parse_error(ParseErrorKind::UnexpectedEndTag);
+    }
+    self.pop_until_one_of(&["td", "th"]);
+    self.clear_active_formatting_to_last_marker();
+    self.mode = InsertionMode::InRow;
+  }
+
+  fn in_select(&mut self, token: Token) {
+    match token {
+      Token::Character('\0') => self.parse_error(ParseErrorKind::UnexpectedNullCharacter),
+      Token::Character(chr) => self.insert_character(chr),
+      Token::Comment(comment) => self.insert_comment(comment, None),
+      Token::Doctype(_) => self.parse_error(ParseErrorKind::UnexpectedDoctype),
+      Token::StartTag(tag) => match tag.name.as_str() {
+        "html" => self.in_body(Token::StartTag(tag)),
+        "option" => {
+          if self.current_node_is("option") {
+            self.pop();
+          }
+          self.insert_html_element(&tag);
+        },
+        "optgroup" | "hr" => {
+          if self.current_node_is("option") {
+            self.pop();
+          }
+          if self.current_node_is("optgroup") {
+            self.pop();
+          }
+          self.insert_html_element(&tag);
+          if tag.name == "hr" {
+            self.pop();
+            self.self_closing_acknowledged = true;
+          }
+        },
+        "select" => {
+          self.parse_error(ParseErrorKind::UnexpectedStartTag);
+          if !self.in_scope("select", Scope::Select) {
+            return
+          }
+          self.pop_until("select");
+          self.reset_insertion_mode();
+        },
+        "input" | "keygen" | "textarea" => {
+          self.parse_error(ParseErrorKind::UnexpectedStartTag);
+          if !self.in_scope("select", Scope::Select) {
+            return
+          }
+          self.pop_until("select");
+          self.reset_insertion_mode();
+          self.process_in(self.mode, Token::StartTag(tag));
+        },
+        "script" | "template" => self.in_head(Token::StartTag(tag)),
+        _ => self.parse_error(ParseErrorKind::UnexpectedStartTag),
+      },
+      Token::EndTag(tag) => match tag.name.as_str() {
+        "optgroup" => {
+          let len = self.open_elements.len();
+          if self.current_node_is("option") && len > 1 && is(&self.dom[self.open_elements[len - 2]], "optgroup") {
+            self.pop();
+          }
+          if self.current_node_is("optgroup") {
+            self.pop();
+          } else {
+            self.parse_error(ParseErrorKind::UnexpectedEndTag);
+          }
+        },
+        "option" => {
+          if self.current_node_is("option") {
+            self.pop();
+          } else {
+            self.parse_error(ParseErrorKind::UnexpectedEndTag);
+          }
+        },
+        "select" => {
+          if !self.in_scope("select", Scope::Select) {
+            self.parse_error(ParseErrorKind::UnexpectedEndTag);
+            return
+          }
+          self.pop_until("select");
+          self.reset_insertion_mode();
+        },
+        "template" => self.in_head(Token::EndTag(tag)),
+        _ => self.parse_error(ParseErrorKind::UnexpectedEndTag),
+      },
+      Token::Eof => self.in_body(Token::Eof),
+    }
+  }
+
+  fn in_select_in_table(&mut self, token: Token) {
+    const TABLE_TAGS: &[&str] = &["caption", "table", "tbody", "tfoot", "thead", "tr", "td", "th"];
+    match token {
+      Token::StartTag(ref tag) if TABLE_TAGS.contains(&tag.name.as_str()) => {
+        self.parse_error(ParseErrorKind::UnexpectedStartTag);
+        self.pop_until("select");
+        self.reset_insertion_mode();
+        self.process_in(self.mode, token);
+      },
+      Token::EndTag(ref tag) if TABLE_TAGS.contains(&tag.name.as_str()) => {
+        self.parse_error(ParseErrorKind::UnexpectedEndTag);
+        if !self.in_scope(&tag.name, Scope::Table) {
+          return
+        }
+        self.pop_until("select");
+        self.reset_insertion_mode();
+        self.process_in(self.mode, token);
+      },
+      token => self.in_select(token),
+    }
+  }
+
+  fn in_template(&mut self, token: Token) {
+    match token {
+      Token::Character(_) | Token::Comment(_) | Token::Doctype(_) => self.in_body(token),
+      Token::StartTag(tag) => {
+        let mode = match tag.name.as_str() {
+          "base" | "basefont" | "bgsound" | "link" | "meta" | "noframes" | "script" | "style" | "template" | "title" => {
+            self.in_head(Token::StartTag(tag));
+            return
+          },
+          "caption" | "colgroup" | "tbody" | "tfoot" | "thead" => InsertionMode::InTable,
+          "col" => InsertionMode::InColumnGroup,
+          "tr" => InsertionMode::InTableBody,
+          "td" | "th" => InsertionMode::InRow,
+          _ => InsertionMode::InBody,
+        };
+        self.template_modes.pop();
+        self.template_modes.push(mode);
+        self.reprocess(mode, Token::StartTag(tag));
+      },
+      Token::EndTag(tag) if tag.name == "template" => self.in_head(Token::EndTag(tag)),
+      Token::EndTag(_) => self.parse_error(ParseErrorKind::UnexpectedEndTag),
+      Token::Eof => {
+        if !self.stack_contains("template") {
+          self.stop_parsing();
+          return
+        }
+        self.parse_error(ParseErrorKind::EofInTemplate);
+        self.pop_until("template");
+        self.clear_active_formatting_to_last_marker();
+        self.template_modes.pop();
+        self.reset_insertion_mode();
+        // Reprocessed by `process`, doing it here would recurse once per open template
+        self.reprocess_eof = true;
+      },
+    }
+  }
+
+  fn after_body(&mut self, token: Token) {
+    match token {
+      Token::Character(chr) if is_whitespace(chr) => self.in_body(token),
+      Token::Comment(comment) => self.insert_comment(comment, Some(self.open_elements[0])),
+      Token::Doctype(_) => self.parse_error(ParseErrorKind::UnexpectedDoctype),
+      Token::StartTag(tag) if tag.name == "html" => self.in_body(Token::StartTag(tag)),
+      Token::EndTag(tag) if tag.name == "html" => {
+        if self.context.is_some() {
+          self.parse_error(ParseErrorKind::UnexpectedEndTag);
+          return
+        }
+        self.record_end_tag(self.open_elements[0]);
+        self.mode = InsertionMode::AfterAfterBody;
+      },
+      Token::Eof => self.stop_parsing(),
+      token => {
+        self.parse_error(ParseErrorKind::UnexpectedTokenAfterBody);
+        self.reprocess(InsertionMode::InBody, token);
+      },
+    }
+  }
+
+  fn in_frameset(&mut self, token: Token) {
+    match token {
+      Token::Character(chr) if is_whitespace(chr) => self.insert_character(chr),
+      Token::Comment(comment) => self.insert_comment(comment, None),
+      Token::Doctype(_) => self.parse_error(ParseErrorKind::UnexpectedDoctype),
+      Token::StartTag(tag) => match tag.name.as_str() {
+        "html" => self.in_body(Token::StartTag(tag)),
+        "frameset" => {
+          self.insert_html_element(&tag);
+        },
+        "frame" => {
+          self.insert_html_element(&tag);
+          self.pop();
+          self.self_closing_acknowledged = true;
+        },
+        "noframes" => self.in_head(Token::StartTag(tag)),
+        _ => self.parse_error(ParseErrorKind::UnexpectedStartTag),
+      },
+      Token::EndTag(tag) if tag.name == "frameset" => {
+        if self.open_elements.len() == 1 {
+          self.parse_error(ParseErrorKind::UnexpectedEndTag);
+          return
+        }
+        self.pop();
+        if self.context.is_none() && !self.current_node_is("frameset") {
+          self.mode = InsertionMode::AfterFrameset;
+        }
+      },
+      Token::Eof => {
+        if self.open_elements.len() != 1 {
+          self.parse_error(ParseErrorKind::EofInFrameset);
+        }
+        self.stop_parsing();
+      },
+      _ => self.parse_error(ParseErrorKind::UnexpectedTokenInFrameset),
+    }
+  }
+
+  fn after_frameset(&mut self, token: Token) {
+    match token {
+      Token::Character(chr) if is_whitespace(chr) => self.insert_character(chr),
+      Token::Comment(comment) => self.insert_comment(comment, None),
+      Token::Doctype(_) => self.parse_error(ParseErrorKind::UnexpectedDoctype),
+      Token::StartTag(tag) if tag.name == "html" => self.in_body(Token::StartTag(tag)),
+      Token::EndTag(tag) if tag.name == "html" => self.mode = InsertionMode::AfterAfterFrameset,
+      Token::StartTag(tag) if tag.name == "noframes" => self.in_head(Token::StartTag(tag)),
+      Token::Eof => self.stop_parsing(),
+      _ => self.parse_error(ParseErrorKind::UnexpectedTokenAfterFrameset),
+    }
+  }
+
+  fn after_after_body(&mut self, token: Token) {
+    match token {
+      Token::Comment(comment) => self.insert_comment(comment, Some(self.dom.tree)),
+      Token::Doctype(_) => self.in_body(token),
+      Token::Character(chr) if is_whitespace(chr) => self.in_body(token),
+      Token::StartTag(tag) if tag.name == "html" => self.in_body(Token::StartTag(tag)),
+      Token::Eof => self.stop_parsing(),
+      token => {
+        self.parse_error(ParseErrorKind::UnexpectedTokenAfterBody);
+        self.reprocess(InsertionMode::InBody, token);
+      },
+    }
+  }
+
+  fn after_after_frameset(&mut self, token: Token) {
+    match token {
+      Token::Comment(comment) => self.insert_comment(comment, Some(self.dom.tree)),
+      Token::Doctype(_) => self.in_body(token),
+      Token::Character(chr) if is_whitespace(chr) => self.in_body(token),
+      Token::StartTag(tag) if tag.name == "html" => self.in_body(Token::StartTag(tag)),
+      Token::StartTag(tag) if tag.name == "noframes" => self.in_head(Token::StartTag(tag)),
+      Token::Eof => self.stop_parsing(),
+      _ => self.parse_error(ParseErrorKind::UnexpectedTokenAfterFrameset),
+    }
+  }
+
+  // ---- foreign content ----
+
+  /// Pop foreign elements until back in HTML content
+  fn pop_foreign_elements(&mut self) {
+    while !self.open_elements.last().map(|&x| &self.dom[x]).is_none_or(|node| {
+      is_mathml_text_integration_point(node) ||
+      is_html_integration_point(node) ||
+      namespace(node) == Some(Namespace::Html)
+    }) {
+      self.pop();
+    }
+  }
+
+  fn foreign_content(&mut self, token: Token) {
+    match token {
+      Token::Character('\0') => {
+        self.parse_error(ParseErrorKind::UnexpectedNullCharacter);
+        self.insert_character('\u{FFFD}');
+      },
+      Token::Character(chr) => {
+        self.insert_character(chr);
+        if !is_whitespace(chr) {
+          self.frameset_ok = false;
+        }
+      },
+      Token::Comment(comment) => self.insert_comment(comment, None),
+      Token::Doctype(_) => self.parse_error(ParseErrorKind::UnexpectedDoctype),
+      Token::StartTag(tag) if FOREIGN_BREAKOUT.contains(&tag.name.as_str()) || (
+        tag.name == "font" && ["color", "face", "size"].iter().any(|x| tag.attribute(x).is_some())
+      ) => {
+        self.parse_error(ParseErrorKind::UnexpectedStartTag);
+        self.pop_foreign_elements();
+        self.process_in(self.mode, Token::StartTag(tag));
+      },
+      Token::EndTag(tag) if tag.name == "br" || tag.name == "p" => {
+        self.parse_error(ParseErrorKind::UnexpectedEndTag);
+        self.pop_foreign_elements();
+        self.process_in(self.mode, Token::EndTag(tag));
+      },
+      Token::StartTag(mut tag) => {
+        let namespace = self.adjusted_current_node().and_then(|x| namespace(&self.dom[x])).unwrap();
+        match namespace {
+          Namespace::MathMl => foreign::adjust_mathml_attributes(&mut tag),
+          Namespace::Svg => {
+            foreign::adjust_svg_tag_name(&mut tag);
+            foreign::adjust_svg_attributes(&mut tag);
+          },
+          _ => (),
+        }
+        self.insert_element(&tag, namespace);
+        if tag.self_closing {
+          self.pop();
+          self.self_closing_acknowledged = true;
+        }
+      },
+      Token::EndTag(tag) => {
+        let matches = |node: &Node| match node {
+          Node::Element(element) => element.tag_name.eq_ignore_ascii_case(&tag.name),
+          _ => false,
+        };
+        let mut index = self.open_elements.len() - 1;
+        if !matches(&self.dom[self.open_elements[index]]) {
+          self.parse_error(ParseErrorKind::UnexpectedEndTag);
+        }
+        while index > 0 {
+          let node = self.open_elements[index];
+          if matches(&self.dom[node]) {
+            self.pop_until_node(node);
+            return
+          }
+          index -= 1;
+          if namespace(&self.dom[self.open_elements[index]]) == Some(Namespace::Html) {
+            self.process_in(self.mode, Token::EndTag(tag));
+            return
+          }
+        }
+      },
+      Token::Eof => unreachable!(),
+    }
+  }
+}
@@ -0,0 +1,963 @@
+//! XML parser, see <https://www.w3.org/TR/xml/> and <https://www.w3.org/TR/xml-names/>
+//!
+//! Builds the same tree as the HTML parser does, but unlike HTML any
+//! well-formedness error is fatal: parsing stops at the first one and the
+//! tree is left as it was at that point.
+
+use std::{cell::Cell, ops::Range};
+use rustc_hash::FxHashMap;
+use super::{encoding::{InputDecoder, Sniffing}, entities::NAMED_CHARACTER_REFERENCES, LineIndex};
+use crate::dom::{
+  AttributeMap, CommentNode, Dom, DoctypeNode, ElementNode, ElementSource, LineColumn, Namespace, Node, NodeId,
+  ParseError, ParseErrorKind, ProcessingInstructionNode, Syntax, TextNode,
+};
+
+/// Doctypes which make the HTML named character references available, see
+/// <https://html.spec.whatwg.org/multipage/xhtml.html#parsing-xhtml-documents>
+const XHTML_PUBLIC_IDS: &[&str] = &[
+  "-//W3C//DTD XHTML 1.0 Transitional//EN",
+  "-//W3C//DTD XHTML 1.1//EN",
+  "-//W3C//DTD XHTML 1.0 Strict//EN",
+  "-//W3C//DTD XHTML 1.0 Frameset//EN",
+  "-//W3C//DTD XHTML Basic 1.0//EN",
+  "-//W3C//DTD XHTML 1.1 plus MathML 2.0//EN",
+  "-//W3C//DTD XHTML 1.1 plus MathML 2.0 plus SVG 1.1//EN",
+  "-//W3C//DTD MathML 2.0//EN",
+  "-//WAPFORUM//DTD XHTML Mobile 1.0//EN",
+];
+
+/// Limit on the length of entities declared in the internal subset, keeps nested entities from blowing up
+const MAX_ENTITY_LENGTH: usize = 1 << 20;
+
+/// Limit on the total length of entity replacement text in a document, references to large entities add up too
+const MAX_ENTITY_EXPANSION: usize = 1 << 24;
+
+/// Larger documents may expand to this many times their length instead
+const ENTITY_EXPANSION_FACTOR: usize = 8;
+
+fn is_whitespace(chr: char) -> bool {
+  matches!(chr, ' ' | '\t' | '\n' | '\r')
+}
+
+fn is_char(chr: char) -> bool {
+  matches!(chr, '\t' | '\n' | '\r' | '\u{20}'..='\u{D7FF}' | '\u{E000}'..='\u{FFFD}' | '\u{10000}'..)
+}
+
+fn is_name_start_char(chr: char) -> bool {
+  matches!(chr,
+    ':' | 'A'..='Z' | '_' | 'a'..='z' | '\u{C0}'..='\u{D6}' | '\u{D8}'..='\u{F6}' | '\u{F8}'..='\u{2FF}' |
+    '\u{370}'..='\u{37D}' | '\u{37F}'..='\u{1FFF}' | '\u{200C}'..='\u{200D}' | '\u{2070}'..='\u{218F}' |
+    '\u{2C00}'..='\u{2FEF}' | '\u{3001}'..='\u{D7FF}' | '\u{F900}'..='\u{FDCF}' | '\u{FDF0}'..='\u{FFFD}' |
+    '\u{10000}'..='\u{EFFFF}'
+  )
+}
+
+fn is_name_char(chr: char) -> bool {
+  is_name_start_char(chr) ||
+    matches!(chr, '-' | '.' | '0'..='9' | '\u{B7}' | '\u{300}'..='\u{36F}' | '\u{203F}'..='\u{2040}')
+}
+
+fn is_name(name: &str) -> bool {
+  name.starts_with(is_name_start_char) && name.chars().all(is_name_char)
+}
+
+/// Split a qualified name into its prefix and local name
+fn split_qualified_name(name: &str) -> Option<(Option<&str>, &str)> {
+  match name.split_once(':') {
+    None => Some((None, name)),
+    Some((prefix, local_name)) if !prefix.is_empty() && is_name(local_name) && !local_name.contains(':') => {
+      Some((Some(prefix), local_name))
+    },
+    Some(_) => None,
+  }
+}
+
+/// Why a construct couldn't be parsed
+enum Stop {
+  /// It continues past the input received so far
+  Incomplete,
+  Error(ParseErrorKind, Range<usize>),
+}
+
+type Step<T> = Result<T, Stop>;
+
+fn error<T>(kind: ParseErrorKind, span: Range<usize>) -> Step<T> {
+  Err(Stop::Error(kind, span))
+}
+
+struct OpenElement {
+  node: NodeId,
+  name: String,
+  /// Length of the namespace binding stack before the element's declarations
+  bindings: usize,
+}
+
+struct Attribute {
+  name: String,
+  value: String,
+  span: Range<usize>,
+}
+
+/// Incremental XML parser
+///
+/// Works like [`DomParser`](super::DomParser), except that it's for XML
+/// documents (like XHTML served as `application/xhtml+xml`, or SVG images).
+/// Names are case-sensitive and namespace prefixes are resolved. Any
+/// well-formedness error ends parsing, it's reported in [`Dom::errors`].
+///
+/// Entities declared in the internal subset of the doctype are expanded as
+/// text, external entities aren't loaded.
+pub struct XmlParser {
+  source: String,
+  /// Start of the first construct that hasn't been parsed yet
+  position: usize,
+  /// Last search that ran out of input: where it started, what it looked for and where to resume it,
+  /// so a long construct arriving in small chunks isn't rescanned from its start every time
+  searched: Cell<Option<(usize, &'static str, usize)>>,
+  closed: bool,
+  failed: bool,
+  line_index: LineIndex,
+  dom: Dom,
+  open_elements: Vec<OpenElement>,
+  /// Namespace prefixes in scope, the default namespace has an empty prefix
+  bindings: Vec<(String, Namespace)>,
+  /// General entities declared in the internal subset
+  entities: FxHashMap<String, String>,
+  /// Total length of entity replacement text so far, see [`MAX_ENTITY_EXPANSION`]
+  expanded: usize,
+  /// The doctype is an XHTML one, so HTML named character references can be used
+  html_entities: bool,
+  seen_doctype: bool,
+  seen_root: bool,
+  input: InputDecoder,
+}
+
+impl Default for XmlParser {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl XmlParser {
+  /// Parser for UTF-8 encoded input
+  pub fn new() -> Self {
+    Self {
+      source: String::new(),
+      position: 0,
+      searched: Cell::new(None),
+      closed: false,
+      failed: false,
+      line_index: LineIndex::new(),
+      dom: Dom {
+        syntax: Syntax::Xml,
+        ..Dom::new()
+      },
+      open_elements: Vec::new(),
+      bindings: vec![("xml".into(), Namespace::Xml), ("xmlns".into(), Namespace::Xmlns)],
+      entities: FxHashMap::default(),
+      expanded: 0,
+      html_entities: false,
+      seen_doctype: false,
+      seen_root: false,
+      input: InputDecoder::utf8(Sniffing::Xml),
+    }
+  }
+
+  /// Parser that detects the encoding of the input
+  ///
+  /// The encoding is taken from the byte order mark, then `transport_hint`
+  /// (an encoding label, like the `charset` of the `Content-Type` header),
+  /// then the `<?xml?>` declaration, falling back to UTF-8.
+  pub fn with_encoding_detection(transport_hint: Option<&str>) -> Self {
+    let mut parser = Self::new();
+    parser.input = InputDecoder::detecting(Sniffing::Xml, transport_hint);
+    parser
+  }
+
+  /// Feed the next chunk of input\
+  /// Chunks may be split at any byte, invalid sequences are replaced with U+FFFD
+  pub fn feed(&mut self, chunk: &[u8]) {
+    self.decode(chunk, false);
+  }
+
+  fn decode(&mut self, bytes: &[u8], last: bool) {
+    let data = self.input.decode(bytes, last);
+    if let Some(encoding) = self.input.encoding() {
+      self.dom.encoding = encoding;
+    }
+    self.feed_str(&data);
+  }
+
+  fn feed_str(&mut self, data: &str) {
+    if data.is_empty() {
+      return
+    }
+    self.source.push_str(data);
+    self.run();
+  }
+
+  /// The document parsed so far
+  pub fn dom(&self) -> &Dom {
+    &self.dom
+  }
+
+  /// Signal the end of input and return the finished document
+  pub fn finish(mut self) -> Dom {
+    self.decode(&[], true);
+    self.closed = true;
+    self.run();
+    self.dom
+  }
+
+  fn run(&mut self) {
+    if self.failed {
+      return
+    }
+    self.line_index.update(&self.source, self.closed);
+    loop {
+      let result = match self.source[self.position..].starts_with('<') {
+        true => self.markup(),
+        false => self.text(),
+      };
+      match result {
+        Ok(true) => (),
+        Ok(false) | Err(Stop::Incomplete) => break,
+        Err(Stop::Error(kind, span)) => {
+          let LineColumn { line, column } = self.line_index.locate(&self.source, span.start);
+          self.dom.errors.push(ParseError { kind, line, column, span });
+          self.failed = true;
+          break
+        },
+      }
+    }
+  }
+
+  /// `Incomplete` if more input may come, otherwise an error at `at`
+  fn eof<T>(&self, kind: ParseErrorKind, at: usize) -> Step<T> {
+    match self.closed {
+      true => error(kind, at..at),
+      false => Err(Stop::Incomplete),
+    }
+  }
+
+  fn char_at(&self, at: usize) -> Option<char> {
+    self.source[at..].chars().next()
+  }
+
+  fn skip_whitespace(&self, at: usize) -> usize {
+    at + self.source[at..].find(|x| !is_whitespace(x)).unwrap_or(self.source.len() - at)
+  }
+
+  /// Like `skip_whitespace`, but at least one whitespace character is required
+  fn require_whitespace(&self, at: usize, eof: ParseErrorKind) -> Step<usize> {
+    let end = self.skip_whitespace(at);
+    match self.char_at(end) {
+      None => self.eof(eof, end),
+      Some(_) if end == at => error(ParseErrorKind::UnexpectedCharacter, at..at + 1),
+      Some(_) => Ok(end),
+    }
+  }
+
+  /// Read a name, returns the position right after it
+  fn name(&self, at: usize, eof: ParseErrorKind) -> Step<usize> {
+    match self.char_at(at) {
+      None => return self.eof(eof, at),
+      Some(chr) if !is_name_start_char(chr) => return error(ParseErrorKind::InvalidName, at..at + chr.len_utf8()),
+      Some(_) => (),
+    }
+    match self.source[at..].find(|x| !is_name_char(x)) {
+      Some(length) => Ok(at + length),
+      None => self.eof(eof, self.source.len()).map(|()| self.source.len()),
+    }
+  }
+
+  /// Read a quoted literal, returns the range of its contents
+  fn quoted(&self, at: usize, eof: ParseErrorKind) -> Step<Range<usize>> {
+    let quote = match self.char_at(at) {
+      None => return self.eof(eof, at),
+      Some('"') => "\"",
+      Some('\'') => "'",
+      Some(_) => return error(ParseErrorKind::UnexpectedCharacter, at..at + 1),
+    };
+    match self.search(at + 1, quote) {
+      Some(end) => Ok(at + 1..end),
+      None => self.eof(eof, self.source.len()),
+    }
+  }
+
+  /// Position right after the first occurrence of `what` at or after `at`
+  fn find(&self, at: usize, what: &'static str, eof: ParseErrorKind) -> Step<usize> {
+    match self.search(at, what) {
+      Some(offset) => Ok(offset + what.len()),
+      None => self.eof(eof, self.source.len()),
+    }
+  }
+
+  /// Position of the first occurrence of `what` at or after `at`\
+  /// If it's not there yet, the next search from `at` starts where this one stopped
+  fn search(&self, at: usize, what: &'static str) -> Option<usize> {
+    let from = match self.searched.get() {
+      Some((start, needle, resume)) if start == at && needle == what => resume,
+      _ => at,
+    };
+    if let Some(offset) = self.source[from..].find(what) {
+      return Some(from + offset)
+    }
+    //the start of a match may already be there
+    let mut resume = self.source.len().saturating_sub(what.len() - 1).max(from);
+    while !self.source.is_char_boundary(resume) {
+      resume -= 1;
+    }
+    self.searched.set(Some((at, what, resume)));
+    None
+  }
+
+  fn check_chars(&self, range: Range<usize>) -> Step<()> {
+    match self.source[range.clone()].char_indices().find(|&(_, x)| !is_char(x)) {
+      Some((offset, chr)) => error(ParseErrorKind::InvalidCharacter, range.start + offset..range.start + offset + chr.len_utf8()),
+      None => Ok(()),
+    }
+  }
+
+  /// Add `length` bytes of entity replacement text to `expanded`, failing once it's over the limit
+  fn count_expansion(&self, expanded: &mut usize, length: usize, span: Range<usize>) -> Step<()> {
+    *expanded += length;
+    match *expanded > MAX_ENTITY_EXPANSION.max(self.source.len() * ENTITY_EXPANSION_FACTOR) {
+      true => error(ParseErrorKind::EntityTooLarge, span),
+      false => Ok(()),
+    }
+  }
+
+  /// Decode character data, expanding references and normalizing newlines\
+  /// Expanded entities are counted in `expanded`
+  fn expand(&self, range: Range<usize>, attribute: bool, expanded: &mut usize) -> Step<String> {
+    let raw = &self.source[range.clone()];
+    let mut result = String::with_capacity(raw.len());
+    let mut chars = raw.char_indices().peekable();
+    while let Some((offset, chr)) = chars.next() {
+      let at = range.start + offset;
+      match chr {
+        '&' => {
+          let Some(length) = raw[offset..].find(';') else {
+            return error(ParseErrorKind::UnescapedAmpersand, at..at + 1)
+          };
+          result.push_str(&self.reference(at..at + length + 1, expanded)?);
+          while chars.next_if(|&(x, _)| x <= offset + length).is_some() {}
+        },
+        '<' if attribute => return error(ParseErrorKind::LessThanSignInAttributeValue, at..at + 1),
+        ']' if !attribute && raw[offset..].starts_with("]]>") => {
+          return error(ParseErrorKind::CdataEndInText, at..at + 3)
+        },
+        '\r' => {
+          chars.next_if(|&(_, x)| x == '\n');
+          result.push(if attribute { ' ' } else { '\n' });
+        },
+        '\t' | '\n' if attribute => result.push(' '),
+        chr if !is_char(chr) => return error(ParseErrorKind::InvalidCharacter, at..at + chr.len_utf8()),
+        chr => result.push(chr),
+      }
+    }
+    Ok(result)
+  }
+
+  /// Value of a `&...;` reference, declared entities are counted in `expanded`
+  fn reference(&self, span: Range<usize>, expanded: &mut usize) -> Step<String> {
+    let name = &self.source[span.start + 1..span.end - 1];
+    if let Some(number) = name.strip_prefix('#') {
+      let code = match number.strip_prefix('x') {
+        Some(hex) if hex.bytes().all(|x| x.is_ascii_hexdigit()) => u32::from_str_radix(hex, 16).ok(),
+        None if number.bytes().all(|x| x.is_ascii_digit()) => number.parse().ok(),
+        _ => None,
+      };
+      return match code.and_then(char::from_u32).filter(|&x| is_char(x)) {
+        Some(chr) => Ok(chr.into()),
+        None => error(ParseErrorKind::InvalidCharacterReference, span),
+      }
+    }
+    if !is_name(name) {
+      return error(ParseErrorKind::UnescapedAmpersand, span.start..span.start + 1)
+    }
+    let value = match name {
+      "lt" => "<",
+      "gt" => ">",
+      "amp" => "&",
+      "apos" => "'",
+      "quot" => "\"",
+      _ => match self.entities.get(name) {
+        Some(value) => {
+          self.count_expansion(expanded, value.len(), span)?;
+          value
+        },
+        None if self.html_entities => {
+          let name = &self.source[span.start + 1..span.end];
+          match NAMED_CHARACTER_REFERENCES.binary_search_by(|(x, _)| x.cmp(&name)) {
+            Ok(index) => NAMED_CHARACTER_REFERENCES[index].1,
+            Err(_) => return error(ParseErrorKind::UndefinedEntity, span),
+          }
+        },
+        None => return error(ParseErrorKind::UndefinedEntity, span),
+      },
+    };
+    Ok(value.into())
+  }
+
+  /// Node new children go to
+  fn current_parent(&self) -> NodeId {
+    let Some(current) = self.open_elements.last() else {
+      return self.dom.tree
+    };
+    match &self.dom[current.node] {
+      Node::Element(element) => element.template_contents.unwrap_or(current.node),
+      _ => unreachable!(),
+    }
+  }
+
+  fn insert(&mut self, node: Node) -> NodeId {
+    let parent = self.current_parent();
+    let node = self.dom.create_node(node);
+    self.dom.link_child(parent, node);
+    node
+  }
+
+  fn insert_text(&mut self, text: String, span: Range<usize>) {
+    let last = self.dom.data(self.current_parent()).last_child;
+    if let Some(Node::Text(node)) = last.map(|x| &mut self.dom[x]) {
+      node.text.push_str(&text);
+      if let Some(source) = &mut node.source {
+        source.range.end = span.end;
+        source.end = self.line_index.locate(&self.source, span.end);
+      }
+      return
+    }
+    self.insert(Node::Text(TextNode {
+      text,
+      source: Some(self.line_index.span(&self.source, span)),
+    }));
+  }
+
+  /// Character data up to the next tag, or the end of the document
+  fn text(&mut self) -> Step<bool> {
+    let start = self.position;
+    let end = match self.search(start, "<") {
+      Some(end) => end,
+      None if self.closed => self.source.len(),
+      None => return Ok(false),
+    };
+    if start == end {
+      return self.end_of_document()
+    }
+    let mut expanded = self.expanded;
+    let text = self.expand(start..end, false, &mut expanded)?;
+    self.expanded = expanded;
+    if self.open_elements.is_empty() {
+      if let Some(offset) = self.source[start..end].find(|x| !is_whitespace(x)) {
+        return error(ParseErrorKind::ContentOutsideRootElement, start + offset..end)
+      }
+    } else {
+      self.insert_text(text, start..end);
+    }
+    self.position = end;
+    Ok(true)
+  }
+
+  fn end_of_document(&mut self) -> Step<bool> {
+    let end = self.source.len();
+    if !self.closed {
+      return Ok(false)
+    }
+    if !self.open_elements.is_empty() {
+      return error(ParseErrorKind::UnclosedElements, end..end)
+    }
+    if !self.seen_root {
+      return error(ParseErrorKind::MissingRootElement, end..end)
+    }
+    Ok(false)
+  }
+
+  fn markup(&mut self) -> Step<bool> {
+    let start = self.position;
+    let rest = &self.source[start..];
+    const MARKERS: [&str; 4] = ["<!--", "<![CDATA[", "<!DOCTYPE", "<?"];
+    // Wait until it's clear which construct this is
+    if !self.closed && MARKERS.iter().any(|x| x.len() > rest.len() && x.starts_with(rest)) {
+      return Ok(false)
+    }
+    let end = if rest.starts_with("<!--") {
+      self.comment(start)?
+    } else if rest.starts_with("<![CDATA[") {
+      self.cdata(start)?
+    } else if rest.starts_with("<!DOCTYPE") {
+      self.doctype(start)?
+    } else if rest.starts_with("<?") {
+      self.processing_instruction(start)?
+    } else if rest.starts_with("</") {
+      self.end_tag(start)?
+    } else if rest.starts_with("<!") {
+      return error(ParseErrorKind::UnexpectedCharacter, start..start + 2)
+    } else {
+      self.start_tag(start)?
+    };
+    self.position = end;
+    Ok(true)
+  }
+
+  fn comment(&mut self, start: usize) -> Step<usize> {
+    let end = self.find(start + 4, "--", ParseErrorKind::EofInComment)?;
+    match self.char_at(end) {
+      None => return self.eof(ParseErrorKind::EofInComment, end),
+      Some('>') => (),
+      Some(_) => return error(ParseErrorKind::DoubleHyphenInComment, end - 2..end),
+    }
+    self.check_chars(start + 4..end - 2)?;
+    self.insert(Node::Comment(CommentNode {
+      text: self.source[start + 4..end - 2].replace("\r\n", "\n").replace('\r', "\n"),
+      source: Some(self.line_index.span(&self.source, start..end + 1)),
+    }));
+    Ok(end + 1)
+  }
+
+  fn cdata(&mut self, start: usize) -> Step<usize> {
+    let end = self.find(start + 9, "]]>", ParseErrorKind::EofInCdata)?;
+    if self.open_elements.is_empty() {
+      return error(ParseErrorKind::ContentOutsideRootElement, start..end)
+    }
+    self.check_chars(start + 9..end - 3)?;
+    let text = self.source[start + 9..end - 3].replace("\r\n", "\n").replace('\r', "\n");
+    self.insert_text(text, start..end);
+    Ok(end)
+  }
+
+  fn processing_instruction(&mut self, start: usize) -> Step<usize> {
+    let eof = ParseErrorKind::EofInProcessingInstruction;
+    let target_end = self.name(start + 2, eof)?;
+    let target = &self.source[start + 2..target_end];
+    let data_start = match self.source[target_end..].starts_with("?>") {
+      true => target_end,
+      false => self.require_whitespace(target_end, eof)?,
+    };
+    let end = self.find(data_start, "?>", eof)?;
+    if target.eq_ignore_ascii_case("xml") {
+      if start != 0 || target != "xml" {
+        return error(ParseErrorKind::MisplacedXmlDeclaration, start..end)
+      }
+      self.xml_declaration(data_start..end - 2)?;
+      return Ok(end)
+    }
+    if target.contains(':') {
+      return error(ParseErrorKind::InvalidQualifiedName, start + 2..target_end)
+    }
+    self.check_chars(data_start..end - 2)?;
+    self.insert(Node::ProcessingInstruction(ProcessingInstructionNode {
+      target: target.into(),
+      data: self.source[data_start..end - 2].into(),
+      source: Some(self.line_index.span(&self.source, start..end)),
+    }));
+    Ok(end)
+  }
+
+  /// Check the contents of `<?xml ... ?>`
+  fn xml_declaration(&self, range: Range<usize>) -> Step<()> {
+    let invalid = || error(ParseErrorKind::InvalidXmlDeclaration, range.clone());
+    let mut expected = ["version", "encoding", "standalone"].as_slice();
+    let mut at = range.start;
+    while at < range.end {
+      let name_start = self.skip_whitespace(at);
+      if name_start == range.end {
+        break
+      }
+      let Ok(name_end) = self.name(name_start, ParseErrorKind::InvalidXmlDeclaration) else { return invalid() };
+      let name = &self.source[name_start..name_end];
+      let equals = self.skip_whitespace(name_end);
+      if (name_start == at && at != range.start) || self.char_at(equals) != Some('=') {
+        return invalid()
+      }
+      let Ok(value) = self.quoted(self.skip_whitespace(equals + 1), ParseErrorKind::InvalidXmlDeclaration) else {
+        return invalid()
+      };
+      if value.end >= range.end {
+        return invalid()
+      }
+      let value_str = &self.source[value.clone()];
+      let Some(index) = expected.iter().position(|x| *x == name) else { return invalid() };
+      if at == range.start && name != "version" {
+        return invalid()
+      }
+      let valid = match name {
+        "version" => value_str.strip_prefix("1.").is_some_and(|x| !x.is_empty() && x.bytes().all(|x| x.is_ascii_digit())),
+        "encoding" => value_str.starts_with(|x: char| x.is_ascii_alphabetic()) &&
+          value_str.chars().all(|x| x.is_ascii_alphanumeric() || matches!(x, '.' | '_' | '-')),
+        _ => matches!(value_str, "yes" | "no"),
+      };
+      if !valid {
+        return invalid()
+      }
+      expected = &expected[index + 1..];
+      at = value.end + 1;
+    }
+    if at == range.start {
+      return invalid()
+    }
+    Ok(())
+  }
+
+  fn doctype(&mut self, start: usize) -> Step<usize> {
+    let eof = ParseErrorKind::EofInDoctype;
+    if self.seen_doctype || self.seen_root {
+      return error(ParseErrorKind::UnexpectedDoctype, start..start + 9)
+    }
+    let name_start = self.require_whitespace(start + 9, eof)?;
+    let name_end = self.name(name_start, eof)?;
+    let mut at = self.skip_whitespace(name_end);
+    let mut public_id = None;
+    let mut system_id = None;
+    let rest = &self.source[at..];
+    if !self.closed && rest.len() < 6 && ("PUBLIC".starts_with(rest) || "SYSTEM".starts_with(rest)) {
+      return Err(Stop::Incomplete)
+    }
+    if at > name_end && (rest.starts_with("PUBLIC") || rest.starts_with("SYSTEM")) {
+      if rest.starts_with("PUBLIC") {
+        let public = self.quoted(self.require_whitespace(at + 6, eof)?, eof)?;
+        public_id = Some(public.clone());
+        at = public.end + 1;
+      } else {
+        at += 6;
+      }
+      let system = self.quoted(self.require_whitespace(at, eof)?, eof)?;
+      system_id = Some(system.clone());
+      at = self.skip_whitespace(system.end + 1);
+    }
+    let mut entities = FxHashMap::default();
+    let mut expanded = self.expanded;
+    if self.char_at(at) == Some('[') {
+      at = self.internal_subset(at + 1, &mut entities, &mut expanded)?;
+      at = self.skip_whitespace(at);
+    }
+    match self.char_at(at) {
+      None => return self.eof(eof, at),
+      Some('>') => (),
+      Some(_) => return error(ParseErrorKind::UnexpectedCharacter, at..at + 1),
+    }
+    let public_id = public_id.map(|x| self.source[x].to_string()).unwrap_or_default();
+    self.html_entities = XHTML_PUBLIC_IDS.contains(&public_id.as_str());
+    self.entities = entities;
+    self.expanded = expanded;
+    self.seen_doctype = true;
+    self.insert(Node::Doctype(DoctypeNode {
+      name: self.source[name_start..name_end].into(),
+      public_id,
+      system_id: system_id.map(|x| self.source[x].to_string()).unwrap_or_default(),
+      source: Some(self.line_index.span(&self.source, start..at + 1)),
+    }));
+    Ok(at + 1)
+  }
+
+  /// Go through the declarations between `[` and `]` in the doctype, collecting entities\
+  /// Returns the position after the closing bracket
+  fn internal_subset(&self, mut at: usize, entities: &mut FxHashMap<String, String>, expanded: &mut usize) -> Step<usize> {
+    let eof = ParseErrorKind::EofInDoctype;
+    loop {
+      at = self.skip_whitespace(at);
+      let rest = &self.source[at..];
+      if rest.starts_with(']') {
+        return Ok(at + 1)
+      } else if rest.starts_with("<!--") {
+        at = self.find(at + 4, "--", eof)?;
+        if self.char_at(at) != Some('>') {
+          return match self.char_at(at) {
+            None => self.eof(eof, at),
+            Some(_) => error(ParseErrorKind::DoubleHyphenInComment, at - 2..at),
+          }
+        }
+        at += 1;
+      } else if rest.starts_with("<?") {
+        at = self.find(at + 2, "?>", eof)?;
+      } else if rest.starts_with("<!ENTITY") {
+        at = self.entity_declaration(at, entities, expanded)?;
+      } else if rest.starts_with("<!") {
+        // Other declarations are skipped, they may contain quoted `>`
+        at += 2;
+        loop {
+          match self.char_at(at) {
+            None => return self.eof(eof, at),
+            Some('>') => break,
+            Some('"' | '\'') => at = self.quoted(at, eof)?.end,
+            Some(_) => (),
+          }
+          at += 1;
+        }
+        at += 1;
+      } else if rest.starts_with('%') {
+        let name_end = self.name(at + 1, eof)?;
+        match self.char_at(name_end) {
+          None => return self.eof(eof, name_end),
+          Some(';') => at = name_end + 1,
+          Some(_) => return error(ParseErrorKind::UnexpectedCharacter, name_end..name_end + 1),
+        }
+      } else if rest.is_empty() || (!self.closed && "<!ENTITY".starts_with(rest)) {
+        return self.eof(eof, at)
+      } else {
+        return error(ParseErrorKind::UnexpectedCharacter, at..at + 1)
+      }
+    }
+  }
+
+  /// `<!ENTITY name "value">`, only internal general entities are kept
+  fn entity_declaration(&self, start: usize, entities: &mut FxHashMap<String, String>, expanded: &mut usize) -> Step<usize> {
+    let eof = ParseErrorKind::EofInDoctype;
+    let mut at = self.require_whitespace(start + 8, eof)?;
+    let parameter = self.char_at(at) == Some('%');
+    if parameter {
+      at = self.require_whitespace(at + 1, eof)?;
+    }
+    let name_end = self.name(at, eof)?;
+    let name = &self.source[at..name_end];
+    at = self.require_whitespace(name_end, eof)?;
+    let mut value = None;
+    match self.char_at(at) {
+      Some('"' | '\'') => {
+        let literal = self.quoted(at, eof)?;
+        value = Some(literal.clone());
+        at = literal.end + 1;
+      },
+      _ => {
+        // External entities aren't loaded
+        while !matches!(self.char_at(at), Some('>') | None) {
+          at = match self.char_at(at) {
+            Some('"' | '\'') => self.quoted(at, eof)?.end + 1,
+            _ => at + 1,
+          };
+        }
+      },
+    }
+    at = self.skip_whitespace(at);
+    match self.char_at(at) {
+      None => return self.eof(eof, at),
+      Some('>') => (),
+      Some(_) => return error(ParseErrorKind::UnexpectedCharacter, at..at + 1),
+    }
+    if let (false, Some(value)) = (parameter, value) {
+      if !entities.contains_key(name) {
+        let replacement = self.entity_value(value.clone(), entities)?;
+        self.count_expansion(expanded, replacement.len(), value)?;
+        entities.insert(name.into(), replacement);
+      }
+    }
+    Ok(at + 1)
+  }
+
+  /// Replacement text of an entity, references to previously declared entities are expanded
+  fn entity_value(&self, range: Range<usize>, entities: &FxHashMap<String, String>) -> Step<String> {
+    let raw = &self.source[range.clone()];
+    let mut result = String::new();
+    let mut offset = 0;
+    while let Some(length) = raw[offset..].find('&') {
+      result.push_str(&raw[offset..offset + length]);
+      let at = range.start + offset + length;
+      let Some(end) = raw[offset + length..].find(';').map(|x| at + x + 1) else {
+        return error(ParseErrorKind::UnescapedAmpersand, at..at + 1)
+      };
+      match entities.get(&self.source[at + 1..end - 1]) {
+        Some(value) => result.push_str(value),
+        //only predefined entities and character references are left, the replacement text is counted as a whole
+        None => result.push_str(&self.reference(at..end, &mut 0)?),
+      }
+      if result.len() > MAX_ENTITY_LENGTH {
+        return error(ParseErrorKind::EntityTooLarge, range)
+      }
+      offset = end - range.start;
+    }
+    result.push_str(&raw[offset..]);
+    self.check_chars(range)?;
+    Ok(result)
+  }
+
+  fn start_tag(&mut self, start: usize) -> Step<usize> {
+    let eof = ParseErrorKind::EofInTag;
+    let name_end = self.name(start + 1, eof)?;
+    let mut at = name_end;
+    let mut attributes = Vec::<Attribute>::new();
+    let mut expanded = self.expanded;
+    let self_closing = loop {
+      let next = self.skip_whitespace(at);
+      match self.char_at(next) {
+        None => return self.eof(eof, next),
+        Some('>') => {
+          at = next + 1;
+          break false
+        },
+        Some('/') => match self.char_at(next + 1) {
+          None => return self.eof(eof, next + 1),
+          Some('>') => {
+            at = next + 2;
+            break true
+          },
+          Some(_) => return error(ParseErrorKind::UnexpectedSolidusInTag, next..next + 1),
+        },
+        Some(_) if next == at => return error(ParseErrorKind::MissingWhitespaceBetweenAttributes, at..at + 1),
+        Some(_) => {
+          let attribute_end = self.name(next, eof)?;
+          let equals = self.skip_whitespace(attribute_end);
+          match self.char_at(equals) {
+            None => return self.eof(eof, equals),
+            Some('=') => (),
+            Some(_) => return error(ParseErrorKind::MissingAttributeValue, next..attribute_end),
+          }
+          let quote = self.skip_whitespace(equals + 1);
+          if self.char_at(quote).is_some_and(|x| !matches!(x, '"' | '\'')) {
+            return error(ParseErrorKind::UnquotedAttributeValue, quote..quote + 1)
+          }
+          let value = self.quoted(quote, eof)?;
+          let name = &self.source[next..attribute_end];
+          if attributes.iter().any(|x| x.name == name) {
+            return error(ParseErrorKind::DuplicateAttribute, next..attribute_end)
+          }
+          attributes.push(Attribute {
+            name: name.into(),
+            value: self.expand(value.clone(), true, &mut expanded)?,
+            span: next..value.end + 1,
+          });
+          at = value.end + 1;
+        },
+      }
+    };
+    let name = self.source[start + 1..name_end].to_string();
+    if self.open_elements.is_empty() && self.seen_root {
+      return error(ParseErrorKind::ContentOutsideRootElement, start..at)
+    }
+    let bindings = self.bindings.len();
+    let element = self.create_element(name.clone(), start + 1..name_end, attributes, start..at);
+    let element = match element {
+      Ok(element) => element,
+      Err(stop) => {
+        self.bindings.truncate(bindings);
+        return Err(stop)
+      },
+    };
+    let node = self.insert(Node::Element(element));
+    self.expanded = expanded;
+    self.seen_root = true;
+    match self_closing {
+      true => self.bindings.truncate(bindings),
+      false => self.open_elements.push(OpenElement { node, name, bindings }),
+    }
+    Ok(at)
+  }
+
+  fn lookup_namespace(&self, prefix: &str) -> Option<&Namespace> {
+    self.bindings.iter().rev().find(|(x, _)| x == prefix).map(|(_, namespace)| namespace)
+  }
+
+  /// Build an element, binding the namespaces it declares
+  fn create_element(&mut self, name: String, name_span: Range<usize>, attributes: Vec<Attribute>, span: Range<usize>) -> Step<ElementNode> {
+    for attribute in &attributes {
+      let prefix = match attribute.name.split_once(':') {
+        None if attribute.name == "xmlns" => "",
+        Some(("xmlns", prefix)) => prefix,
+        _ => continue,
+      };
+      let namespace = Namespace::from_url(&attribute.value);
+      let reserved = match prefix {
+        "xml" => namespace != Namespace::Xml,
+        "xmlns" => true,
+        "" => matches!(namespace, Namespace::Xml | Namespace::Xmlns),
+        _ => matches!(namespace, Namespace::Xml | Namespace::Xmlns | Namespace::None),
+      };
+      if reserved || split_qualified_name(&attribute.name).is_none() {
+        return error(ParseErrorKind::ReservedNamespace, attribute.span.clone())
+      }
+      self.bindings.push((prefix.into(), namespace));
+    }
+
+    let resolve = |name: &str, span: Range<usize>, default: bool| -> Step<Namespace> {
+      match split_qualified_name(name) {
+        None => error(ParseErrorKind::InvalidQualifiedName, span),
+        Some((None, _)) if !default => Ok(Namespace::None),
+        Some((prefix, _)) => match self.lookup_namespace(prefix.unwrap_or("")) {
+          Some(namespace) => Ok(namespace.clone()),
+          None if prefix.is_none() => Ok(Namespace::None),
+          None => error(ParseErrorKind::UnboundNamespacePrefix, span),
+        },
+      }
+    };
+    let namespace = resolve(&name, name_span, true)?;
+
+    let mut map = AttributeMap::default();
+    let mut attribute_namespaces = FxHashMap::default();
+    let mut expanded_names = Vec::new();
+    for attribute in &attributes {
+      let namespace = match attribute.name == "xmlns" || attribute.name.starts_with("xmlns:") {
+        true => Namespace::Xmlns,
+        false => resolve(&attribute.name, attribute.span.clone(), false)?,
+      };
+      let local_name = attribute.name.rsplit(':').next().unwrap();
+      if expanded_names.contains(&(namespace.clone(), local_name)) {
+        return error(ParseErrorKind::DuplicateAttribute, attribute.span.clone())
+      }
+      expanded_names.push((namespace.clone(), local_name));
+      if namespace != Namespace::None {
+        attribute_namespaces.insert(attribute.name.clone(), namespace);
+      }
+      map.insert(attribute.name.clone(), attribute.value.clone());
+    }
+
+    let mut element = ElementNode::new_with_tag_and_attributes(name, map);
+    if namespace == Namespace::Html && element.tag_name == "template" {
+      element.template_contents = Some(self.dom.create_fragment());
+    }
+    element.namespace = namespace;
+    element.attribute_namespaces = attribute_namespaces;
+    element.xml = true;
+    element.source = Some(Box::new(ElementSource {
+      start_tag: self.line_index.span(&self.source, span),
+      end_tag: None,
+      attributes: attributes.into_iter()
+        .map(|x| (x.name, self.line_index.span(&self.source, x.span)))
+        .collect(),
+    }));
+    Ok(element)
+  }
+
+  fn end_tag(&mut self, start: usize) -> Step<usize> {
+    let eof = ParseErrorKind::EofInTag;
+    let name_end = self.name(start + 2, eof)?;
+    let end = self.skip_whitespace(name_end);
+    match self.char_at(end) {
+      None => return self.eof(eof, end),
+      Some('>') => (),
+      Some(_) => return error(ParseErrorKind::UnexpectedCharacter, end..end + 1),
+    }
+    let name = &self.source[start + 2..name_end];
+    let Some(current) = self.open_elements.last() else {
+      return error(ParseErrorKind::UnexpectedEndTag, start..end + 1)
+    };
+    if current.name != name {
+      return error(ParseErrorKind::MismatchedEndTag, start..end + 1)
+    }
+    let current = self.open_elements.pop().unwrap();
+    self.bindings.truncate(current.bindings);
+    if let Node::Element(element) = &mut self.dom[current.node] {
+      if let Some(source) = &mut element.source {
+        source.end_tag = Some(self.line_index.span(&self.source, start..end + 1));
+      }
+    }
+    Ok(end + 1)
+  }
+}
+
+impl Dom {
+  /// Parse an XML document, see [`XmlParser`]
+  pub fn parse_xml(xml: &str) -> Self {
+    let mut parser = XmlParser::new();
+    parser.feed_str(xml);
+    parser.finish()
+  }
+
+  /// Parse an XML document from bytes in an unknown encoding
+  ///
+  /// See [`XmlParser::with_encoding_detection`] for how the encoding is determined
+  pub fn parse_xml_bytes(xml: &[u8], transport_hint: Option<&str>) -> Self {
+    let mut parser = XmlParser::with_encoding_detection(transport_hint);
+    parser.feed(xml);
+    parser.finish()
+  }
+}
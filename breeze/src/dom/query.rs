@@ -0,0 +1,41 @@
+//! Finding elements with CSS selectors
+
+use crate::css::{selector::{CssSelectorList, MatchContext}, SelectorParseError};
+use super::{DocumentMode, NodeRef};
+
+impl<'a> NodeRef<'a> {
+  /// Context for selectors matched relative to this node, which is `:scope`
+  fn match_context(&self) -> MatchContext {
+    MatchContext {
+      quirks: self.dom().mode == DocumentMode::Quirks,
+      scope: Some(self.id()),
+    }
+  }
+
+  /// First descendant element in document order matching the selectors
+  pub fn query_selector(&self, selectors: &str) -> Result<Option<NodeRef<'a>>, SelectorParseError> {
+    let selectors = CssSelectorList::parse(selectors)?;
+    let context = self.match_context();
+    Ok(self.descendants().find(|x| selectors.matches(*x, &context)))
+  }
+
+  /// All descendant elements matching the selectors, in document order
+  pub fn query_selector_all(&self, selectors: &str) -> Result<Vec<NodeRef<'a>>, SelectorParseError> {
+    let selectors = CssSelectorList::parse(selectors)?;
+    let context = self.match_context();
+    Ok(self.descendants().filter(|x| selectors.matches(*x, &context)).collect())
+  }
+
+  /// Whether this node is an element matching the selectors
+  pub fn matches(&self, selectors: &str) -> Result<bool, SelectorParseError> {
+    let selectors = CssSelectorList::parse(selectors)?;
+    Ok(selectors.matches(*self, &self.match_context()))
+  }
+
+  /// This node or its nearest ancestor matching the selectors
+  pub fn closest(&self, selectors: &str) -> Result<Option<NodeRef<'a>>, SelectorParseError> {
+    let selectors = CssSelectorList::parse(selectors)?;
+    let context = self.match_context();
+    Ok(std::iter::once(*self).chain(self.ancestors()).find(|x| selectors.matches(*x, &context)))
+  }
+}
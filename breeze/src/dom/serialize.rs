@@ -0,0 +1,160 @@
+//! Serializing nodes back to markup\
+//! See <https://html.spec.whatwg.org/multipage/parsing.html#serialising-html-fragments>
+//! and <https://w3c.github.io/DOM-Parsing/#xml-serialization> for XML documents
+
+use super::{InnerHtml, Namespace, Node, NodeRef, ElementNode, DoctypeNode, Syntax};
+
+/// Elements that never have an end tag or children
+const VOID_ELEMENTS: &[&str] = &[
+  "area", "base", "basefont", "bgsound", "br", "col", "embed", "frame", "hr", "img", "input", "keygen", "link",
+  "meta", "param", "source", "track", "wbr",
+];
+
+/// Elements whose text is written without escaping
+const RAW_TEXT_ELEMENTS: &[&str] = &["iframe", "noembed", "noframes", "plaintext", "script", "style", "xmp"];
+
+/// Elements where the parser drops a newline right after the start tag
+const NEWLINE_ELEMENTS: &[&str] = &["listing", "pre", "textarea"];
+
+fn html_element_is(element: &ElementNode, names: &[&str]) -> bool {
+  element.namespace == Namespace::Html && names.contains(&element.tag_name.as_str())
+}
+
+struct Serializer {
+  out: String,
+  xml: bool,
+}
+
+impl Serializer {
+  fn escape(&mut self, text: &str, attribute: bool) {
+    for c in text.chars() {
+      match c {
+        '&' => self.out.push_str("&amp;"),
+        '<' => self.out.push_str("&lt;"),
+        '>' => self.out.push_str("&gt;"),
+        '"' if attribute => self.out.push_str("&quot;"),
+        '\u{a0}' if !self.xml => self.out.push_str("&nbsp;"),
+        c => self.out.push(c),
+      }
+    }
+  }
+
+  fn start_tag(&mut self, element: &ElementNode, empty: bool) {
+    self.out.push('<');
+    self.out.push_str(&element.tag_name);
+    for (name, value) in &element.attributes {
+      self.out.push(' ');
+      self.out.push_str(name);
+      self.out.push_str("=\"");
+      self.escape(value, true);
+      self.out.push('"');
+    }
+    self.out.push_str(if empty { "/>" } else { ">" });
+  }
+
+  fn doctype(&mut self, doctype: &DoctypeNode) {
+    self.out.push_str("<!DOCTYPE ");
+    self.out.push_str(&doctype.name);
+    //identifiers are dropped in html, only the name affects parsing
+    if self.xml {
+      if !doctype.public_id.is_empty() {
+        self.out.push_str(&format!(" PUBLIC \"{}\"", doctype.public_id));
+      } else if !doctype.system_id.is_empty() {
+        self.out.push_str(" SYSTEM");
+      }
+      if !doctype.system_id.is_empty() {
+        self.out.push_str(&format!(" \"{}\"", doctype.system_id));
+      }
+    }
+    self.out.push('>');
+  }
+
+  /// Write the nodes with an explicit stack, recursing would overflow on deeply nested trees
+  fn nodes<'a>(&mut self, nodes: impl DoubleEndedIterator<Item = NodeRef<'a>>) {
+    enum Item<'b> {
+      Node(NodeRef<'b>),
+      EndTag(&'b str),
+    }
+    let mut stack: Vec<Item<'a>> = nodes.rev().map(Item::Node).collect();
+    while let Some(item) = stack.pop() {
+      let node = match item {
+        Item::Node(node) => node,
+        Item::EndTag(name) => {
+          self.out.push_str(&format!("</{}>", name));
+          continue
+        },
+      };
+      match node.value() {
+        Node::Element(element) => {
+          let mut children = node.serialized_children().peekable();
+          if self.xml {
+            let empty = children.peek().is_none();
+            self.start_tag(element, empty);
+            if empty {
+              continue
+            }
+          } else {
+            self.start_tag(element, false);
+            if html_element_is(element, VOID_ELEMENTS) {
+              continue
+            }
+            let newline = children.peek().is_some_and(|x| matches!(x.value(), Node::Text(text) if text.text.starts_with('\n')));
+            if newline && html_element_is(element, NEWLINE_ELEMENTS) {
+              self.out.push('\n');
+            }
+          }
+          stack.push(Item::EndTag(&element.tag_name));
+          stack.extend(children.rev().map(Item::Node));
+        },
+        Node::DocumentFragment(_) => stack.extend(node.children().rev().map(Item::Node)),
+        Node::Text(text) => {
+          let raw = !self.xml && node.parent().and_then(|x| x.element()).is_some_and(|x| html_element_is(x, RAW_TEXT_ELEMENTS));
+          match raw {
+            true => self.out.push_str(&text.text),
+            false => self.escape(&text.text, false),
+          }
+        },
+        Node::Comment(comment) => self.out.push_str(&format!("<!--{}-->", comment.text)),
+        Node::Doctype(doctype) => self.doctype(doctype),
+        Node::ProcessingInstruction(instruction) => {
+          self.out.push_str("<?");
+          self.out.push_str(&instruction.target);
+          if !instruction.data.is_empty() {
+            self.out.push(' ');
+            self.out.push_str(&instruction.data);
+          }
+          //html ends them at the first `>`, there's no `?` before it
+          self.out.push_str(if self.xml { "?>" } else { ">" });
+        },
+      }
+    }
+  }
+}
+
+impl<'a> NodeRef<'a> {
+  /// Nodes serialized inside of this one, the contents of templates take the place of their children
+  fn serialized_children(&self) -> super::Children<'a> {
+    self.template_contents().unwrap_or(*self).children()
+  }
+
+  fn serializer(&self) -> Serializer {
+    Serializer { out: String::new(), xml: self.dom().syntax == Syntax::Xml }
+  }
+}
+
+impl InnerHtml for NodeRef<'_> {
+  fn inner_html(&self) -> String {
+    let mut serializer = self.serializer();
+    //void elements in html have no content even if they have children
+    if serializer.xml || !self.element().is_some_and(|x| html_element_is(x, VOID_ELEMENTS)) {
+      serializer.nodes(self.serialized_children());
+    }
+    serializer.out
+  }
+
+  fn outer_html(&self) -> String {
+    let mut serializer = self.serializer();
+    serializer.nodes(std::iter::once(*self));
+    serializer.out
+  }
+}
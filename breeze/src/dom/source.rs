@@ -0,0 +1,33 @@
+use std::ops::Range;
+
+/// Line and column in the source, both 1-based, columns count characters
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct LineColumn {
+  pub line: usize,
+  pub column: usize,
+}
+
+/// Location of a piece of markup in the source
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SourceSpan {
+  /// Byte range in the (decoded) source
+  pub range: Range<usize>,
+  pub start: LineColumn,
+  pub end: LineColumn,
+}
+
+/// Where an element came from, elements implied by the parser don't have one
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ElementSource {
+  pub start_tag: SourceSpan,
+  /// `None` if the element was closed implicitly
+  pub end_tag: Option<SourceSpan>,
+  /// Spans of `name="value"` pairs in source order
+  pub attributes: Vec<(String, SourceSpan)>,
+}
+
+impl ElementSource {
+  pub fn attribute(&self, name: &str) -> Option<&SourceSpan> {
+    self.attributes.iter().find(|(x, _)| x == name).map(|(_, span)| span)
+  }
+}
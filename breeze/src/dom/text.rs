@@ -0,0 +1,238 @@
+//! Text of a subtree, as stored (`text_content`) and as rendered (`inner_text`)
+//!
+//! There is no style system yet, so rendering follows the user agent stylesheet defaults for html elements,
+//! the `hidden` attribute and `display`/`white-space` declarations in `style` attributes
+
+use super::{Edge, ElementNode, Namespace, Node, NodeIteratorExt, NodeMut, NodeRef, TextNode};
+
+/// Elements that aren't rendered by default
+const HIDDEN_ELEMENTS: &[&str] = &[
+  "area", "base", "basefont", "datalist", "head", "link", "meta", "noembed", "noframes", "param", "rp", "script",
+  "style", "template", "title",
+];
+
+/// Elements that are block-level by default
+const BLOCK_ELEMENTS: &[&str] = &[
+  "address", "article", "aside", "blockquote", "body", "caption", "center", "dd", "details", "dialog", "dir", "div",
+  "dl", "dt", "fieldset", "figcaption", "figure", "footer", "form", "h1", "h2", "h3", "h4", "h5", "h6", "header",
+  "hgroup", "hr", "html", "legend", "li", "listing", "main", "menu", "nav", "ol", "p", "plaintext", "pre", "search",
+  "section", "summary", "table", "ul", "xmp",
+];
+
+/// Elements that keep whitespace by default
+const PRE_ELEMENTS: &[&str] = &["listing", "plaintext", "pre", "textarea", "xmp"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Display {
+  None,
+  Inline,
+  Block,
+  TableRow,
+  TableCell,
+}
+
+/// Value of the last declaration of a property in the `style` attribute
+fn inline_style<'a>(element: &'a ElementNode, property: &str) -> Option<&'a str> {
+  element.attribute("style")?.split(';').rev().find_map(|declaration| {
+    let (name, value) = declaration.split_once(':')?;
+    let value = value.trim();
+    let value = value.strip_suffix("!important").unwrap_or(value).trim();
+    name.trim().eq_ignore_ascii_case(property).then_some(value)
+  })
+}
+
+fn html_element_is(element: &ElementNode, names: &[&str]) -> bool {
+  element.namespace == Namespace::Html && names.contains(&element.tag_name.as_str())
+}
+
+fn display(element: &ElementNode) -> Display {
+  if let Some(display) = inline_style(element, "display") {
+    return match display.to_ascii_lowercase().as_str() {
+      "none" => Display::None,
+      "table-row" => Display::TableRow,
+      "table-cell" => Display::TableCell,
+      "inline" | "inline-block" | "inline-flex" | "inline-grid" | "inline-table" | "contents" | "ruby" => Display::Inline,
+      _ => Display::Block,
+    }
+  }
+  let hidden_input = element.tag_name == "input" && element.attribute("type").is_some_and(|x| x.eq_ignore_ascii_case("hidden"));
+  match () {
+    _ if element.namespace != Namespace::Html => Display::Inline,
+    _ if element.attribute("hidden").is_some() || hidden_input || html_element_is(element, HIDDEN_ELEMENTS) => Display::None,
+    _ if html_element_is(element, BLOCK_ELEMENTS) => Display::Block,
+    _ if element.tag_name == "tr" => Display::TableRow,
+    _ if matches!(element.tag_name.as_str(), "td" | "th") => Display::TableCell,
+    _ => Display::Inline,
+  }
+}
+
+/// Whether the element keeps whitespace, `None` if it inherits it
+fn preserves_whitespace(element: &ElementNode) -> Option<bool> {
+  match inline_style(element, "white-space").map(|x| x.to_ascii_lowercase()).as_deref() {
+    Some("pre" | "pre-wrap" | "break-spaces") => Some(true),
+    Some("normal" | "nowrap" | "pre-line") => Some(false),
+    _ => html_element_is(element, PRE_ELEMENTS).then_some(true),
+  }
+}
+
+/// Builds rendered text, collapsing whitespace and merging line breaks between blocks
+#[derive(Default)]
+struct TextCollector {
+  out: String,
+  /// Collapsed whitespace, written only if more text follows on the same line
+  space: bool,
+  /// Required line breaks, the most requested are written if more text follows
+  line_breaks: usize,
+}
+
+impl TextCollector {
+  /// Whitespace is dropped at the start of lines and table cells
+  fn at_line_start(&self) -> bool {
+    self.line_breaks > 0 || self.out.is_empty() || self.out.ends_with(['\n', '\t'])
+  }
+
+  fn flush(&mut self) {
+    if self.line_breaks > 0 && !self.out.is_empty() {
+      self.out.extend(std::iter::repeat_n('\n', self.line_breaks));
+      self.space = false;
+    }
+    self.line_breaks = 0;
+    if std::mem::take(&mut self.space) {
+      self.out.push(' ');
+    }
+  }
+
+  fn text(&mut self, text: &str, preserve: bool) {
+    if preserve {
+      if !text.is_empty() {
+        self.flush();
+        self.out.push_str(text);
+      }
+      return
+    }
+    for c in text.chars() {
+      match c {
+        ' ' | '\t' | '\n' | '\r' | '\x0c' => self.space |= !self.at_line_start(),
+        c => {
+          self.flush();
+          self.out.push(c);
+        },
+      }
+    }
+  }
+
+  /// Text that ends the line or cell, so whitespace before it is dropped
+  fn separator(&mut self, separator: char) {
+    self.space = false;
+    self.flush();
+    self.out.push(separator);
+  }
+
+  fn require_line_breaks(&mut self, count: usize) {
+    self.space = false;
+    self.line_breaks = self.line_breaks.max(count);
+  }
+}
+
+fn has_next_cell(cell: NodeRef) -> bool {
+  cell.following_siblings().elements().any(|x| x.element().is_some_and(|x| display(x) == Display::TableCell))
+}
+
+/// Whether a table row is followed by another row of the same table
+fn has_next_row(row: NodeRef) -> bool {
+  let is_row = |x: &NodeRef| x.element().is_some_and(|x| x.tag_name == "tr");
+  row.following_siblings().elements().any(|x| is_row(&x)) || row.parent().is_some_and(|section| {
+    section.element().is_some_and(|x| matches!(x.tag_name.as_str(), "tbody" | "thead" | "tfoot"))
+      && section.following_siblings().elements().any(|x| is_row(&x) || x.children().any(|x| is_row(&x)))
+  })
+}
+
+impl NodeRef<'_> {
+  /// Text of all descendant text nodes in document order, or the data of text, comment and processing instruction nodes
+  pub fn text_content(&self) -> String {
+    match self.value() {
+      Node::Text(text) => text.text.clone(),
+      Node::Comment(comment) => comment.text.clone(),
+      Node::ProcessingInstruction(instruction) => instruction.data.clone(),
+      Node::Doctype(_) => String::new(),
+      Node::Element(_) | Node::DocumentFragment(_) => self.descendants().filter_map(|x| match x.value() {
+        Node::Text(text) => Some(text.text.as_str()),
+        _ => None,
+      }).collect(),
+    }
+  }
+
+  /// Text as it would be rendered: hidden elements are skipped, whitespace is collapsed,
+  /// and blocks, `<br>` and table rows and cells are separated\
+  /// Like [`text_content`](Self::text_content) if the node itself isn't rendered
+  pub fn inner_text(&self) -> String {
+    let rendered = std::iter::once(*self).chain(self.ancestors())
+      .all(|x| !x.element().is_some_and(|x| display(x) == Display::None));
+    if !rendered || self.element().is_none() {
+      return self.text_content()
+    }
+    let mut collector = TextCollector::default();
+    //whitespace handling of the elements being traversed, it is inherited from ancestors
+    let own = std::iter::once(*self).chain(self.ancestors()).find_map(|x| x.element().and_then(preserves_whitespace));
+    let mut preserve = vec![own.unwrap_or(false)];
+    let mut traverse = self.traverse();
+    traverse.next();
+    while let Some(edge) = traverse.next() {
+      match edge {
+        Edge::Enter(node) => match node.value() {
+          Node::Text(text) => collector.text(&text.text, *preserve.last().unwrap()),
+          Node::Element(element) => match display(element) {
+            Display::None => traverse.skip_children(),
+            _ if html_element_is(element, &["br"]) => collector.separator('\n'),
+            display => {
+              preserve.push(preserves_whitespace(element).unwrap_or(*preserve.last().unwrap()));
+              if display == Display::Block {
+                collector.require_line_breaks(if element.tag_name == "p" { 2 } else { 1 });
+              }
+            },
+          },
+          _ => (),
+        },
+        Edge::Leave(node) if node == *self => (),
+        Edge::Leave(node) => {
+          let Some(element) = node.element() else { continue };
+          match display(element) {
+            Display::None => continue,
+            _ if html_element_is(element, &["br"]) => continue,
+            Display::Block => collector.require_line_breaks(if element.tag_name == "p" { 2 } else { 1 }),
+            Display::TableCell if has_next_cell(node) => collector.separator('\t'),
+            Display::TableRow if has_next_row(node) => collector.separator('\n'),
+            _ => (),
+          }
+          preserve.pop();
+        },
+      }
+    }
+    collector.out
+  }
+}
+
+impl NodeMut<'_> {
+  /// Replace the children with a single text node (none if `text` is empty), the old ones are freed,
+  /// or set the data of text, comment and processing instruction nodes\
+  /// Observers are notified either way, the document itself can't hold text so this does nothing for it
+  pub fn set_text_content(&mut self, text: &str) {
+    let id = self.id();
+    let dom = self.dom();
+    if dom.is_document(id) {
+      return
+    }
+    if let Node::Element(_) | Node::DocumentFragment(_) = dom[id] {
+      let node = (!text.is_empty()).then(|| dom.create_node(Node::Text(TextNode { text: text.to_string(), source: None })));
+      dom.replace_all(id, node).unwrap();
+      return
+    }
+    let old_value = match &mut dom[id] {
+      Node::Text(node) => std::mem::replace(&mut node.text, text.to_string()),
+      Node::Comment(node) => std::mem::replace(&mut node.text, text.to_string()),
+      Node::ProcessingInstruction(node) => std::mem::replace(&mut node.data, text.to_string()),
+      _ => return,
+    };
+    dom.queue_character_data(id, old_value);
+  }
+}
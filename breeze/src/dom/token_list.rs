@@ -0,0 +1,140 @@
+//! Sets of space-separated tokens stored in an attribute, like DOM `DOMTokenList`
+
+use super::{NodeMut, TokenListError};
+
+/// Tokens of an attribute such as `class`, `rel` or `sandbox`, as an ordered set\
+/// Changes are written back to the attribute with [`NodeMut::set_attribute`], which also updates the element's cache
+pub struct TokenList<'a> {
+  node: NodeMut<'a>,
+  attribute: String,
+}
+
+/// Ordered set of the tokens in an attribute value
+fn parse(value: &str) -> Vec<&str> {
+  let mut tokens = Vec::new();
+  for token in value.split_ascii_whitespace() {
+    if !tokens.contains(&token) {
+      tokens.push(token);
+    }
+  }
+  tokens
+}
+
+fn validate(token: &str) -> Result<(), TokenListError> {
+  if token.is_empty() {
+    return Err(TokenListError::Empty)
+  }
+  if token.bytes().any(|x| x.is_ascii_whitespace()) {
+    return Err(TokenListError::Whitespace)
+  }
+  Ok(())
+}
+
+impl<'a> TokenList<'a> {
+  /// Current value of the attribute, if it is set
+  pub fn value(&self) -> Option<&str> {
+    self.node.node().element()?.attribute(&self.attribute)
+  }
+
+  pub fn tokens(&self) -> Vec<&str> {
+    parse(self.value().unwrap_or_default())
+  }
+
+  pub fn len(&self) -> usize {
+    self.tokens().len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.tokens().is_empty()
+  }
+
+  pub fn item(&self, index: usize) -> Option<&str> {
+    self.tokens().get(index).copied()
+  }
+
+  pub fn contains(&self, token: &str) -> bool {
+    self.tokens().contains(&token)
+  }
+
+  fn owned_tokens(&self) -> Vec<String> {
+    self.tokens().into_iter().map(str::to_string).collect()
+  }
+
+  /// Write the tokens back, an absent attribute stays absent if there are none
+  fn update(&mut self, tokens: Vec<String>) {
+    if tokens.is_empty() && self.value().is_none() {
+      return
+    }
+    self.node.set_attribute(&self.attribute, Some(tokens.join(" ")));
+  }
+
+  /// Add the tokens that aren't in the set yet
+  pub fn add(&mut self, tokens: &[&str]) -> Result<(), TokenListError> {
+    tokens.iter().try_for_each(|x| validate(x))?;
+    let mut set = self.owned_tokens();
+    for token in tokens {
+      if !set.iter().any(|x| x == token) {
+        set.push(token.to_string());
+      }
+    }
+    self.update(set);
+    Ok(())
+  }
+
+  pub fn remove(&mut self, tokens: &[&str]) -> Result<(), TokenListError> {
+    tokens.iter().try_for_each(|x| validate(x))?;
+    let mut set = self.owned_tokens();
+    set.retain(|x| !tokens.contains(&x.as_str()));
+    self.update(set);
+    Ok(())
+  }
+
+  /// Remove the token if it's there and add it otherwise, or only add/remove it if `force` is given\
+  /// Returns whether the token is now in the set
+  pub fn toggle(&mut self, token: &str, force: Option<bool>) -> Result<bool, TokenListError> {
+    validate(token)?;
+    let mut set = self.owned_tokens();
+    let present = set.iter().any(|x| x == token);
+    match (present, force) {
+      (true, None | Some(false)) => set.retain(|x| x != token),
+      (false, None | Some(true)) => set.push(token.to_string()),
+      (present, _) => return Ok(present),
+    }
+    self.update(set);
+    Ok(!present)
+  }
+
+  /// Replace `token` with `new_token`, keeping its position\
+  /// Returns false if `token` wasn't in the set
+  pub fn replace(&mut self, token: &str, new_token: &str) -> Result<bool, TokenListError> {
+    validate(token)?;
+    validate(new_token)?;
+    let set = self.owned_tokens();
+    if !set.iter().any(|x| x == token) {
+      return Ok(false)
+    }
+    //the first of the two takes the place, any other copy goes
+    let mut replaced = false;
+    let set = set.into_iter().filter_map(|x| match x == token || x == new_token {
+      true if replaced => None,
+      true => { replaced = true; Some(new_token.to_string()) },
+      false => Some(x),
+    }).collect();
+    self.update(set);
+    Ok(true)
+  }
+}
+
+impl NodeMut<'_> {
+  /// Tokens of an attribute of an element, `None` for other nodes
+  pub fn token_list(&mut self, attribute: &str) -> Option<TokenList<'_>> {
+    self.node().element()?;
+    let id = self.id();
+    Some(TokenList { node: self.dom().node_mut(id), attribute: attribute.to_string() })
+  }
+
+  /// Classes of an element, `None` for other nodes
+  pub fn class_list(&mut self) -> Option<TokenList<'_>> {
+    self.token_list("class")
+  }
+}
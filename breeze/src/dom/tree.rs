@@ -0,0 +1,659 @@
+//! Arena storage for the nodes of a [`Dom`]
+//!
+//! Nodes live in one vector owned by the document and refer to each other by
+//! [`NodeId`], so walking the tree needs no reference counting or runtime
+//! borrow checks, and dropping a document frees every node at once.
+//!
+//! Slots of nodes that were freed (see [`NodeMut::remove`]) are reused. Ids carry the generation of their slot,
+//! so an id of a freed node never refers to the node that took its place.
+
+use std::{num::NonZeroU32, ops::{Deref, Index, IndexMut}};
+use super::{Dom, Node, ElementNode, DocumentFragmentNode, DomPushError};
+
+mod traverse;
+mod ids;
+pub(crate) use ids::IdIndex;
+pub use traverse::{Edge, NodeFilter, Traverse, Descendants, PostOrder, Links, TreeWalker, NodeIteratorExt, Elements};
+
+/// Handle to a node of a [`Dom`]
+///
+/// Ids are only meaningful to the document that created them, and stop being valid once the node is freed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId {
+  index: NonZeroU32,
+  generation: u32,
+}
+
+impl NodeId {
+  pub(super) fn from_index(index: usize) -> Self {
+    let index = u32::try_from(index + 1).expect("too many nodes");
+    Self { index: NonZeroU32::new(index).unwrap(), generation: 0 }
+  }
+
+  fn index(self) -> usize {
+    self.index.get() as usize - 1
+  }
+}
+
+/// A node and its links to the rest of the tree
+#[derive(Debug)]
+pub(crate) struct NodeData {
+  pub node: Node,
+  pub parent: Option<NodeId>,
+  pub previous_sibling: Option<NodeId>,
+  pub next_sibling: Option<NodeId>,
+  pub first_child: Option<NodeId>,
+  pub last_child: Option<NodeId>,
+  /// Times the slot was freed, ids of earlier nodes in it have a lower generation
+  generation: u32,
+}
+
+impl NodeData {
+  pub(super) fn new(node: Node) -> Self {
+    Self {
+      node,
+      parent: None,
+      previous_sibling: None,
+      next_sibling: None,
+      first_child: None,
+      last_child: None,
+      generation: 0,
+    }
+  }
+}
+
+/// Only elements and document fragments can have children
+fn can_have_children(node: &Node) -> bool {
+  matches!(node, Node::Element(_) | Node::DocumentFragment(_))
+}
+
+impl Dom {
+  /// Panics if the node was freed
+  pub(crate) fn data(&self, id: NodeId) -> &NodeData {
+    let data = &self.nodes[id.index()];
+    assert!(data.generation == id.generation, "node was freed");
+    data
+  }
+
+  fn data_mut(&mut self, id: NodeId) -> &mut NodeData {
+    let data = &mut self.nodes[id.index()];
+    assert!(data.generation == id.generation, "node was freed");
+    data
+  }
+
+  /// Add a detached node to the document
+  pub fn create_node(&mut self, node: Node) -> NodeId {
+    let contents = match &node {
+      Node::Element(element) => element.template_contents,
+      _ => None,
+    };
+    let id = self.alloc(node);
+    if let Some(Node::DocumentFragment(fragment)) = contents.map(|x| &mut self.nodes[x.index()].node) {
+      fragment.host = Some(id);
+    }
+    id
+  }
+
+  /// Put a node in a free slot, or a new one
+  fn alloc(&mut self, node: Node) -> NodeId {
+    match self.free.pop() {
+      Some(id) => {
+        self.nodes[id.index()] = NodeData { generation: id.generation, ..NodeData::new(node) };
+        id
+      },
+      None => {
+        self.nodes.push(NodeData::new(node));
+        NodeId::from_index(self.nodes.len() - 1)
+      },
+    }
+  }
+
+  /// Number of nodes in the document, including detached ones that weren't freed
+  pub fn node_count(&self) -> usize {
+    self.nodes.len() - self.free.len()
+  }
+
+  /// The node and all of its descendants, including the contents of templates, parents first
+  fn subtree(&self, node: NodeId) -> Vec<NodeId> {
+    let mut nodes = vec![node];
+    let mut index = 0;
+    while let Some(&node) = nodes.get(index) {
+      let node = NodeRef { dom: self, id: node };
+      nodes.extend(node.children().map(|x| x.id));
+      if let Some(contents) = node.template_contents() {
+        nodes.push(contents.id);
+      }
+      index += 1;
+    }
+    nodes
+  }
+
+  /// Free a detached node and its descendants, so their slots can be reused
+  fn free(&mut self, node: NodeId) {
+    debug_assert!(self.data(node).parent.is_none(), "freeing an attached node");
+    for id in self.subtree(node) {
+      //a text node without text doesn't allocate
+      let data = NodeData::new(Node::Text(Default::default()));
+      //a slot whose generation would wrap around is retired, ids of its old nodes would be valid again otherwise
+      let Some(generation) = id.generation.checked_add(1) else {
+        self.nodes[id.index()] = NodeData { generation: id.generation, ..data };
+        continue
+      };
+      self.nodes[id.index()] = NodeData { generation, ..data };
+      self.free.push(NodeId { generation, ..id });
+    }
+  }
+
+  /// Add an empty, detached document fragment to the document
+  pub fn create_fragment(&mut self) -> NodeId {
+    self.create_node(Node::DocumentFragment(DocumentFragmentNode::default()))
+  }
+
+  /// Root of the tree, an element for documents and a fragment for parsed fragments
+  pub fn root(&self) -> NodeRef<'_> {
+    self.node(self.tree)
+  }
+
+  pub fn root_mut(&mut self) -> NodeMut<'_> {
+    self.node_mut(self.tree)
+  }
+
+  /// Panics if the node doesn't belong to this document
+  pub fn node(&self, id: NodeId) -> NodeRef<'_> {
+    assert!(id.index() < self.nodes.len(), "node does not belong to this document");
+    NodeRef { dom: self, id }
+  }
+
+  /// Panics if the node doesn't belong to this document
+  pub fn node_mut(&mut self, id: NodeId) -> NodeMut<'_> {
+    assert!(id.index() < self.nodes.len(), "node does not belong to this document");
+    NodeMut { dom: self, id }
+  }
+
+  /// Link a detached node as the last child of `parent`\
+  /// Like the other raw link changes this drops the id index, unless it was taken out with `take_ids`
+  pub(crate) fn link_child(&mut self, parent: NodeId, child: NodeId) {
+    self.ids.take();
+    let previous = self.data(parent).last_child;
+    match previous {
+      Some(previous) => self.data_mut(previous).next_sibling = Some(child),
+      None => self.data_mut(parent).first_child = Some(child),
+    }
+    self.data_mut(parent).last_child = Some(child);
+    let data = self.data_mut(child);
+    data.parent = Some(parent);
+    data.previous_sibling = previous;
+    data.next_sibling = None;
+  }
+
+  /// Link a detached node before `reference`, a child of `parent`
+  pub(crate) fn link_before(&mut self, parent: NodeId, child: NodeId, reference: NodeId) {
+    self.ids.take();
+    debug_assert_eq!(self.data(reference).parent, Some(parent), "reference is not a child");
+    let previous = self.data(reference).previous_sibling;
+    match previous {
+      Some(previous) => self.data_mut(previous).next_sibling = Some(child),
+      None => self.data_mut(parent).first_child = Some(child),
+    }
+    self.data_mut(reference).previous_sibling = Some(child);
+    let data = self.data_mut(child);
+    data.parent = Some(parent);
+    data.previous_sibling = previous;
+    data.next_sibling = Some(reference);
+  }
+
+  /// Remove a node from its parent (if any), its own children stay with it
+  pub(crate) fn unlink(&mut self, node: NodeId) {
+    self.ids.take();
+    let data = self.data_mut(node);
+    let Some(parent) = data.parent.take() else { return };
+    let previous = data.previous_sibling.take();
+    let next = data.next_sibling.take();
+    match previous {
+      Some(previous) => self.data_mut(previous).next_sibling = next,
+      None => self.data_mut(parent).first_child = next,
+    }
+    match next {
+      Some(next) => self.data_mut(next).previous_sibling = previous,
+      None => self.data_mut(parent).last_child = previous,
+    }
+  }
+
+  /// Move all children of `from` to the end of `to`
+  pub(crate) fn move_children(&mut self, from: NodeId, to: NodeId) {
+    while let Some(child) = self.data(from).first_child {
+      self.unlink(child);
+      self.link_child(to, child);
+    }
+  }
+
+  /// Move the children of the root of `other` into this document, returning their new ids\
+  /// They're detached, the root itself and nodes outside of it are dropped
+  ///
+  /// The nodes keep their links, ids from `other` are not valid in this document.
+  pub fn adopt(&mut self, other: Dom) -> Vec<NodeId> {
+    let subtree = other.subtree(other.tree);
+    let roots: Vec<_> = other.node(other.tree).children().map(|x| x.id).collect();
+    let mut nodes: Vec<_> = other.nodes.into_iter().map(Some).collect();
+    let mut new_ids = vec![None; nodes.len()];
+    let mut adopted = Vec::with_capacity(subtree.len() - 1);
+    for old in &subtree[1..] {
+      let mut data = nodes[old.index()].take().unwrap();
+      let id = self.alloc(std::mem::replace(&mut data.node, Node::Text(Default::default())));
+      new_ids[old.index()] = Some(id);
+      adopted.push((id, data));
+    }
+    //links to the root and to dropped nodes are cut
+    let remap = |id: Option<NodeId>| id.and_then(|x| new_ids[x.index()]);
+    for (id, links) in adopted {
+      let data = &mut self.nodes[id.index()];
+      data.parent = remap(links.parent);
+      data.previous_sibling = remap(links.previous_sibling);
+      data.next_sibling = remap(links.next_sibling);
+      data.first_child = remap(links.first_child);
+      data.last_child = remap(links.last_child);
+      match &mut data.node {
+        Node::Element(element) => element.template_contents = remap(element.template_contents),
+        Node::DocumentFragment(fragment) => fragment.host = remap(fragment.host),
+        _ => (),
+      }
+    }
+    let roots: Vec<_> = roots.into_iter().map(|x| remap(Some(x)).unwrap()).collect();
+    for root in &roots {
+      let data = &mut self.nodes[root.index()];
+      data.previous_sibling = None;
+      data.next_sibling = None;
+    }
+    roots
+  }
+
+  /// Template element whose contents are `fragment`
+  fn template_host(&self, fragment: NodeId) -> Option<NodeId> {
+    match &self[fragment] {
+      Node::DocumentFragment(fragment) => fragment.host,
+      _ => None,
+    }
+  }
+
+  /// Whether `node` is `other` or one of its ancestors, template contents count as descendants of the template
+  fn is_inclusive_ancestor(&self, node: NodeId, other: NodeId) -> bool {
+    let mut current = Some(other);
+    while let Some(id) = current {
+      if id == node {
+        return true
+      }
+      current = match self.data(id).parent {
+        None if matches!(self[id], Node::DocumentFragment(_)) => self.template_host(id),
+        parent => parent,
+      };
+    }
+    false
+  }
+
+  /// Whether `node` is the root of a document, not of a parsed fragment
+  pub(super) fn is_document(&self, node: NodeId) -> bool {
+    node == self.tree && matches!(self[node], Node::Element(_))
+  }
+
+  /// Check that `node` can be inserted into `parent` before `reference`\
+  /// Doctypes only go in the document, at most one of them, and text can't be put in the document directly
+  fn check_insert(&self, parent: NodeId, node: NodeId, reference: Option<NodeId>) -> Result<(), DomPushError> {
+    if !can_have_children(&self[parent]) {
+      return Err(DomPushError::NodeInfertile)
+    }
+    if reference.is_some_and(|reference| self.data(reference).parent != Some(parent)) {
+      return Err(DomPushError::NotAChild)
+    }
+    if node == self.tree || self.is_inclusive_ancestor(node, parent) {
+      return Err(DomPushError::HierarchyRequest)
+    }
+    let nodes: Vec<_> = match self[node] {
+      Node::DocumentFragment(_) => NodeRef { dom: self, id: node }.children().collect(),
+      _ => vec![NodeRef { dom: self, id: node }],
+    };
+    let document = self.is_document(parent);
+    let doctypes = nodes.iter().filter(|x| matches!(x.value(), Node::Doctype(_))).count();
+    let has_doctype = NodeRef { dom: self, id: parent }.children()
+      .any(|x| x.id != node && matches!(x.value(), Node::Doctype(_)));
+    if (doctypes > 0 && (!document || has_doctype || doctypes > 1)) ||
+      (document && nodes.iter().any(|x| matches!(x.value(), Node::Text(_))))
+    {
+      return Err(DomPushError::HierarchyRequest)
+    }
+    Ok(())
+  }
+
+  /// Insert `node` as a child of `parent`, before `reference` or at the end, moving it from its old parent\
+  /// Inserting a document fragment moves all of its children instead
+  fn insert(&mut self, parent: NodeId, node: NodeId, reference: Option<NodeId>) -> Result<(), DomPushError> {
+    self.check_insert(parent, node, reference)?;
+    //inserting a node before itself leaves it where it is
+    let reference = match reference {
+      Some(reference) if reference == node => self.data(node).next_sibling,
+      reference => reference,
+    };
+    let nodes = self.take_inserted(node);
+    self.link_nodes(parent, &nodes, reference);
+    if let (Some(first), Some(last)) = (nodes.first(), nodes.last()) {
+      let (previous, next) = (self.data(*first).previous_sibling, self.data(*last).next_sibling);
+      self.queue_child_list(parent, nodes, Vec::new(), previous, next);
+    }
+    Ok(())
+  }
+
+  /// Replace all children of `parent` with `node` (or the children of a fragment), freeing the old ones\
+  /// Observers get a single record of both the removed and added nodes
+  pub(crate) fn replace_all(&mut self, parent: NodeId, node: Option<NodeId>) -> Result<(), DomPushError> {
+    if let Some(node) = node {
+      self.check_insert(parent, node, None)?;
+    }
+    let removed: Vec<_> = NodeRef { dom: self, id: parent }.children().map(|x| x.id).collect();
+    let added = node.map(|node| self.take_inserted(node)).unwrap_or_default();
+    for &child in &removed {
+      self.detach_node(child);
+    }
+    self.link_nodes(parent, &added, None);
+    self.queue_child_list(parent, added, removed.clone(), None, None);
+    for child in removed {
+      self.free(child);
+    }
+    Ok(())
+  }
+
+  /// Nodes that inserting `node` adds, the children of a fragment or the node itself\
+  /// Records them being taken from where they are
+  fn take_inserted(&mut self, node: NodeId) -> Vec<NodeId> {
+    match self[node] {
+      Node::DocumentFragment(_) => {
+        let nodes: Vec<_> = NodeRef { dom: self, id: node }.children().map(|x| x.id).collect();
+        self.queue_child_list(node, Vec::new(), nodes.clone(), None, None);
+        nodes
+      },
+      _ => {
+        self.queue_removal(node);
+        vec![node]
+      },
+    }
+  }
+
+  /// Move `nodes` to `parent`, before `reference` or at the end, keeping the id index up to date
+  fn link_nodes(&mut self, parent: NodeId, nodes: &[NodeId], reference: Option<NodeId>) {
+    let mut ids = self.take_ids();
+    for &node in nodes {
+      let was_connected = ids.is_some() && self.is_connected(node);
+      self.unlink(node);
+      match reference {
+        Some(reference) => self.link_before(parent, node, reference),
+        None => self.link_child(parent, node),
+      }
+      self.update_ids(&mut ids, node, was_connected);
+    }
+    self.restore_ids(ids);
+  }
+
+  /// Record a node being removed from its parent, if it has one
+  fn queue_removal(&mut self, node: NodeId) {
+    let data = self.data(node);
+    if let Some(parent) = data.parent {
+      let (previous, next) = (data.previous_sibling, data.next_sibling);
+      self.queue_child_list(parent, Vec::new(), vec![node], previous, next);
+    }
+  }
+
+  /// Remove a node from its parent, keeping the id index up to date
+  fn remove(&mut self, node: NodeId) {
+    self.queue_removal(node);
+    self.detach_node(node);
+  }
+
+  /// Like [`Dom::remove`], without recording the mutation
+  fn detach_node(&mut self, node: NodeId) {
+    let mut ids = self.take_ids();
+    let was_connected = ids.is_some() && self.is_connected(node);
+    self.unlink(node);
+    self.update_ids(&mut ids, node, was_connected);
+    self.restore_ids(ids);
+  }
+}
+
+impl Index<NodeId> for Dom {
+  type Output = Node;
+  fn index(&self, id: NodeId) -> &Node {
+    &self.data(id).node
+  }
+}
+
+/// Mutable access to an element drops the id index, as its id may be changed through it
+impl IndexMut<NodeId> for Dom {
+  fn index_mut(&mut self, id: NodeId) -> &mut Node {
+    if let Node::Element(_) = self[id] {
+      self.ids.take();
+    }
+    &mut self.data_mut(id).node
+  }
+}
+
+/// Shared reference to a node and the document it's in
+#[derive(Clone, Copy)]
+pub struct NodeRef<'a> {
+  dom: &'a Dom,
+  id: NodeId,
+}
+
+impl<'a> NodeRef<'a> {
+  pub fn id(&self) -> NodeId {
+    self.id
+  }
+
+  pub fn dom(&self) -> &'a Dom {
+    self.dom
+  }
+
+  pub fn value(&self) -> &'a Node {
+    &self.dom[self.id]
+  }
+
+  pub fn element(&self) -> Option<&'a ElementNode> {
+    match self.value() {
+      Node::Element(element) => Some(element),
+      _ => None,
+    }
+  }
+
+  fn related(&self, id: Option<NodeId>) -> Option<NodeRef<'a>> {
+    id.map(|id| NodeRef { dom: self.dom, id })
+  }
+
+  pub fn parent(&self) -> Option<NodeRef<'a>> {
+    self.related(self.dom.data(self.id).parent)
+  }
+
+  /// Children in order, template contents aren't children of the template
+  pub fn children(&self) -> Children<'a> {
+    let data = self.dom.data(self.id);
+    Children {
+      dom: self.dom,
+      next: data.first_child,
+      next_back: data.last_child,
+    }
+  }
+
+  pub fn first_child(&self) -> Option<NodeRef<'a>> {
+    self.related(self.dom.data(self.id).first_child)
+  }
+
+  pub fn last_child(&self) -> Option<NodeRef<'a>> {
+    self.related(self.dom.data(self.id).last_child)
+  }
+
+  pub fn previous_sibling(&self) -> Option<NodeRef<'a>> {
+    self.related(self.dom.data(self.id).previous_sibling)
+  }
+
+  pub fn next_sibling(&self) -> Option<NodeRef<'a>> {
+    self.related(self.dom.data(self.id).next_sibling)
+  }
+
+  /// Contents of a `<template>` element
+  pub fn template_contents(&self) -> Option<NodeRef<'a>> {
+    match self.value() {
+      Node::Element(element) => self.related(element.template_contents),
+      _ => None,
+    }
+  }
+}
+
+impl Deref for NodeRef<'_> {
+  type Target = Node;
+  fn deref(&self) -> &Node {
+    self.value()
+  }
+}
+
+/// Nodes are compared by identity, not by value
+impl PartialEq for NodeRef<'_> {
+  fn eq(&self, other: &Self) -> bool {
+    std::ptr::eq(self.dom, other.dom) && self.id == other.id
+  }
+}
+
+impl Eq for NodeRef<'_> {}
+
+impl std::fmt::Debug for NodeRef<'_> {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("NodeRef").field("id", &self.id).field("value", self.value()).finish()
+  }
+}
+
+/// Iterator over the children of a node
+pub struct Children<'a> {
+  dom: &'a Dom,
+  next: Option<NodeId>,
+  next_back: Option<NodeId>,
+}
+
+impl<'a> Children<'a> {
+  fn yield_id(&mut self, id: NodeId) -> NodeRef<'a> {
+    //both ends met, the iterator is done
+    if self.next == self.next_back {
+      self.next = None;
+      self.next_back = None;
+    }
+    NodeRef { dom: self.dom, id }
+  }
+}
+
+impl<'a> Iterator for Children<'a> {
+  type Item = NodeRef<'a>;
+  fn next(&mut self) -> Option<NodeRef<'a>> {
+    let id = self.next?;
+    let node = self.yield_id(id);
+    if self.next.is_some() {
+      self.next = self.dom.data(id).next_sibling;
+    }
+    Some(node)
+  }
+}
+
+impl DoubleEndedIterator for Children<'_> {
+  fn next_back(&mut self) -> Option<Self::Item> {
+    let id = self.next_back?;
+    let node = self.yield_id(id);
+    if self.next_back.is_some() {
+      self.next_back = self.dom.data(id).previous_sibling;
+    }
+    Some(node)
+  }
+}
+
+/// Exclusive reference to a node, through which the document can be changed
+pub struct NodeMut<'a> {
+  dom: &'a mut Dom,
+  id: NodeId,
+}
+
+impl NodeMut<'_> {
+  pub fn id(&self) -> NodeId {
+    self.id
+  }
+
+  /// Shared reference to the same node
+  pub fn node(&self) -> NodeRef<'_> {
+    NodeRef { dom: self.dom, id: self.id }
+  }
+
+  pub fn value(&mut self) -> &mut Node {
+    &mut self.dom[self.id]
+  }
+
+  pub(crate) fn dom(&mut self) -> &mut Dom {
+    self.dom
+  }
+
+  /// Append a detached `node` to the children of this node\
+  /// Pushing a document fragment moves all of its children instead
+  pub fn push(&mut self, node: NodeId) -> Result<(), DomPushError> {
+    if self.dom.data(node).parent.is_some() {
+      return Err(DomPushError::AlreadyHasParent)
+    }
+    self.dom.insert(self.id, node, None)
+  }
+
+  /// Append `node` to the children of this node, moving it from its old parent
+  pub fn append(&mut self, node: NodeId) -> Result<(), DomPushError> {
+    self.dom.insert(self.id, node, None)
+  }
+
+  /// Insert `node` before the first child of this node, moving it from its old parent
+  pub fn prepend(&mut self, node: NodeId) -> Result<(), DomPushError> {
+    let first = self.dom.data(self.id).first_child;
+    self.dom.insert(self.id, node, first)
+  }
+
+  /// Insert `node` before `reference`, a child of this node, or at the end if there's no reference
+  pub fn insert_before(&mut self, node: NodeId, reference: Option<NodeId>) -> Result<(), DomPushError> {
+    self.dom.insert(self.id, node, reference)
+  }
+
+  /// Detach `child` from this node, it stays in the document and can be inserted again
+  pub fn remove_child(&mut self, child: NodeId) -> Result<(), DomPushError> {
+    if self.dom.data(child).parent != Some(self.id) {
+      return Err(DomPushError::NotAChild)
+    }
+    self.dom.remove(child);
+    Ok(())
+  }
+
+  /// Put `node` where `child` is and detach `child`
+  pub fn replace_child(&mut self, node: NodeId, child: NodeId) -> Result<(), DomPushError> {
+    if self.dom.data(child).parent != Some(self.id) {
+      return Err(DomPushError::NotAChild)
+    }
+    if node != child {
+      self.dom.insert(self.id, node, Some(child))?;
+      self.dom.remove(child);
+    }
+    Ok(())
+  }
+
+  /// Remove this node from its parent, if it has one\
+  /// The root is never detached
+  pub fn detach(&mut self) {
+    if self.id == self.dom.tree {
+      return
+    }
+    self.dom.remove(self.id);
+  }
+
+  /// Remove this node from its parent and free it along with its descendants\
+  /// Their ids can't be used afterwards, the slots are reused for new nodes
+  ///
+  /// The root and template contents (which are only freed with their template) can't be removed, this does nothing for them
+  pub fn remove(self) {
+    if self.id == self.dom.tree || self.dom.template_host(self.id).is_some() {
+      return
+    }
+    self.dom.remove(self.id);
+    self.dom.free(self.id);
+  }
+}
@@ -0,0 +1,138 @@
+//! Index of the elements in the tree by id
+//!
+//! The index is built on first use, the mutation methods of [`NodeMut`] keep it up to date.
+//! Changes to the tree made while parsing, and `&mut` access to elements (whose ids could change) drop it,
+//! to be rebuilt on the next lookup.
+
+use std::{cmp::Ordering, sync::OnceLock};
+use rustc_hash::FxHashMap;
+use super::{Dom, Node, NodeId, NodeRef, NodeMut};
+
+/// Elements connected to the root, by their id, in tree order
+#[derive(Debug, Default)]
+pub(crate) struct IdIndex(FxHashMap<String, Vec<NodeId>>);
+
+impl IdIndex {
+  fn build(dom: &Dom) -> Self {
+    let mut index = Self::default();
+    index.update_subtree(dom, dom.tree, false, true);
+    index
+  }
+
+  fn add(&mut self, dom: &Dom, id: &str, node: NodeId) {
+    let nodes = self.0.entry(id.to_string()).or_default();
+    //positions are only compared when the id is shared
+    let index = nodes.partition_point(|x| dom.tree_order(*x, node) == Ordering::Less);
+    nodes.insert(index, node);
+  }
+
+  fn remove(&mut self, id: &str, node: NodeId) {
+    if let Some(nodes) = self.0.get_mut(id) {
+      nodes.retain(|x| *x != node);
+      if nodes.is_empty() {
+        self.0.remove(id);
+      }
+    }
+  }
+
+  /// Update the ids of `node` and its descendants after it moved
+  fn update_subtree(&mut self, dom: &Dom, node: NodeId, was_connected: bool, connected: bool) {
+    let node = dom.node(node);
+    for node in std::iter::once(node).chain(node.descendants()) {
+      let Some(id) = node.element().and_then(|x| x.id()) else { continue };
+      //elements moved within the tree only need a new position among others with the same id
+      let shared = self.0.get(id).is_some_and(|x| x.len() > 1);
+      if was_connected && (!connected || shared) {
+        self.remove(id, node.id);
+      }
+      if connected && (!was_connected || shared) {
+        self.add(dom, id, node.id);
+      }
+    }
+  }
+}
+
+impl Dom {
+  /// Take the index out for a mutation that keeps it up to date, see [`Dom::restore_ids`]
+  pub(super) fn take_ids(&mut self) -> Option<IdIndex> {
+    self.ids.take()
+  }
+
+  pub(super) fn restore_ids(&mut self, ids: Option<IdIndex>) {
+    if let Some(ids) = ids {
+      self.ids = OnceLock::from(ids);
+    }
+  }
+
+  /// Whether the node is in the tree under the root
+  pub(super) fn is_connected(&self, node: NodeId) -> bool {
+    let mut node = node;
+    while let Some(parent) = self.data(node).parent {
+      node = parent;
+    }
+    node == self.tree
+  }
+
+  /// Update the index for `node` and its descendants moving in, out of or within the tree
+  pub(super) fn update_ids(&self, ids: &mut Option<IdIndex>, node: NodeId, was_connected: bool) {
+    let connected = self.is_connected(node);
+    if let Some(ids) = ids.as_mut().filter(|_| connected || was_connected) {
+      ids.update_subtree(self, node, was_connected, connected);
+    }
+  }
+
+  /// Positions of the node and its ancestors among their siblings, from the root down
+  fn tree_position(&self, node: NodeId) -> Vec<usize> {
+    let node = self.node(node);
+    let mut position: Vec<_> = std::iter::once(node).chain(node.ancestors())
+      .map(|x| x.preceding_siblings().count())
+      .collect();
+    position.reverse();
+    position
+  }
+
+  fn tree_order(&self, a: NodeId, b: NodeId) -> Ordering {
+    self.tree_position(a).cmp(&self.tree_position(b))
+  }
+
+  /// First element in tree order with the given id
+  pub fn get_element_by_id(&self, id: &str) -> Option<NodeRef<'_>> {
+    let nodes = self.ids.get_or_init(|| IdIndex::build(self)).0.get(id)?;
+    Some(self.node(*nodes.first()?))
+  }
+}
+
+impl NodeMut<'_> {
+  /// Set or remove (with `None`) an attribute of an element, keeping the id index up to date and notifying observers\
+  /// Does nothing for other nodes
+  pub fn set_attribute(&mut self, key: &str, value: Option<String>) {
+    let mut ids = self.dom.take_ids();
+    let connected = ids.is_some() && self.dom.is_connected(self.id);
+    let mut mutation = None;
+    let mut id_change = None;
+    if let Node::Element(element) = &mut self.dom[self.id] {
+      let old_value = element.attribute(key).map(str::to_string);
+      //removing a missing attribute changes nothing
+      if value.is_some() || old_value.is_some() {
+        mutation = Some((element.attribute_key(key).into_owned(), old_value));
+      }
+      let old_id = element.id().map(str::to_string);
+      element.set_attribute(key, value);
+      if element.id() != old_id.as_deref() {
+        id_change = Some((old_id, element.id().map(str::to_string)));
+      }
+    }
+    if let (Some(ids), Some((old_id, new_id)), true) = (ids.as_mut(), id_change, connected) {
+      if let Some(old_id) = old_id {
+        ids.remove(&old_id, self.id);
+      }
+      if let Some(new_id) = new_id {
+        ids.add(self.dom, &new_id, self.id);
+      }
+    }
+    self.dom.restore_ids(ids);
+    if let Some((name, old_value)) = mutation {
+      self.dom.queue_attribute(self.id, name, old_value);
+    }
+  }
+}
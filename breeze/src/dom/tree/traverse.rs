@@ -0,0 +1,194 @@
+//! Iterators over the tree
+//!
+//! None of them recurse, so they are safe on arbitrarily deep documents.
+//! Template contents aren't descendants of the template, see [`NodeRef::template_contents`]
+
+use super::{NodeId, NodeRef, Dom};
+
+/// Step of a depth-first traversal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edge<'a> {
+  /// Entering a node, before any of its descendants
+  Enter(NodeRef<'a>),
+  /// Leaving a node, after all of its descendants
+  Leave(NodeRef<'a>),
+}
+
+/// Result of a [`TreeWalker`] filter, like DOM `NodeFilter`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeFilter {
+  /// Yield the node and visit its children
+  Accept,
+  /// Don't yield the node but visit its children
+  Skip,
+  /// Don't yield the node or any of its descendants
+  Reject,
+}
+
+impl<'a> NodeRef<'a> {
+  /// Depth-first traversal of this node and its descendants
+  pub fn traverse(&self) -> Traverse<'a> {
+    Traverse { root: self.id, next: Some(Edge::Enter(*self)) }
+  }
+
+  /// Descendants in document order (pre-order), not including this node
+  pub fn descendants(&self) -> Descendants<'a> {
+    let mut traverse = self.traverse();
+    traverse.next();
+    Descendants(traverse)
+  }
+
+  /// Descendants in post-order, children before their parent, not including this node
+  pub fn descendants_post_order(&self) -> PostOrder<'a> {
+    PostOrder(self.traverse())
+  }
+
+  /// Parent, grandparent and so on up to the root
+  pub fn ancestors(&self) -> Links<'a> {
+    Links { dom: self.dom, next: self.dom.data(self.id).parent, step: |dom, id| dom.data(id).parent }
+  }
+
+  /// Siblings after this node, nearest first
+  pub fn following_siblings(&self) -> Links<'a> {
+    Links { dom: self.dom, next: self.dom.data(self.id).next_sibling, step: |dom, id| dom.data(id).next_sibling }
+  }
+
+  /// Siblings before this node, nearest first
+  pub fn preceding_siblings(&self) -> Links<'a> {
+    Links { dom: self.dom, next: self.dom.data(self.id).previous_sibling, step: |dom, id| dom.data(id).previous_sibling }
+  }
+
+  /// Descendants in document order, filtered by `filter`
+  pub fn walk<F: FnMut(NodeRef<'a>) -> NodeFilter>(&self, filter: F) -> TreeWalker<'a, F> {
+    TreeWalker { traverse: self.descendants().0, filter }
+  }
+}
+
+/// See [`NodeRef::traverse`]
+#[derive(Clone)]
+pub struct Traverse<'a> {
+  root: NodeId,
+  next: Option<Edge<'a>>,
+}
+
+impl Traverse<'_> {
+  /// Don't descend into the node that was just entered, the next step is leaving it\
+  /// Only meaningful right after an [`Edge::Enter`]
+  pub fn skip_children(&mut self) {
+    if let Some(Edge::Enter(child)) = self.next {
+      self.next = child.parent().map(Edge::Leave);
+    }
+  }
+}
+
+impl<'a> Iterator for Traverse<'a> {
+  type Item = Edge<'a>;
+  fn next(&mut self) -> Option<Edge<'a>> {
+    let edge = self.next?;
+    self.next = match edge {
+      Edge::Enter(node) => Some(match node.first_child() {
+        Some(child) => Edge::Enter(child),
+        None => Edge::Leave(node),
+      }),
+      Edge::Leave(node) if node.id == self.root => None,
+      Edge::Leave(node) => match node.next_sibling() {
+        Some(sibling) => Some(Edge::Enter(sibling)),
+        None => node.parent().map(Edge::Leave),
+      },
+    };
+    Some(edge)
+  }
+}
+
+/// See [`NodeRef::descendants`]
+#[derive(Clone)]
+pub struct Descendants<'a>(Traverse<'a>);
+
+impl<'a> Iterator for Descendants<'a> {
+  type Item = NodeRef<'a>;
+  fn next(&mut self) -> Option<NodeRef<'a>> {
+    self.0.find_map(|edge| match edge {
+      Edge::Enter(node) => Some(node),
+      Edge::Leave(_) => None,
+    })
+  }
+}
+
+/// See [`NodeRef::descendants_post_order`]
+#[derive(Clone)]
+pub struct PostOrder<'a>(Traverse<'a>);
+
+impl<'a> Iterator for PostOrder<'a> {
+  type Item = NodeRef<'a>;
+  fn next(&mut self) -> Option<NodeRef<'a>> {
+    let root = self.0.root;
+    self.0.find_map(|edge| match edge {
+      Edge::Leave(node) if node.id != root => Some(node),
+      _ => None,
+    })
+  }
+}
+
+/// Nodes reached by following one link at a time, see [`NodeRef::ancestors`]
+#[derive(Clone)]
+pub struct Links<'a> {
+  dom: &'a Dom,
+  next: Option<NodeId>,
+  step: fn(&Dom, NodeId) -> Option<NodeId>,
+}
+
+impl<'a> Iterator for Links<'a> {
+  type Item = NodeRef<'a>;
+  fn next(&mut self) -> Option<NodeRef<'a>> {
+    let id = self.next?;
+    self.next = (self.step)(self.dom, id);
+    Some(NodeRef { dom: self.dom, id })
+  }
+}
+
+/// See [`NodeRef::walk`]
+pub struct TreeWalker<'a, F> {
+  traverse: Traverse<'a>,
+  filter: F,
+}
+
+impl<'a, F: FnMut(NodeRef<'a>) -> NodeFilter> Iterator for TreeWalker<'a, F> {
+  type Item = NodeRef<'a>;
+  fn next(&mut self) -> Option<NodeRef<'a>> {
+    loop {
+      let Edge::Enter(node) = self.traverse.next()? else { continue };
+      match (self.filter)(node) {
+        NodeFilter::Accept => return Some(node),
+        NodeFilter::Skip => (),
+        NodeFilter::Reject => self.traverse.skip_children(),
+      }
+    }
+  }
+}
+
+/// Element-only variants of the node iterators
+pub trait NodeIteratorExt<'a>: Iterator<Item = NodeRef<'a>> + Sized {
+  /// Only yield element nodes
+  fn elements(self) -> Elements<Self> {
+    Elements(self)
+  }
+}
+
+impl<'a, I: Iterator<Item = NodeRef<'a>>> NodeIteratorExt<'a> for I {}
+
+/// See [`NodeIteratorExt::elements`]
+#[derive(Clone)]
+pub struct Elements<I>(I);
+
+impl<'a, I: Iterator<Item = NodeRef<'a>>> Iterator for Elements<I> {
+  type Item = NodeRef<'a>;
+  fn next(&mut self) -> Option<NodeRef<'a>> {
+    self.0.find(|node| node.element().is_some())
+  }
+}
+
+impl<'a, I: DoubleEndedIterator<Item = NodeRef<'a>>> DoubleEndedIterator for Elements<I> {
+  fn next_back(&mut self) -> Option<NodeRef<'a>> {
+    self.0.rfind(|node| node.element().is_some())
+  }
+}
@@ -1,17 +1,7 @@
-use crate::{dom::{SharedNode, ElementNode, Node}, layout::ComputedElementLayout};
+use crate::dom::NodeRef;
 use nalgebra::Vector2;
 
-///Internal function\
-///Panics if node is a Text node
-fn element_node<'a>(node: &'a SharedNode) -> &'a ElementNode {
-  let x = node.0.borrow();
-  match &*x {
-    Node::Element(node) => node,
-    _ => unreachable!()
-  }
-}
-
-pub trait ElementInterface {
+pub trait ElementInterface: Send + Sync {
   /// Compute node's internal content size\
   /// 
   /// Defaults to a 10x10 square (if no children)\
@@ -25,8 +15,8 @@ pub trait ElementInterface {
   /// 
   /// not guaranteed to match the actual size after the layout stage.\
   /// this is not exact and it can and will be overriden by styles.
-  fn compute_content_size(&self, node: &SharedNode) -> Option<Vector2<f32>> {
-    element_node(node).children.is_empty().then_some(Vector2::new(10., 10.))
+  fn compute_content_size(&self, node: NodeRef) -> Option<Vector2<f32>> {
+    node.children().next().is_none().then_some(Vector2::new(10., 10.))
   }
 
   //compute_layout_
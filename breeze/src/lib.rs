@@ -1,10 +1,11 @@
-pub(crate) mod consts;
+pub mod consts;
+#[allow(dead_code)] //css grammar is not used yet
 pub(crate) mod grammar;
-pub(crate) mod layout;
-pub(crate) mod css;
+pub mod layout;
+pub mod css;
 pub(crate) mod render;
-pub(crate) mod elements;
-pub(crate) mod dom;
+pub mod elements;
+pub mod dom;
 
 pub struct BreezeInstance {
   pub dom: dom::Dom,
@@ -1,4 +1,4 @@
-use breeze::dom::{Dom, InnerHtml};
+use breeze::dom::{Dom, InnerHtml, LineColumn, Node, NodeRef};
 
 #[test]
 pub fn _0() {
@@ -7,9 +7,9 @@ pub fn _0() {
     <img src="foo.png"/>
     <quirky lol="\"" owo="\\" x="\'" y='\"' />
     <button type="button" id="register-button" class="rounded stylish-button">Register</button>
-  "#).unwrap();
+  "#);
   println!("{:#?}", dom);
-  println!("{}", dom.tree.0.borrow().inner_html());
+  println!("{}", dom.root().inner_html());
 }
 
 #[test]
@@ -71,5 +71,985 @@ pub fn _1() {
       </body>
     </html>
   "#;
-  let dom = Dom::parse(motherfucking_website).unwrap();
+  let _dom = Dom::parse(motherfucking_website);
+}
+
+fn parse_to_string(html: &str) -> String {
+  Dom::parse(html).root().inner_html()
+}
+
+fn find_element<'a>(node: NodeRef<'a>, tag_name: &str) -> Option<NodeRef<'a>> {
+  let Node::Element(element) = node.value() else { return None };
+  if element.tag_name == tag_name {
+    return Some(node)
+  }
+  node.children().find_map(|x| find_element(x, tag_name))
+}
+
+fn namespace(node: &Node) -> breeze::dom::Namespace {
+  match node {
+    Node::Element(element) => element.namespace.clone(),
+    _ => unreachable!(),
+  }
+}
+
+fn attribute(node: &Node, name: &str) -> Option<String> {
+  match node {
+    Node::Element(element) => element.attribute(name).map(|x| x.to_owned()),
+    _ => None,
+  }
+}
+
+#[test]
+pub fn implied_end_tags() {
+  assert_eq!(
+    parse_to_string("<p>a<p>b<ul><li>1<li>2</ul>"),
+    "<html><head></head><body><p>a</p><p>b</p><ul><li>1</li><li>2</li></ul></body></html>"
+  );
+}
+
+#[test]
+pub fn misnested_formatting_elements() {
+  assert_eq!(
+    parse_to_string("<b>1<p>2</b>3</p>"),
+    "<html><head></head><body><b>1</b><p><b>2</b>3</p></body></html>"
+  );
+  assert_eq!(
+    parse_to_string("<a>1<div>2<a>3</a>4</div>"),
+    "<html><head></head><body><a>1</a><div><a>2</a><a>3</a>4</div></body></html>"
+  );
+}
+
+#[test]
+pub fn stray_end_tags() {
+  assert_eq!(
+    parse_to_string("</div>a</span>b</p>"),
+    "<html><head></head><body>ab<p></p></body></html>"
+  );
+}
+
+#[test]
+pub fn foster_parenting() {
+  assert_eq!(
+    parse_to_string("<table>x<tr><td>a</table>"),
+    "<html><head></head><body>x<table><tbody><tr><td>a</td></tr></tbody></table></body></html>"
+  );
+}
+
+#[test]
+pub fn character_references() {
+  assert_eq!(
+    parse_to_string("<p>&amp; &lt;b&gt; &eacute;&nbsp;&#x1F600;&#65;&#x80; &notit; &amp x&ampy &unknown; &#0;</p>"),
+    "<html><head></head><body><p>&amp; &lt;b&gt; é&nbsp;😀A€ ¬it; &amp; x&amp;y &amp;unknown; \u{FFFD}</p></body></html>"
+  );
+}
+
+#[test]
+pub fn character_references_in_attributes() {
+  let dom = Dom::parse(r#"<a title="&lt;&quot;&gt;" href="?a=1&amp=2&copy=3&copy;" alt=&acE;>"#);
+  let a = find_element(dom.root(), "a").unwrap();
+  assert_eq!(attribute(&a, "title").unwrap(), "<\">");
+  assert_eq!(attribute(&a, "href").unwrap(), "?a=1&amp=2&copy=3©");
+  assert_eq!(attribute(&a, "alt").unwrap(), "\u{223E}\u{333}");
+}
+
+#[test]
+pub fn raw_text_elements() {
+  assert_eq!(
+    parse_to_string(r#"<style>a > b { content: "</p>" }</style><script>if (a < b && "<div>") {}</script>"#),
+    r#"<html><head><style>a > b { content: "</p>" }</style><script>if (a < b && "<div>") {}</script></head><body></body></html>"#
+  );
+  assert_eq!(
+    parse_to_string("<xmp><b>&amp;</xmp><iframe><p></iframe><noembed></noemb></NOEMBED x>"),
+    "<html><head></head><body><xmp><b>&amp;</xmp><iframe><p></iframe><noembed></noemb></noembed></body></html>"
+  );
+}
+
+#[test]
+pub fn script_data_escapes() {
+  assert_eq!(
+    parse_to_string("<script><!--<script></script>--></script>"),
+    "<html><head><script><!--<script></script>--></script></head><body></body></html>"
+  );
+  assert_eq!(
+    parse_to_string("<script><!--</script>x"),
+    "<html><head><script><!--</script></head><body>x</body></html>"
+  );
+}
+
+#[test]
+pub fn rcdata_elements() {
+  assert_eq!(
+    parse_to_string("<title>a <b> &amp; </title ></title><textarea><p>&lt;</textarea>"),
+    "<html><head><title>a &lt;b&gt; &amp; </title></head><body><textarea>&lt;p&gt;&lt;</textarea></body></html>"
+  );
+}
+
+#[test]
+pub fn parse_errors() {
+  use breeze::dom::ParseErrorKind;
+  let dom = Dom::parse("<!DOCTYPE html>\n<div id=a id=b>\n  é</span></div>");
+  let errors: Vec<_> = dom.errors.iter().map(|x| (x.kind, x.line, x.column, x.span.clone())).collect();
+  assert_eq!(errors, vec![
+    (ParseErrorKind::DuplicateAttribute, 2, 11, 26..28),
+    (ParseErrorKind::UnexpectedEndTag, 3, 4, 36..43),
+  ]);
+  assert_eq!(dom.errors[1].to_string(), "3:4: unexpected-end-tag");
+  assert!(Dom::parse("<!DOCTYPE html><p>ok</p>").errors.is_empty());
+}
+
+#[test]
+pub fn streaming_parser() {
+  use breeze::dom::DomParser;
+  let html = "<!DOCTYPE html>\r\n<title>caf&eacute; &CounterClockwiseContourIntegral;</title>\r\n<p class=x>žluťoučký &amp kůň<!-- c -->\r<script><!--<script>a</script>--></script><b><i>x</b></i></p>";
+  let expected = Dom::parse(html);
+  let mut parser = DomParser::new();
+  for byte in html.as_bytes() {
+    parser.feed(&[*byte]);
+  }
+  let dom = parser.finish();
+  assert_eq!(dom.root().inner_html(), expected.root().inner_html());
+  assert_eq!(dom.errors, expected.errors);
+
+  let mut parser = DomParser::new();
+  parser.feed(b"<ul><li>first<li>sec");
+  assert_eq!(parser.dom().root().inner_html(), "<html><head></head><body><ul><li>first</li><li>sec</li></ul></body></html>");
+  parser.feed(b"ond</ul>");
+  let dom = parser.finish();
+  assert_eq!(dom.root().inner_html(), "<html><head></head><body><ul><li>first</li><li>second</li></ul></body></html>");
+}
+
+#[test]
+pub fn fragment_parsing() {
+  let mut dom = Dom::parse("<body><div id=target>old</div><form><table><tr id=row></tr></table></form><textarea></textarea>");
+
+  let div = find_element(dom.root(), "div").unwrap().id();
+  dom.node_mut(div).set_inner_html("<p>a<p>b &amp; <b>c</div>d").unwrap();
+  assert_eq!(dom.node(div).outer_html(), "<div id=\"target\"><p>a</p><p>b &amp; <b>cd</b></p></div>");
+
+  let row = find_element(dom.root(), "tr").unwrap().id();
+  dom.node_mut(row).set_inner_html("<td>1<td>2").unwrap();
+  assert_eq!(dom.node(row).outer_html(), "<tr id=\"row\"><td>1</td><td>2</td></tr>");
+
+  let textarea = find_element(dom.root(), "textarea").unwrap().id();
+  dom.node_mut(textarea).set_inner_html("<b>&lt;</textarea>").unwrap();
+  assert_eq!(dom.node(textarea).outer_html(), "<textarea>&lt;b&gt;&lt;&lt;/textarea&gt;</textarea>");
+
+  let fragment = Dom::parse_fragment("<html><body>x</body></html>", dom.node(div));
+  assert_eq!(fragment.root().inner_html(), "x");
+
+  //document fragments are parsed into like body
+  let fragment = dom.create_fragment();
+  dom.node_mut(fragment).set_inner_html("<p>a<tr><td>b</p>c").unwrap();
+  assert_eq!(dom.node(fragment).inner_html(), "<p>ab</p>c");
+}
+
+#[test]
+pub fn encoding_detection() {
+  use breeze::dom::DomParser;
+  let text = |dom: &Dom| find_element(dom.root(), "p").unwrap().outer_html();
+
+  //no declaration at all
+  let dom = Dom::parse_bytes(b"<p>caf\xE9</p>", None);
+  assert_eq!(dom.encoding.name(), "windows-1252");
+  assert_eq!(text(&dom), "<p>café</p>");
+
+  //meta charset, attribute values are lowercased and labels trimmed
+  let dom = Dom::parse_bytes(b"<!-- <meta charset=utf-8> --><head><meta name=x charset=' SHIFT_JIS'><p>\x93\xFA\x96\x7B</p>", None);
+  assert_eq!(dom.encoding.name(), "Shift_JIS");
+  assert_eq!(text(&dom), "<p>日本</p>");
+
+  //http-equiv pragma, content without the pragma is ignored
+  let html = b"<meta content='text/html; charset=gbk'><meta http-equiv=Content-Type content=\"text/html;charset=gbk\"><p>\xC4\xE3\xBA\xC3</p>";
+  let dom = Dom::parse_bytes(html, None);
+  assert_eq!(dom.encoding.name(), "GBK");
+  assert_eq!(text(&dom), "<p>你好</p>");
+  assert_eq!(Dom::parse_bytes(b"<meta content='text/html; charset=gbk'>", None).encoding.name(), "windows-1252");
+
+  //utf-16 in a meta means utf-8
+  assert_eq!(Dom::parse_bytes(b"<meta charset=utf-16le>", None).encoding.name(), "UTF-8");
+
+  //transport hint wins over meta, the byte order mark wins over both
+  let dom = Dom::parse_bytes(b"<meta charset=shift_jis><p>caf\xC3\xA9", Some("utf8"));
+  assert_eq!(dom.encoding.name(), "UTF-8");
+  assert_eq!(text(&dom), "<p>café</p>");
+  let dom = Dom::parse_bytes(b"\xFF\xFE<\0p\0>\0x\0", Some("latin1"));
+  assert_eq!(dom.encoding.name(), "UTF-16LE");
+  assert_eq!(text(&dom), "<p>x</p>");
+
+  //streaming, declaration past the first chunk
+  let mut html = b"<meta charset=shift_jis>".to_vec();
+  html.extend(b"<p>\x93\xFA\x96\x7B</p>".repeat(200));
+  let mut parser = DomParser::with_encoding_detection(None);
+  for chunk in html.chunks(7) {
+    parser.feed(chunk);
+  }
+  let dom = parser.finish();
+  assert_eq!(dom.encoding.name(), "Shift_JIS");
+  assert_eq!(text(&dom), "<p>日本</p>");
+  assert_eq!(Dom::parse("<p>x").encoding.name(), "UTF-8");
+}
+
+#[test]
+pub fn doctypes() {
+  use breeze::dom::{DocumentMode, ParseErrorKind};
+  let doctype = |dom: &Dom| {
+    let first = dom.root().children().next().unwrap();
+    let Node::Doctype(doctype) = first.value() else { panic!("no doctype") };
+    (doctype.name.clone(), doctype.public_id.clone(), doctype.system_id.clone())
+  };
+
+  let dom = Dom::parse("<!DOCTYPE html><p>x");
+  assert_eq!(dom.mode, DocumentMode::NoQuirks);
+  assert_eq!(doctype(&dom), ("html".into(), "".into(), "".into()));
+  assert!(dom.errors.is_empty());
+  assert_eq!(Dom::parse("<!doctype html system 'about:legacy-compat'>").mode, DocumentMode::NoQuirks);
+
+  let dom = Dom::parse("<!DOCTYPE HTML PUBLIC \"-//W3C//DTD HTML 4.01//EN\"\n  \"http://www.w3.org/TR/html4/strict.dtd\">");
+  assert_eq!(dom.mode, DocumentMode::NoQuirks);
+  assert_eq!(doctype(&dom), ("html".into(), "-//W3C//DTD HTML 4.01//EN".into(), "http://www.w3.org/TR/html4/strict.dtd".into()));
+  assert_eq!(dom.errors[0].kind, ParseErrorKind::NonConformingDoctype);
+
+  let dom = Dom::parse("<!DOCTYPE html PUBLIC '-//W3C//DTD XHTML 1.0 Transitional//EN' 'http://www.w3.org/TR/xhtml1/DTD/xhtml1-transitional.dtd'>");
+  assert_eq!(dom.mode, DocumentMode::LimitedQuirks);
+  assert_eq!(Dom::parse("<!DOCTYPE html PUBLIC \"-//W3C//DTD HTML 4.01 Transitional//EN\">").mode, DocumentMode::Quirks);
+  assert_eq!(Dom::parse("<!DOCTYPE html PUBLIC \"-//W3C//DTD HTML 4.01 Transitional//EN\" \"\">").mode, DocumentMode::LimitedQuirks);
+  assert_eq!(Dom::parse("<!DOCTYPE html PUBLIC \"-//IETF//DTD HTML 2.0//EN\">").mode, DocumentMode::Quirks);
+  assert_eq!(Dom::parse("<!DOCTYPE svg>").mode, DocumentMode::Quirks);
+  assert_eq!(Dom::parse("<p>no doctype").mode, DocumentMode::Quirks);
+
+  //missing whitespace and trailing garbage are recovered from, a cut off identifier forces quirks mode
+  let dom = Dom::parse("<!DOCTYPE html PUBLIC\"x\"'y' z><p>");
+  assert_eq!(dom.mode, DocumentMode::NoQuirks);
+  assert_eq!(doctype(&dom), ("html".into(), "x".into(), "y".into()));
+  assert_eq!(
+    dom.errors.iter().map(|x| x.kind).collect::<Vec<_>>(),
+    [
+      ParseErrorKind::NonConformingDoctype,
+      ParseErrorKind::MissingWhitespaceAfterDoctypePublicKeyword,
+      ParseErrorKind::MissingWhitespaceBetweenDoctypePublicAndSystemIdentifiers,
+      ParseErrorKind::UnexpectedCharacterAfterDoctypeSystemIdentifier,
+    ]
+  );
+  let dom = Dom::parse("<!DOCTYPE html PUBLIC \"abrupt>text");
+  assert_eq!(dom.mode, DocumentMode::Quirks);
+  assert_eq!(doctype(&dom).1, "abrupt");
+
+  //a table doesn't close paragraphs in quirks mode
+  assert_eq!(
+    parse_to_string("<p><table></table>"),
+    "<html><head></head><body><p><table></table></p></body></html>"
+  );
+  assert_eq!(
+    parse_to_string("<!DOCTYPE html><p><table></table>"),
+    "<!DOCTYPE html><html><head></head><body><p></p><table></table></body></html>"
+  );
+}
+
+#[test]
+pub fn comments_and_processing_instructions() {
+  assert_eq!(
+    parse_to_string("<?xml version=\"1.0\"?><!-- a --><!DOCTYPE html><html><!--b--><p>x<!---->y</p><table><!--c--><tr></table></html><!--d-->"),
+    "<?xml version=\"1.0\"><!-- a --><!DOCTYPE html><html><!--b--><head></head><body><p>x<!---->y</p><table><!--c--><tbody><tr></tr></tbody></table></body></html><!--d-->"
+  );
+  assert_eq!(
+    parse_to_string("<!DOCTYPE html><body></body><!--after body--></html><!--after html-->"),
+    "<!DOCTYPE html><html><head></head><body></body><!--after body--></html><!--after html-->"
+  );
+  //bogus comments
+  assert_eq!(
+    parse_to_string("<!DOCTYPE html><p><! bogus ><?php echo 1 ?></p>"),
+    "<!DOCTYPE html><html><head></head><body><p><!-- bogus --><?php echo 1 ></p></body></html>"
+  );
+}
+
+#[test]
+pub fn whitespace() {
+  assert_eq!(
+    parse_to_string("<!DOCTYPE html><b>a</b> <i>b</i>\n<p>a  \t b\r\n</p>\n\n<p> </p>"),
+    "<!DOCTYPE html><html><head></head><body><b>a</b> <i>b</i>\n<p>a  \t b\n</p>\n\n<p> </p></body></html>"
+  );
+  //only the first newline after pre/listing/textarea is dropped
+  assert_eq!(
+    parse_to_string("<!DOCTYPE html><pre>\n\n  x  y\n</pre><listing>\r\nz</listing><textarea>\n\n</textarea><pre> \nw</pre>"),
+    "<!DOCTYPE html><html><head></head><body><pre>\n\n  x  y\n</pre><listing>z</listing><textarea>\n\n</textarea><pre> \nw</pre></body></html>"
+  );
+  //whitespace is kept inside head and tables, and before the document it's dropped
+  assert_eq!(
+    parse_to_string(" \n<!DOCTYPE html> <html> <head> <title> t </title> </head> <body><table> <tr> <td> a </td> </tr> </table>"),
+    "<!DOCTYPE html><html><head> <title> t </title> </head> <body><table> <tbody><tr> <td> a </td> </tr> </tbody></table></body></html>"
+  );
+}
+
+#[test]
+pub fn foreign_content() {
+  use breeze::dom::Namespace;
+
+  let dom = Dom::parse("<!DOCTYPE html><p>a<svg viewbox='0 0 1 1'><a xlink:href=#x><text xml:lang=en></text></a><foreignobject><p>html</p></foreignobject><path d='M0'/><![CDATA[<x>]]></svg>b");
+  assert_eq!(
+    dom.root().inner_html(),
+    "<!DOCTYPE html><html><head></head><body><p>a<svg viewBox=\"0 0 1 1\"><a xlink:href=\"#x\"><text xml:lang=\"en\"></text></a><foreignObject><p>html</p></foreignObject><path d=\"M0\"></path>&lt;x&gt;</svg>b</p></body></html>"
+  );
+  assert!(dom.errors.is_empty());
+  let svg = find_element(dom.root(), "svg").unwrap();
+  assert_eq!(namespace(&svg), Namespace::Svg);
+  assert_eq!(attribute(&svg, "viewBox").as_deref(), Some("0 0 1 1"));
+  let link = find_element(svg, "a").unwrap();
+  assert_eq!(namespace(&link), Namespace::Svg);
+  let Node::Element(element) = link.value() else { unreachable!() };
+  assert_eq!(element.attribute_ns(Namespace::XLink, "href"), Some("#x"));
+  assert_eq!(element.attribute_ns(Namespace::Xml, "href"), None);
+  let text = find_element(svg, "text").unwrap();
+  let Node::Element(element) = text.value() else { unreachable!() };
+  assert_eq!(element.attribute_ns(Namespace::Xml, "lang"), Some("en"));
+  let object = find_element(svg, "foreignObject").unwrap();
+  assert_eq!(namespace(&find_element(object, "p").unwrap()), Namespace::Html);
+
+  //html elements break out of foreign content, integration points switch back to html
+  assert_eq!(
+    parse_to_string("<!DOCTYPE html><svg><g><p>breakout</p><font color=red>x</font>"),
+    "<!DOCTYPE html><html><head></head><body><svg><g></g></svg><p>breakout</p><font color=\"red\">x</font></body></html>"
+  );
+  let dom = Dom::parse("<!DOCTYPE html><math><mi><b>x</b></mi><annotation-xml encoding=text/html><div>y</div></annotation-xml><mo><mglyph/></mo></math>");
+  assert_eq!(namespace(&find_element(dom.root(), "math").unwrap()), Namespace::MathMl);
+  assert_eq!(namespace(&find_element(dom.root(), "b").unwrap()), Namespace::Html);
+  assert_eq!(namespace(&find_element(dom.root(), "div").unwrap()), Namespace::Html);
+  assert_eq!(namespace(&find_element(dom.root(), "mglyph").unwrap()), Namespace::MathMl);
+
+  //cdata sections are only recognized in foreign content
+  assert_eq!(
+    parse_to_string("<!DOCTYPE html><p><![CDATA[x]]></p>"),
+    "<!DOCTYPE html><html><head></head><body><p><!--[CDATA[x]]--></p></body></html>"
+  );
+}
+
+#[test]
+pub fn templates_and_fragments() {
+  let mut dom = Dom::parse("<!DOCTYPE html><template id=t><tr><td>cell</td></tr><!--c--></template><p>x</p>");
+  let template = find_element(dom.root(), "template").unwrap();
+  assert!(template.children().next().is_none());
+  let contents = template.template_contents().unwrap();
+  assert!(matches!(contents.value(), Node::DocumentFragment(_)));
+  assert!(contents.parent().is_none());
+  assert_eq!(contents.inner_html(), "<tr><td>cell</td></tr><!--c-->");
+  assert_eq!(template.outer_html(), "<template id=\"t\"><tr><td>cell</td></tr><!--c--></template>");
+  assert!(find_element(dom.root(), "td").is_none());
+
+  //pushing a fragment moves its children
+  let parsed = Dom::parse_fragment("a<b>b</b>c", template);
+  let template = template.id();
+  let fragment = dom.create_fragment();
+  for node in dom.adopt(parsed) {
+    dom.node_mut(fragment).append(node).unwrap();
+  }
+  let p = find_element(dom.root(), "p").unwrap().id();
+  dom.node_mut(p).push(fragment).unwrap();
+  assert_eq!(dom.node(p).outer_html(), "<p>xa<b>b</b>c</p>");
+  assert_eq!(dom.node(fragment).inner_html(), "");
+  let b = find_element(dom.node(p), "b").unwrap();
+  assert!(b.parent().unwrap() == dom.node(p));
+
+  dom.node_mut(template).set_inner_html("<li>new").unwrap();
+  assert_eq!(dom.node(template).outer_html(), "<template id=\"t\"><li>new</li></template>");
+  let fragment = dom.create_fragment();
+  let empty = dom.create_fragment();
+  dom.node_mut(fragment).push(empty).unwrap();
+  assert_eq!(dom.node(fragment).inner_html(), "");
+}
+
+#[test]
+pub fn source_positions() {
+  let html = "<!DOCTYPE html>\n<div id=a class='b c'>héllo\n<b>x</b><p>y</div>";
+  let dom = Dom::parse(html);
+  let div = find_element(dom.root(), "div").unwrap();
+  let Node::Element(element) = div.value() else { unreachable!() };
+  let source = element.source.as_ref().unwrap();
+  assert_eq!(&html[source.start_tag.range.clone()], "<div id=a class='b c'>");
+  assert_eq!(source.start_tag.start, LineColumn { line: 2, column: 1 });
+  assert_eq!(source.start_tag.end, LineColumn { line: 2, column: 23 });
+  assert_eq!(&html[source.attribute("id").unwrap().range.clone()], "id=a");
+  let class = source.attribute("class").unwrap();
+  assert_eq!(&html[class.range.clone()], "class='b c'");
+  assert_eq!(class.start, LineColumn { line: 2, column: 11 });
+  let end_tag = source.end_tag.as_ref().unwrap();
+  assert_eq!(&html[end_tag.range.clone()], "</div>");
+  assert_eq!(end_tag.start, LineColumn { line: 3, column: 13 });
+
+  //text spans cover every character, columns count characters rather than bytes
+  let text = div.children().next().unwrap();
+  let span = text.source_span().unwrap();
+  assert_eq!(&html[span.range.clone()], "héllo\n");
+  assert_eq!(span.end, LineColumn { line: 3, column: 1 });
+
+  let b = find_element(div, "b").unwrap();
+  let Node::Element(element) = b.value() else { unreachable!() };
+  assert_eq!(element.source.as_ref().unwrap().start_tag.start, LineColumn { line: 3, column: 1 });
+  assert!(element.source.as_ref().unwrap().end_tag.is_some());
+
+  //implicitly closed and implied elements
+  let p = find_element(div, "p").unwrap();
+  let Node::Element(element) = p.value() else { unreachable!() };
+  assert!(element.source.as_ref().unwrap().end_tag.is_none());
+  let head = find_element(dom.root(), "head").unwrap();
+  assert!(head.source_span().is_none());
+  let doctype = dom.root().children().next().unwrap();
+  assert_eq!(doctype.source_span().unwrap().range, 0..15);
+}
+
+#[test]
+pub fn attribute_order() {
+  use breeze::dom::ParseErrorKind;
+  let html = "<!DOCTYPE html><div z=1 id=x a=2 z=3 class=c ID=y></div>";
+  let expected = "<!DOCTYPE html><html><head></head><body><div z=\"1\" id=\"x\" a=\"2\" class=\"c\"></div></body></html>";
+  for _ in 0..4 {
+    assert_eq!(parse_to_string(html), expected);
+  }
+  let mut dom = Dom::parse(html);
+  assert_eq!(dom.errors.iter().filter(|x| x.kind == ParseErrorKind::DuplicateAttribute).count(), 2);
+
+  //changing a value keeps the position, removing and adding moves it to the end
+  let div = find_element(dom.root(), "div").unwrap().id();
+  let Node::Element(element) = &mut dom[div] else { unreachable!() };
+  assert_eq!(element.attribute("Id"), Some("x"));
+  element.set_attribute("A", Some("4".into()));
+  element.set_attribute("z", None);
+  element.set_attribute("z", Some("5".into()));
+  assert_eq!(element.attributes.keys().collect::<Vec<_>>(), ["id", "a", "class", "z"]);
+  assert_eq!(dom.node(div).outer_html(), "<div id=\"x\" a=\"4\" class=\"c\" z=\"5\"></div>");
+}
+
+#[test]
+pub fn xml_documents() {
+  use breeze::dom::{Namespace, ParseErrorKind, Syntax, XmlParser};
+  let xhtml = concat!(
+    "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n",
+    "<!DOCTYPE html PUBLIC \"-//W3C//DTD XHTML 1.0 Strict//EN\" \"http://www.w3.org/TR/xhtml1/DTD/xhtml1-strict.dtd\">\n",
+    "<html xmlns=\"http://www.w3.org/1999/xhtml\" xmlns:i=\"urn:ink\"><head><title>a&amp;b&nbsp;</title></head>",
+    "<body><DIV onClick=\"f()\"><![CDATA[<p>]]><br/><i:layer i:id=\"1\"/></DIV></body></html>",
+  );
+  let dom = Dom::parse_document(xhtml.as_bytes(), "application/xhtml+xml");
+  assert!(dom.errors.is_empty());
+  assert_eq!(dom.syntax, Syntax::Xml);
+  assert_eq!(
+    dom.root().inner_html(),
+    "<!DOCTYPE html PUBLIC \"-//W3C//DTD XHTML 1.0 Strict//EN\" \"http://www.w3.org/TR/xhtml1/DTD/xhtml1-strict.dtd\"><html xmlns=\"http://www.w3.org/1999/xhtml\" xmlns:i=\"urn:ink\"><head><title>a&amp;b\u{A0}</title></head><body><DIV onClick=\"f()\">&lt;p&gt;<br/><i:layer i:id=\"1\"/></DIV></body></html>"
+  );
+
+  //names are case-sensitive, prefixes are resolved
+  assert!(find_element(dom.root(), "div").is_none());
+  let div = find_element(dom.root(), "DIV").unwrap();
+  let Node::Element(element) = div.value() else { unreachable!() };
+  assert_eq!(element.namespace, Namespace::Html);
+  assert_eq!(element.attribute("onClick"), Some("f()"));
+  assert_eq!(element.attribute("onclick"), None);
+  let layer = find_element(div, "i:layer").unwrap();
+  let Node::Element(element) = layer.value() else { unreachable!() };
+  assert_eq!(element.namespace, Namespace::Other("urn:ink".into()));
+  assert_eq!(element.attribute_ns(Namespace::from_url("urn:ink"), "id"), Some("1"));
+
+  //well-formedness errors are fatal and have positions
+  let errors = [
+    ("<a><b></a>", ParseErrorKind::MismatchedEndTag, 1, 7),
+    ("<a>\n  <b c=d/></a>", ParseErrorKind::UnquotedAttributeValue, 2, 8),
+    ("<a/><b/>", ParseErrorKind::ContentOutsideRootElement, 1, 5),
+    ("<a><p:b/></a>", ParseErrorKind::UnboundNamespacePrefix, 1, 5),
+    ("<a>&nbsp;</a>", ParseErrorKind::UndefinedEntity, 1, 4),
+    ("<a><!-- x -- y --></a>", ParseErrorKind::DoubleHyphenInComment, 1, 11),
+    (" <?xml version=\"1.0\"?><a/>", ParseErrorKind::MisplacedXmlDeclaration, 1, 2),
+    ("<a>", ParseErrorKind::UnclosedElements, 1, 4),
+  ];
+  for (xml, kind, line, column) in errors {
+    let dom = Dom::parse_xml(xml);
+    assert_eq!(dom.errors.len(), 1, "{xml}");
+    assert_eq!((dom.errors[0].kind, dom.errors[0].line, dom.errors[0].column), (kind, line, column), "{xml}");
+  }
+  assert_eq!(Dom::parse_xml("<a>x</a><b>y</b>").root().inner_html(), "<a>x</a>");
+
+  //internal subset entities, chunked input
+  let svg = "<!DOCTYPE svg [<!ENTITY ns \"http://www.w3.org/2000/svg\">]><svg xmlns=\"&ns;\"><text>&#x41;</text></svg>";
+  let mut parser = XmlParser::new();
+  for chunk in svg.as_bytes().chunks(3) {
+    parser.feed(chunk);
+  }
+  let dom = parser.finish();
+  assert!(dom.errors.is_empty());
+  assert_eq!(namespace(&find_element(dom.root(), "text").unwrap()), Namespace::Svg);
+  assert_eq!(dom.root().inner_html(), "<!DOCTYPE svg><svg xmlns=\"http://www.w3.org/2000/svg\"><text>A</text></svg>");
+
+  //long text and comments arriving in small chunks aren't rescanned from their start
+  let long = format!("<a>{}<!--{}--></a>", "x".repeat(500_000), "y".repeat(500_000));
+  let mut parser = XmlParser::new();
+  for chunk in long.as_bytes().chunks(7) {
+    parser.feed(chunk);
+  }
+  assert_eq!(parser.finish().root().inner_html(), long);
+
+  //nested entities referenced many times stop parsing once their total expansion is too large
+  let mut laughs = String::from("<!DOCTYPE a [<!ENTITY l0 \"lollollollollollollollollollol\">");
+  for level in 1..5 {
+    laughs += &format!("<!ENTITY l{level} \"{}\">", format!("&l{};", level - 1).repeat(10));
+  }
+  laughs += &format!("]><a>{}</a>", "&l4;".repeat(2000));
+  let dom = Dom::parse_xml(&laughs);
+  assert_eq!(dom.errors.len(), 1);
+  assert_eq!(dom.errors[0].kind, ParseErrorKind::EntityTooLarge);
+  assert!(dom.root().inner_html().len() < 1 << 20);
+
+  assert_eq!(Syntax::from_content_type("image/svg+xml"), Syntax::Xml);
+  assert_eq!(Syntax::from_content_type("Text/XML; charset=utf-8"), Syntax::Xml);
+  assert_eq!(Syntax::from_content_type("text/html"), Syntax::Html);
+  assert_eq!(Dom::parse_document(b"<P>x", "text/html").syntax, Syntax::Html);
+}
+
+#[test]
+pub fn deeply_nested_documents() {
+  //parsing, serializing and dropping must neither recurse per level nor take quadratic time
+  const DEPTH: usize = 20_000;
+  for open in ["<div>", "<b>", "<template>", "<table><template>", "<svg><g>"] {
+    let dom = Dom::parse(&open.repeat(DEPTH));
+    let html = dom.root().inner_html();
+    assert!(html.len() > DEPTH * open.len(), "{open}");
+    assert!(html.ends_with("</html>"), "{open}");
+  }
+  let xml = "<a>".repeat(DEPTH) + &"</a>".repeat(DEPTH);
+  let dom = Dom::parse_xml(&xml);
+  assert!(dom.errors.is_empty());
+  assert_eq!(dom.root().inner_html(), format!("{}<a/>{}", "<a>".repeat(DEPTH - 1), "</a>".repeat(DEPTH - 1)));
+}
+
+#[test]
+pub fn arena_handles() {
+  fn assert_send_sync<T: Send + Sync>() {}
+  assert_send_sync::<Dom>();
+
+  let dom = Dom::parse("<ul><li>a<li>b<li>c</ul>");
+  let ul = find_element(dom.root(), "ul").unwrap();
+  let items: Vec<_> = ul.children().collect();
+  assert_eq!(items.len(), 3);
+  assert!(items.iter().all(|li| li.parent() == Some(ul)));
+  let reversed: Vec<_> = ul.children().rev().collect();
+  assert_eq!(reversed, items.iter().rev().copied().collect::<Vec<_>>());
+  let mut both = ul.children();
+  assert_eq!((both.next(), both.next_back(), both.next(), both.next()), (Some(items[0]), Some(items[2]), Some(items[1]), None));
+  assert_eq!(dom.node(items[1].id()), items[1]);
+
+  //replaced and removed nodes are freed, their slots are reused under new ids
+  let mut dom = Dom::parse("<div id=a></div>");
+  let a = dom.get_element_by_id("a").unwrap().id();
+  let count = dom.node_count();
+  for i in 0..10_000 {
+    dom.node_mut(a).set_inner_html(&format!("<p>{i}<template><b>t</b></template></p>")).unwrap();
+    dom.node_mut(a).set_text_content("text");
+  }
+  assert_eq!(dom.node_count(), count + 1);
+  let text = dom.node(a).first_child().unwrap().id();
+  dom.node_mut(text).remove();
+  let new = dom.create_node(Node::Text(Default::default()));
+  assert_ne!(new, text);
+  assert_eq!(dom.node_count(), count + 1);
+  assert!(std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| dom.node(text).parent())).is_err());
+
+  //template contents stay with their template
+  let mut dom = Dom::parse("<template><b>t</b></template>");
+  let template = find_element(dom.root(), "template").unwrap().id();
+  let contents = dom.node(template).template_contents().unwrap().id();
+  dom.node_mut(contents).remove();
+  assert_eq!(dom.node(template).outer_html(), "<template><b>t</b></template>");
+  dom.node_mut(template).remove();
+  assert!(std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| dom.node(contents).parent())).is_err());
+}
+
+#[test]
+pub fn tree_mutation() {
+  use breeze::dom::{DomPushError, TextNode};
+  let mut dom = Dom::parse("<div id=a><p>1</p><p>2</p></div><div id=b><i>3</i></div>");
+  let body: Vec<_> = find_element(dom.root(), "body").unwrap().children().map(|x| x.id()).collect();
+  let [a, b] = [body[0], body[1]];
+  let ps: Vec<_> = dom.node(a).children().map(|x| x.id()).collect();
+  let i = dom.node(b).first_child().unwrap().id();
+
+  //append and prepend move nodes from their old parent, push doesn't
+  assert!(matches!(dom.node_mut(b).push(ps[0]), Err(DomPushError::AlreadyHasParent)));
+  dom.node_mut(b).append(ps[0]).unwrap();
+  dom.node_mut(b).prepend(ps[1]).unwrap();
+  assert_eq!(dom.node(a).outer_html(), "<div id=\"a\"></div>");
+  assert_eq!(dom.node(b).outer_html(), "<div id=\"b\"><p>2</p><i>3</i><p>1</p></div>");
+  assert_eq!(dom.node(i).previous_sibling().map(|x| x.id()), Some(ps[1]));
+  assert_eq!(dom.node(i).next_sibling().map(|x| x.id()), Some(ps[0]));
+  assert_eq!(dom.node(b).last_child().map(|x| x.id()), Some(ps[0]));
+
+  dom.node_mut(b).insert_before(ps[0], Some(ps[1])).unwrap();
+  dom.node_mut(b).insert_before(i, Some(i)).unwrap();
+  assert_eq!(dom.node(b).outer_html(), "<div id=\"b\"><p>1</p><p>2</p><i>3</i></div>");
+  let text = dom.create_node(Node::Text(TextNode { text: "x".into(), ..Default::default() }));
+  dom.node_mut(b).replace_child(text, ps[1]).unwrap();
+  dom.node_mut(b).remove_child(i).unwrap();
+  dom.node_mut(ps[0]).detach();
+  assert_eq!(dom.node(b).outer_html(), "<div id=\"b\">x</div>");
+  assert!(dom.node(ps[1]).parent().is_none());
+
+  //hierarchy violations leave the tree untouched
+  dom.node_mut(a).append(ps[0]).unwrap();
+  assert!(matches!(dom.node_mut(ps[0]).append(a), Err(DomPushError::HierarchyRequest)));
+  assert!(matches!(dom.node_mut(a).append(a), Err(DomPushError::HierarchyRequest)));
+  let root = dom.root().id();
+  assert!(matches!(dom.node_mut(a).append(root), Err(DomPushError::HierarchyRequest)));
+  assert!(matches!(dom.node_mut(a).remove_child(i), Err(DomPushError::NotAChild)));
+  assert!(matches!(dom.node_mut(a).insert_before(i, Some(text)), Err(DomPushError::NotAChild)));
+  assert!(matches!(dom.node_mut(text).append(i), Err(DomPushError::NodeInfertile)));
+  assert_eq!(dom.node(a).outer_html(), "<div id=\"a\"><p>1</p></div>");
+
+  //doctypes only go in the document, once, and text can't go in it directly
+  let doctype = dom.create_node(Node::Doctype(Default::default()));
+  assert!(matches!(dom.node_mut(a).append(doctype), Err(DomPushError::HierarchyRequest)));
+  let fragment = dom.create_fragment();
+  assert!(matches!(dom.node_mut(fragment).append(doctype), Err(DomPushError::HierarchyRequest)));
+  dom.node_mut(root).prepend(doctype).unwrap();
+  dom.node_mut(root).prepend(doctype).unwrap();
+  let second = dom.create_node(Node::Doctype(Default::default()));
+  assert!(matches!(dom.node_mut(root).append(second), Err(DomPushError::HierarchyRequest)));
+  dom.node_mut(b).remove_child(text).unwrap();
+  assert!(matches!(dom.node_mut(root).append(text), Err(DomPushError::HierarchyRequest)));
+  dom.node_mut(fragment).append(text).unwrap();
+  assert!(matches!(dom.node_mut(root).append(fragment), Err(DomPushError::HierarchyRequest)));
+  dom.root_mut().set_text_content("x");
+  dom.node_mut(b).append(fragment).unwrap();
+  dom.node_mut(doctype).remove();
+
+  //the root stays in place
+  dom.root_mut().detach();
+  dom.root_mut().remove();
+  assert_eq!(dom.root().id(), root);
+  dom.node_mut(a).remove();
+  assert_eq!(dom.root().inner_html(), "<html><head></head><body><div id=\"b\">x</div></body></html>");
+
+  //template contents are part of the template's subtree
+  let mut dom = Dom::parse("<template><b></b></template>");
+  let template = find_element(dom.root(), "template").unwrap();
+  let (template, b) = (template.id(), template.template_contents().unwrap().first_child().unwrap().id());
+  assert!(matches!(dom.node_mut(b).append(template), Err(DomPushError::HierarchyRequest)));
+  dom.node_mut(b).set_inner_html("<template><i></i></template>").unwrap();
+  let inner = dom.node(b).first_child().unwrap();
+  let (inner, i) = (inner.id(), inner.template_contents().unwrap().first_child().unwrap().id());
+  assert!(matches!(dom.node_mut(i).append(inner), Err(DomPushError::HierarchyRequest)));
+  assert!(matches!(dom.node_mut(i).append(template), Err(DomPushError::HierarchyRequest)));
+}
+
+#[test]
+pub fn traversal() {
+  use breeze::dom::{Edge, NodeFilter, NodeIteratorExt};
+  let dom = Dom::parse("<div><p>a<b>b</b></p><!--c--><ul><li>d</ul>e</div>");
+  let div = find_element(dom.root(), "div").unwrap();
+  let name = |node: NodeRef| match node.value() {
+    Node::Element(element) => element.tag_name.clone(),
+    Node::Text(text) => text.text.clone(),
+    Node::Comment(comment) => format!("<!--{}-->", comment.text),
+    _ => unreachable!(),
+  };
+  let names = |nodes: &mut dyn Iterator<Item = NodeRef>| nodes.map(name).collect::<Vec<_>>().join(" ");
+
+  assert_eq!(names(&mut div.descendants()), "p a b b <!--c--> ul li d e");
+  assert_eq!(names(&mut div.descendants_post_order()), "a b b p <!--c--> d li ul e");
+  assert_eq!(names(&mut div.descendants().elements()), "p b ul li");
+  assert_eq!(names(&mut div.children().elements().rev()), "ul p");
+  let b = find_element(div, "b").unwrap();
+  assert_eq!(names(&mut b.ancestors().elements()), "p div body html root");
+  let ul = find_element(div, "ul").unwrap();
+  assert_eq!(names(&mut ul.preceding_siblings()), "<!--c--> p");
+  assert_eq!(names(&mut ul.following_siblings()), "e");
+  assert_eq!(names(&mut b.descendants()), "b");
+
+  let edges: Vec<_> = find_element(div, "p").unwrap().traverse().map(|edge| match edge {
+    Edge::Enter(node) => format!("+{}", name(node)),
+    Edge::Leave(node) => format!("-{}", name(node)),
+  }).collect();
+  assert_eq!(edges.join(" "), "+p +a -a +b +b -b -b -p");
+
+  //rejecting skips the subtree, skipping only the node
+  let mut walked = div.walk(|node| match node.element().map(|x| x.tag_name.as_str()) {
+    Some("p") => NodeFilter::Reject,
+    Some(_) => NodeFilter::Skip,
+    None => NodeFilter::Accept,
+  });
+  assert_eq!(names(&mut walked), "<!--c--> d e");
+}
+
+#[test]
+pub fn selectors() {
+  use breeze::css::SelectorParseError;
+  let dom = Dom::parse(r#"<!DOCTYPE html>
+    <div id=main class="a  b">
+      <ul><li class=x>1<li lang=en-US>2<li data-v="q r">3<li>4</ul>
+      <p>one</p><span></span><p title="Hi">two</p>
+    </div>
+    <svg><foreignObject/></svg>"#);
+  let root = dom.root();
+  let text = |nodes: Vec<NodeRef>| nodes.iter().map(|x| match x.value() {
+    Node::Element(element) => element.attribute("id").map(|x| format!("#{x}")).unwrap_or(element.tag_name.clone()),
+    _ => unreachable!(),
+  }).collect::<Vec<_>>().join(" ");
+  let select = |selectors: &str| text(root.query_selector_all(selectors).unwrap());
+
+  assert_eq!(select("DIV.b.a#main"), "#main");
+  assert_eq!(select("li:nth-child(2n+1), p"), "li li p p");
+  assert_eq!(select("ul > li:last-child"), "li");
+  assert_eq!(select("html div li:is(.x, :nth-last-child(-n + 2))"), "li li li");
+  assert_eq!(select("[lang|=en], [data-v~=r], [title^=h i], [title$=i]"), "li li p");
+  assert_eq!(select("p + span ~ *"), "p");
+  assert_eq!(select("p:first-of-type, span:only-of-type, body :empty:not(svg *)"), "p span");
+  assert_eq!(select(":root > body > *:not(div)"), "svg");
+  assert_eq!(select("foreignObject, foreignobject"), "foreignObject");
+  assert_eq!(select("#main > :scope"), "");
+  let main = root.query_selector("#main").unwrap().unwrap();
+  assert_eq!(text(main.query_selector_all(":scope > *").unwrap()), "ul p span p");
+
+  let li = root.query_selector("li:nth-of-type(3)").unwrap().unwrap();
+  assert!(li.matches("ul li[data-v]").unwrap());
+  assert!(!li.matches("ol li").unwrap());
+  assert_eq!(li.closest("div").unwrap(), Some(main));
+  assert_eq!(li.closest("li").unwrap(), Some(li));
+  assert_eq!(li.closest("p").unwrap(), None);
+
+  assert_eq!(root.query_selector("a,").unwrap_err(), SelectorParseError::UnexpectedEnd);
+  assert_eq!(root.query_selector("a >> b").unwrap_err(), SelectorParseError::UnexpectedCharacter('>', 3));
+  assert_eq!(root.query_selector("p::before").unwrap_err(), SelectorParseError::PseudoElement);
+  assert!(matches!(root.query_selector(":hover"), Err(SelectorParseError::UnknownPseudoClass(_))));
+  assert!(matches!(root.query_selector(":nth-child(n2)"), Err(SelectorParseError::InvalidNth(_))));
+  assert_eq!(root.query_selector(&":not(".repeat(100_000)).unwrap_err(), SelectorParseError::TooDeeplyNested);
+  assert!(root.query_selector(&format!("{}a{}", ":is(".repeat(63), ")".repeat(63))).is_ok());
+
+  //ids and classes are case-insensitive in quirks mode
+  assert!(Dom::parse("<p class=Foo>").root().query_selector(".foo").unwrap().is_some());
+  assert!(Dom::parse("<!DOCTYPE html><p class=Foo>").root().query_selector(".foo").unwrap().is_none());
+}
+
+#[test]
+pub fn element_lookup() {
+  let mut dom = Dom::parse("<!DOCTYPE html><div id=a><p id=x class='k  l'>1</p><P class=k>2</P></div><template><b id=t class=k></b></template>");
+  let a = dom.get_element_by_id("a").unwrap().id();
+  let p = dom.get_element_by_id("x").unwrap().id();
+  assert!(dom.get_element_by_id("t").is_none());
+  assert!(dom.get_element_by_id("").is_none());
+
+  let ps = dom.get_elements_by_tag_name("P");
+  let k = dom.get_elements_by_class_name(" k ");
+  let kl = dom.node(a).get_elements_by_class_name("l k");
+  assert_eq!((ps.len(&dom), k.len(&dom), kl.len(&dom)), (2, 2, 1));
+  assert_eq!(dom.get_elements_by_tag_name("*").len(&dom), 7);
+  assert!(dom.get_elements_by_class_name("").is_empty(&dom));
+
+  //the index and collections follow attribute changes, insertion and removal
+  dom.node_mut(p).set_attribute("id", Some("y".into()));
+  dom.node_mut(p).set_attribute("class", None);
+  assert!(dom.get_element_by_id("x").is_none());
+  assert_eq!(dom.get_element_by_id("y").map(|x| x.id()), Some(p));
+  assert_eq!((k.len(&dom), kl.len(&dom)), (1, 0));
+
+  dom.node_mut(a).detach();
+  assert!(dom.get_element_by_id("a").is_none() && dom.get_element_by_id("y").is_none());
+  assert_eq!(ps.len(&dom), 0);
+  let body = dom.root().query_selector("body").unwrap().unwrap().id();
+  dom.node_mut(body).append(a).unwrap();
+  assert_eq!(dom.get_element_by_id("y").map(|x| x.id()), Some(p));
+  assert_eq!(ps.item(&dom, 1).map(|x| x.id()), dom.node(p).next_sibling().map(|x| x.id()));
+
+  //the first element in tree order wins
+  dom.node_mut(body).set_inner_html("<i id=d>1</i><i>2</i>").unwrap();
+  let [first, second] = [0, 1].map(|i| dom.get_elements_by_tag_name("i").item(&dom, i).unwrap().id());
+  dom.node_mut(second).set_attribute("id", Some("d".into()));
+  assert_eq!(dom.get_element_by_id("d").map(|x| x.id()), Some(first));
+  dom.node_mut(body).prepend(second).unwrap();
+  assert_eq!(dom.get_element_by_id("d").map(|x| x.id()), Some(second));
+  assert!(dom.get_element_by_id("a").is_none());
+
+  //moving an element between others with the same id keeps them in tree order
+  dom.node_mut(body).append(second).unwrap();
+  assert_eq!(dom.get_element_by_id("d").map(|x| x.id()), Some(first));
+
+  //editing text keeps the index, changes to elements through &mut Node are picked up too
+  let text = dom.node(first).first_child().unwrap().id();
+  let Node::Text(text) = &mut dom[text] else { unreachable!() };
+  text.text.push('!');
+  assert!(!format!("{dom:?}").contains("ids: OnceLock(<uninit>)"));
+  let Node::Element(element) = &mut dom[first] else { unreachable!() };
+  element.set_attribute("id", Some("e".into()));
+  assert_eq!(dom.get_element_by_id("e").map(|x| x.id()), Some(first));
+}
+
+#[test]
+pub fn token_lists() {
+  use breeze::dom::TokenListError;
+  let mut dom = Dom::parse("<div class=' a\tb\na  c'>x</div><a rel=noopener>y</a>");
+  let div = dom.root().query_selector("div").unwrap().unwrap().id();
+  let classes = |dom: &Dom| dom.node(div).element().unwrap().classes().iter().cloned().collect::<std::collections::BTreeSet<_>>();
+  let mut node = dom.node_mut(div);
+  let mut list = node.class_list().unwrap();
+  assert_eq!(list.tokens(), ["a", "b", "c"]);
+  assert!(list.contains("b") && !list.contains("a b"));
+  list.add(&["d", "a"]).unwrap();
+  assert_eq!(list.value(), Some("a b c d"));
+  list.remove(&["a", "z"]).unwrap();
+  assert!(!list.toggle("b", None).unwrap());
+  assert!(list.toggle("e", None).unwrap());
+  assert!(list.toggle("e", Some(true)).unwrap());
+  assert!(!list.toggle("f", Some(false)).unwrap());
+  assert!(list.replace("c", "e").unwrap());
+  assert!(!list.replace("q", "r").unwrap());
+  assert_eq!((list.value(), list.len(), list.item(1)), (Some("e d"), 2, Some("d")));
+  assert_eq!(list.add(&["ok", ""]), Err(TokenListError::Empty));
+  assert_eq!(list.toggle("a b", None), Err(TokenListError::Whitespace));
+  assert_eq!(list.value(), Some("e d"));
+  assert_eq!(classes(&dom), ["d", "e"].map(String::from).into());
+
+  //other attributes, absent ones stay absent until a token is added
+  let a = dom.root().query_selector("a").unwrap().unwrap().id();
+  let mut node = dom.node_mut(a);
+  let mut rel = node.token_list("rel").unwrap();
+  rel.add(&["noreferrer"]).unwrap();
+  assert_eq!(rel.value(), Some("noopener noreferrer"));
+  let mut sandbox = node.token_list("sandbox").unwrap();
+  sandbox.remove(&["allow-forms"]).unwrap();
+  assert_eq!(sandbox.value(), None);
+  sandbox.toggle("allow-forms", None).unwrap();
+  assert_eq!(dom.node(a).element().unwrap().attribute("sandbox"), Some("allow-forms"));
+  let text = dom.node(a).first_child().unwrap().id();
+  assert!(dom.node_mut(text).class_list().is_none());
+}
+
+#[test]
+pub fn serialization() {
+  let dom = Dom::parse(r#"<!DOCTYPE html><div title='a "b" & <c>'>1 &lt; 2 &amp;&amp; x&nbsp;<br><img src=x.png><span></span><script>if (a < b && "</p>") {}</script><style>p > a {}</style><!-- c --></div>"#);
+  let div = find_element(dom.root(), "div").unwrap();
+  assert_eq!(div.outer_html(), concat!(
+    r#"<div title="a &quot;b&quot; &amp; &lt;c&gt;">1 &lt; 2 &amp;&amp; x&nbsp;<br><img src="x.png"><span></span>"#,
+    r#"<script>if (a < b && "</p>") {}</script><style>p > a {}</style><!-- c --></div>"#,
+  ));
+  assert_eq!(div.inner_html(), div.outer_html().strip_prefix(r#"<div title="a &quot;b&quot; &amp; &lt;c&gt;">"#).unwrap().strip_suffix("</div>").unwrap());
+  assert_eq!(find_element(div, "br").unwrap().inner_html(), "");
+  assert_eq!(div.first_child().unwrap().outer_html(), "1 &lt; 2 &amp;&amp; x&nbsp;");
+
+  //processing instructions end with `?>` only in xml
+  assert_eq!(find_element(Dom::parse("<p><?pi a b?><?x?>").root(), "p").unwrap().inner_html(), "<?pi a b><?x>");
+  assert_eq!(Dom::parse_xml("<p><?pi a b?><?x?></p>").root().inner_html(), "<p><?pi a b?><?x?></p>");
+
+  //serializing and parsing again gives the same tree
+  for html in [
+    "<!DOCTYPE html><p>a<b>b<i>c</b>d</i><table><tr><td>&lt;x&gt;</table>",
+    "<!DOCTYPE html><pre>\n\nx</pre><textarea>\n&lt;/textarea&gt;</textarea><listing>\ny</listing>",
+    "<!DOCTYPE html><svg><circle r=1></circle><![CDATA[<a> & b]]><foreignObject><br></foreignObject></svg>",
+    "<!DOCTYPE html><template><td>x</td></template><noembed>&lt;</noembed><xmp><b>&amp;</b></xmp><p title='&quot;&#39;'>",
+  ] {
+    let once = Dom::parse(html).root().inner_html();
+    assert_eq!(Dom::parse(&once).root().inner_html(), once, "{html}");
+  }
+}
+
+#[test]
+pub fn text_accessors() {
+  let mut dom = Dom::parse(concat!(
+    "<!DOCTYPE html><title>T</title><div id=a>  Hello,\n  <b>wide</b>   <i> world </i>!<script>x()</script>",
+    "<p>one<br>two  </p><p hidden>gone</p><span style='display: none'>gone</span><span style='display:block'>block</span>",
+    "<ul><li>1<li>2</ul><table><tr><td>a<td> b </td></tr><tr><td>c</td></tr></table>",
+    "<pre>  keep\n  this </pre><!--c-->end</div>",
+  ));
+  let a = dom.get_element_by_id("a").unwrap();
+  assert_eq!(
+    a.text_content(),
+    "  Hello,\n  wide    world !x()onetwo  gonegoneblock12a b c  keep\n  this end",
+  );
+  assert_eq!(a.inner_text(), "Hello, wide world !\n\none\ntwo\n\nblock\n1\n2\na\tb\nc\n  keep\n  this \nend");
+  assert_eq!(a.query_selector("p[hidden]").unwrap().unwrap().inner_text(), "gone");
+  assert_eq!(a.query_selector("pre").unwrap().unwrap().inner_text(), "  keep\n  this ");
+  assert_eq!(dom.root().query_selector("title").unwrap().unwrap().text_content(), "T");
+
+  //setting text replaces all children with one text node
+  let a = a.id();
+  let comment = dom.node(a).last_child().unwrap().previous_sibling().unwrap().id();
+  dom.node_mut(comment).set_text_content("d");
+  assert_eq!(dom.node(comment).outer_html(), "<!--d-->");
+  dom.node_mut(a).set_text_content("<new> & text");
+  assert_eq!(dom.node(a).inner_html(), "&lt;new&gt; &amp; text");
+  assert_eq!(dom.node(a).children().count(), 1);
+  dom.node_mut(a).set_text_content("");
+  assert!(dom.node(a).first_child().is_none());
+}
+
+#[test]
+pub fn mutation_observers() {
+  use breeze::dom::{MutationObserverInit, MutationRecord};
+  let mut dom = Dom::parse("<div id=a><p id=b class=x>text</p></div><span id=c></span>");
+  let [a, b, c] = ["a", "b", "c"].map(|id| dom.get_element_by_id(id).unwrap().id());
+  let text = dom.node(b).first_child().unwrap().id();
+
+  let subtree = dom.create_mutation_observer();
+  dom.observe(subtree, a, MutationObserverInit {
+    child_list: true,
+    attributes: true,
+    attribute_old_value: true,
+    character_data: true,
+    subtree: true,
+    ..Default::default()
+  });
+  let own = dom.create_mutation_observer();
+  dom.observe(own, a, MutationObserverInit {
+    attributes: true,
+    attribute_filter: Some(vec!["title".into()]),
+    ..Default::default()
+  });
+
+  dom.node_mut(b).class_list().unwrap().add(&["y"]).unwrap();
+  dom.node_mut(a).set_attribute("TITLE", Some("t".into()));
+  dom.node_mut(a).set_attribute("missing", None);
+  dom.node_mut(text).set_text_content("changed");
+  dom.node_mut(a).append(c).unwrap();
+  //changes outside of the observed subtree aren't recorded
+  dom.node_mut(c).set_attribute("title", Some("outside".into()));
+  dom.node_mut(c).detach();
+  dom.node_mut(c).set_attribute("title", None);
+
+  assert_eq!(dom.take_records(subtree), [
+    MutationRecord::Attributes { target: b, name: "class".into(), old_value: Some("x".into()) },
+    MutationRecord::Attributes { target: a, name: "title".into(), old_value: None },
+    MutationRecord::CharacterData { target: text, old_value: None },
+    MutationRecord::ChildList { target: a, added: vec![c], removed: vec![], previous_sibling: Some(b), next_sibling: None },
+    MutationRecord::Attributes { target: c, name: "title".into(), old_value: None },
+    MutationRecord::ChildList { target: a, added: vec![], removed: vec![c], previous_sibling: Some(b), next_sibling: None },
+  ]);
+  assert!(dom.take_records(subtree).is_empty());
+  assert_eq!(dom.take_records(own), [
+    MutationRecord::Attributes { target: a, name: "title".into(), old_value: None },
+  ]);
+
+  //moving children out of a fragment is recorded on both ends
+  dom.observe(own, dom.root().id(), MutationObserverInit { child_list: true, subtree: true, ..Default::default() });
+  let fragment = dom.create_fragment();
+  dom.observe(own, fragment, MutationObserverInit { child_list: true, ..Default::default() });
+  dom.node_mut(fragment).append(c).unwrap();
+  dom.node_mut(b).set_text_content("");
+  dom.node_mut(b).prepend(fragment).unwrap();
+  assert_eq!(dom.take_records(own), [
+    MutationRecord::ChildList { target: fragment, added: vec![c], removed: vec![], previous_sibling: None, next_sibling: None },
+    MutationRecord::ChildList { target: b, added: vec![], removed: vec![text], previous_sibling: None, next_sibling: None },
+    MutationRecord::ChildList { target: fragment, added: vec![], removed: vec![c], previous_sibling: None, next_sibling: None },
+    MutationRecord::ChildList { target: b, added: vec![c], removed: vec![], previous_sibling: None, next_sibling: None },
+  ]);
+
+  //replacing all children is a single record
+  dom.node_mut(b).set_inner_html("<i>1</i>2").unwrap();
+  let added: Vec<_> = dom.node(b).children().map(|x| x.id()).collect();
+  assert_eq!(dom.take_records(own), [
+    MutationRecord::ChildList { target: b, added: added.clone(), removed: vec![c], previous_sibling: None, next_sibling: None },
+  ]);
+  dom.node_mut(b).set_text_content("");
+  assert_eq!(dom.take_records(own), [
+    MutationRecord::ChildList { target: b, added: vec![], removed: added, previous_sibling: None, next_sibling: None },
+  ]);
+  let c = dom.create_node(Node::Element(Default::default()));
+  dom.node_mut(b).append(c).unwrap();
+  dom.take_records(own);
+
+  dom.disconnect(subtree);
+  dom.node_mut(b).remove_child(c).unwrap();
+  assert!(dom.take_records(subtree).is_empty());
+  assert_eq!(dom.take_records(own).len(), 1);
 }